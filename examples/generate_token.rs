@@ -1,5 +1,4 @@
-use dotenvy;
-use rust_axum_rest_api::auth::jwt::generate_token;
+use rust_axum_rest_api::auth::{jwt::generate_token, keys::JwtKeys, roles::Role, scopes::Scope};
 
 fn main() {
     dotenvy::dotenv().ok();
@@ -9,9 +8,13 @@ fn main() {
         .and_then(|id| id.parse::<i32>().ok())
         .unwrap_or(1);
 
-    let secret = std::env::var("JWT_SECRET").expect("JWT_SECRET must be set");
-    match generate_token(user_id, &secret) {
+    let role = match std::env::args().nth(2).as_deref() {
+        Some("admin") => Role::Admin,
+        _ => Role::User,
+    };
+
+    match generate_token(user_id, role, &Scope::all_for(role), &JwtKeys::from_env()) {
         Ok(token) => println!("{}", token),
-        Err(e) => println!("Error: {}", e.error),
+        Err(e) => println!("Error: {e}"),
     }
 }
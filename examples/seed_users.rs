@@ -1,4 +1,4 @@
-use rust_axum_rest_api::models::users::User;
+use rust_axum_rest_api::{auth::roles::Role, models::users::User};
 use sqlx::PgPool;
 
 #[tokio::main]
@@ -10,19 +10,20 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let pool = PgPool::connect(&database_url).await?;
 
     let users = vec![
-        ("john_doe", "john@example.com", "password123"),
-        ("jane_smith", "jane@example.com", "password123"),
-        ("admin", "admin@example.com", "admin_secure"),
+        ("john_doe", "john@example.com", "password123", Role::User),
+        ("jane_smith", "jane@example.com", "password123", Role::User),
+        ("admin", "admin@example.com", "admin_secure", Role::Admin),
     ];
 
-    for (username, email, password) in users {
+    for (username, email, password, role) in users {
         let password_hash = User::hash_password(password)?;
 
         sqlx::query!(
-            "INSERT INTO users (username, email, password_hash) VALUES ($1, $2, $3) ON CONFLICT (username) DO NOTHING",
+            "INSERT INTO users (username, email, password_hash, role, email_verified_at) VALUES ($1, $2, $3, $4, NOW()) ON CONFLICT (username) DO NOTHING",
             username,
             email,
-            password_hash
+            password_hash,
+            role as Role
         )
         .execute(&pool)
         .await?;
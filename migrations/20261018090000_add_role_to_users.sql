@@ -0,0 +1,2 @@
+-- Add a role column so admins can manage any user or post
+ALTER TABLE users ADD COLUMN role TEXT NOT NULL DEFAULT 'user' CHECK (role IN ('user', 'admin'));
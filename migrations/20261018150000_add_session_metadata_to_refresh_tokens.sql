@@ -0,0 +1,3 @@
+-- Remember which device each refresh token was issued to; a token's used_at doubles as its last-used time
+ALTER TABLE refresh_tokens ADD COLUMN user_agent TEXT;
+ALTER TABLE refresh_tokens ADD COLUMN ip_address TEXT;
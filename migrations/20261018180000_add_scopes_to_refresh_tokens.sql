@@ -0,0 +1,2 @@
+-- NULL means the session was not narrowed at login and gets every scope the user's role allows.
+ALTER TABLE refresh_tokens ADD COLUMN scopes TEXT[];
@@ -0,0 +1,17 @@
+ALTER TABLE users ADD COLUMN failed_login_count INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE users ADD COLUMN locked_until TIMESTAMP;
+
+-- Every password check, so lockouts can be explained and per-IP failures counted.
+CREATE TABLE login_attempts (
+    id BIGSERIAL PRIMARY KEY,
+    user_id INTEGER REFERENCES users(id) ON DELETE CASCADE,
+    username TEXT NOT NULL,
+    ip_address TEXT,
+    user_agent TEXT,
+    succeeded BOOLEAN NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_login_attempts_user_id ON login_attempts(user_id, created_at DESC);
+CREATE INDEX idx_login_attempts_ip_failures ON login_attempts(ip_address, created_at)
+    WHERE NOT succeeded;
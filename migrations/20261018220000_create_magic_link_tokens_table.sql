@@ -0,0 +1,12 @@
+-- Single-use passwordless sign-in links. Rows outlive their use for a while so requests can be
+-- rate limited per account.
+CREATE TABLE magic_link_tokens (
+    id         SERIAL PRIMARY KEY,
+    user_id    INT  NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    token_hash TEXT NOT NULL UNIQUE,
+    expires_at TIMESTAMP NOT NULL,
+    used_at    TIMESTAMP,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_magic_link_tokens_user_id ON magic_link_tokens(user_id, created_at);
@@ -0,0 +1,18 @@
+-- Access tokens revoked before they expire, e.g. by logging out. Rows are only needed until the
+-- token would have expired anyway.
+CREATE TABLE revoked_access_tokens (
+    jti UUID PRIMARY KEY,
+    expires_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX idx_revoked_access_tokens_expires_at ON revoked_access_tokens(expires_at);
+
+-- Access tokens a user was issued before this time are no longer accepted. No foreign key, so
+-- the watermark outlives a deleted account for as long as its tokens could.
+CREATE TABLE access_token_watermarks (
+    user_id INTEGER PRIMARY KEY,
+    issued_before TIMESTAMP NOT NULL
+);
+
+-- The access token issued alongside each refresh token, so ending a session can revoke it too
+ALTER TABLE refresh_tokens ADD COLUMN access_jti UUID;
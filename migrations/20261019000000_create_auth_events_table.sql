@@ -0,0 +1,34 @@
+-- Security audit log: logins, refreshes, logouts and account changes. Rows are never changed
+-- or removed, and user_id has no foreign key so an account's history outlives the account.
+CREATE TABLE auth_events (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER,
+    event_type TEXT NOT NULL,
+    outcome TEXT NOT NULL,
+    -- Why it failed, or what kind of success it was
+    detail TEXT,
+    -- Username given at login, for failures that don't match an account
+    username TEXT,
+    -- Admin acting for user_id while impersonating them
+    actor_id INTEGER,
+    ip_address TEXT,
+    user_agent TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_auth_events_user_id ON auth_events(user_id, created_at DESC, id DESC);
+CREATE INDEX idx_auth_events_created_at ON auth_events(created_at DESC, id DESC);
+
+CREATE FUNCTION reject_auth_event_changes() RETURNS trigger AS $$
+BEGIN
+    RAISE EXCEPTION 'auth_events is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER auth_events_append_only
+    BEFORE UPDATE OR DELETE ON auth_events
+    FOR EACH ROW EXECUTE FUNCTION reject_auth_event_changes();
+
+CREATE TRIGGER auth_events_no_truncate
+    BEFORE TRUNCATE ON auth_events
+    FOR EACH STATEMENT EXECUTE FUNCTION reject_auth_event_changes();
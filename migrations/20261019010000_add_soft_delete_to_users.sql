@@ -0,0 +1,16 @@
+-- Deleting an account only deactivates it at first; the row is purged once the restore window
+-- has passed.
+ALTER TABLE users ADD COLUMN deleted_at TIMESTAMP;
+
+CREATE INDEX idx_users_deleted_at ON users(deleted_at) WHERE deleted_at IS NOT NULL;
+
+-- Single-use links emailed on deletion for undoing it within the restore window
+CREATE TABLE account_restore_tokens (
+    id         SERIAL PRIMARY KEY,
+    user_id    INT  NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    token_hash TEXT NOT NULL UNIQUE,
+    expires_at TIMESTAMP NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_account_restore_tokens_user_id ON account_restore_tokens(user_id);
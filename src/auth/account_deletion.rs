@@ -0,0 +1,232 @@
+use crate::{
+    auth::{
+        audit::{AuthEvent, AuthEventKind},
+        client_info::ClientInfo,
+        jwt::{generate_refresh_token, hash_token},
+        revocation::{RevocationStore, Revoked},
+    },
+    error::AppResult,
+};
+use sqlx::{PgConnection, PgPool};
+use std::{sync::Arc, time::Duration};
+
+/// How long deleted accounts can be restored, read from `ACCOUNT_*` environment variables.
+#[derive(Debug, Clone)]
+pub struct DeletionPolicy {
+    /// Time after deletion until the account is purged for good.
+    pub restore_window_secs: u64,
+    /// How often the purge task looks for accounts past their window.
+    pub purge_interval_secs: u64,
+}
+
+pub type SharedDeletionPolicy = Arc<DeletionPolicy>;
+
+impl Default for DeletionPolicy {
+    fn default() -> Self {
+        DeletionPolicy {
+            restore_window_secs: 30 * 24 * 60 * 60,
+            purge_interval_secs: 3600,
+        }
+    }
+}
+
+impl DeletionPolicy {
+    pub fn from_env() -> Self {
+        fn var(name: &str, default: u64) -> u64 {
+            std::env::var(name)
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(default)
+        }
+
+        let default = DeletionPolicy::default();
+        DeletionPolicy {
+            restore_window_secs: var(
+                "ACCOUNT_RESTORE_WINDOW_SECONDS",
+                default.restore_window_secs,
+            ),
+            purge_interval_secs: var(
+                "ACCOUNT_PURGE_INTERVAL_SECONDS",
+                default.purge_interval_secs,
+            ),
+        }
+    }
+
+    fn restore_window(&self) -> chrono::Duration {
+        chrono::Duration::seconds(self.restore_window_secs as i64)
+    }
+}
+
+/// An account that was just deactivated.
+pub struct DeactivatedAccount {
+    pub email: String,
+    /// When the purge task will delete the account for good.
+    pub purge_after: chrono::NaiveDateTime,
+    /// The account's access tokens, to apply once the transaction commits.
+    pub revoked: Revoked,
+}
+
+/// Marks `user_id` as deleted and signs it out everywhere, leaving its data in place until the
+/// restore window has passed. `None` if there is no such active account.
+pub async fn deactivate_account(
+    conn: &mut PgConnection,
+    revocations: &RevocationStore,
+    policy: &DeletionPolicy,
+    user_id: i32,
+) -> AppResult<Option<DeactivatedAccount>> {
+    let now = chrono::Utc::now().naive_utc();
+
+    let Some(email) = sqlx::query_scalar!(
+        "UPDATE users SET deleted_at = $2 WHERE id = $1 AND deleted_at IS NULL RETURNING email",
+        user_id,
+        now,
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    else {
+        return Ok(None);
+    };
+
+    let revoked = revocations.revoke_all_for_user(conn, user_id).await?;
+    sqlx::query!("DELETE FROM refresh_tokens WHERE user_id = $1", user_id)
+        .execute(&mut *conn)
+        .await?;
+    // Links already sent out would otherwise still sign in or grant access.
+    sqlx::query!("DELETE FROM magic_link_tokens WHERE user_id = $1", user_id)
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query!(
+        "DELETE FROM password_reset_tokens WHERE user_id = $1",
+        user_id
+    )
+    .execute(&mut *conn)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM oauth_authorization_codes WHERE user_id = $1",
+        user_id
+    )
+    .execute(&mut *conn)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM email_verification_tokens WHERE user_id = $1",
+        user_id
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(Some(DeactivatedAccount {
+        email,
+        purge_after: now + policy.restore_window(),
+        revoked,
+    }))
+}
+
+/// Issues a single-use link token for restoring a deactivated account until it's purged.
+pub async fn issue_restore_token(
+    conn: &mut PgConnection,
+    user_id: i32,
+    account: &DeactivatedAccount,
+) -> AppResult<String> {
+    let (token_plaintext, token_hash) = generate_refresh_token();
+    sqlx::query!(
+        "INSERT INTO account_restore_tokens (user_id, token_hash, expires_at) VALUES ($1, $2, $3)",
+        user_id,
+        token_hash,
+        account.purge_after,
+    )
+    .execute(&mut *conn)
+    .await?;
+    Ok(token_plaintext)
+}
+
+/// Reactivates the account a restore token was issued for. `None` if the token is unknown, used
+/// or expired.
+pub async fn redeem_restore_token(
+    pool: &PgPool,
+    token: &str,
+    client: &ClientInfo,
+) -> AppResult<Option<i32>> {
+    let now = chrono::Utc::now().naive_utc();
+    let mut tx = pool.begin().await?;
+
+    let Some(user_id) = sqlx::query_scalar!(
+        "DELETE FROM account_restore_tokens WHERE token_hash = $1 AND expires_at > $2
+        RETURNING user_id",
+        hash_token(token),
+        now,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+        return Ok(None);
+    };
+
+    if !reactivate_account(&mut tx, user_id).await? {
+        return Ok(None);
+    }
+    AuthEvent::success(AuthEventKind::AccountRestored, user_id)
+        .record(&mut *tx, client)
+        .await?;
+
+    tx.commit().await?;
+    Ok(Some(user_id))
+}
+
+/// Clears a pending deletion. `false` if the account isn't deactivated.
+pub async fn reactivate_account(conn: &mut PgConnection, user_id: i32) -> AppResult<bool> {
+    let result = sqlx::query!(
+        "UPDATE users SET deleted_at = NULL WHERE id = $1 AND deleted_at IS NOT NULL",
+        user_id
+    )
+    .execute(&mut *conn)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM account_restore_tokens WHERE user_id = $1",
+        user_id
+    )
+    .execute(&mut *conn)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Hard-deletes accounts deactivated longer than the restore window ago, cascading to their
+/// posts and tokens. Returns the IDs purged.
+pub async fn purge_deleted_accounts(pool: &PgPool, policy: &DeletionPolicy) -> AppResult<Vec<i32>> {
+    let cutoff = chrono::Utc::now().naive_utc() - policy.restore_window();
+
+    let mut tx = pool.begin().await?;
+
+    let purged = sqlx::query_scalar!(
+        "DELETE FROM users WHERE deleted_at <= $1 RETURNING id",
+        cutoff
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    for &user_id in &purged {
+        AuthEvent::success(AuthEventKind::AccountPurged, user_id)
+            .record(&mut *tx, &ClientInfo::default())
+            .await?;
+    }
+
+    tx.commit().await?;
+    Ok(purged)
+}
+
+/// Runs `purge_deleted_accounts` every `purge_interval_secs` for as long as the process lives.
+pub fn spawn_purge_task(pool: PgPool, policy: DeletionPolicy) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval =
+            tokio::time::interval(Duration::from_secs(policy.purge_interval_secs.max(1)));
+        loop {
+            interval.tick().await;
+            match purge_deleted_accounts(&pool, &policy).await {
+                Ok(purged) if !purged.is_empty() => {
+                    tracing::info!(count = purged.len(), "purged deleted accounts");
+                }
+                Ok(_) => {}
+                Err(e) => tracing::error!(error = %e, "failed to purge deleted accounts"),
+            }
+        }
+    })
+}
@@ -0,0 +1,142 @@
+use crate::{auth::client_info::ClientInfo, error::AppResult};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgExecutor, PgPool};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "text", rename_all = "snake_case")]
+pub enum AuthEventKind {
+    Login,
+    Refresh,
+    Logout,
+    AccountUpdated,
+    AccountDeleted,
+    AccountRestored,
+    /// The final hard delete once a deleted account's restore window has passed.
+    AccountPurged,
+    /// Setting a new password with an emailed reset link.
+    PasswordReset,
+    PasswordChanged,
+    /// An admin starting to act as another user; `actor_id` is the admin.
+    ImpersonationStarted,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "text", rename_all = "lowercase")]
+pub enum AuthEventOutcome {
+    Success,
+    Failure,
+}
+
+/// One entry for the append-only `auth_events` audit log.
+pub struct AuthEvent<'a> {
+    pub kind: AuthEventKind,
+    pub outcome: AuthEventOutcome,
+    pub user_id: Option<i32>,
+    /// Why it failed, e.g. `invalid_credentials`, or what kind of success it was.
+    pub detail: Option<&'a str>,
+    pub username: Option<&'a str>,
+    /// The admin acting for `user_id` while impersonating them.
+    pub actor_id: Option<i32>,
+}
+
+impl<'a> AuthEvent<'a> {
+    pub fn success(kind: AuthEventKind, user_id: i32) -> Self {
+        AuthEvent {
+            kind,
+            outcome: AuthEventOutcome::Success,
+            user_id: Some(user_id),
+            detail: None,
+            username: None,
+            actor_id: None,
+        }
+    }
+
+    pub fn failure(kind: AuthEventKind, user_id: Option<i32>, detail: &'a str) -> Self {
+        AuthEvent {
+            kind,
+            outcome: AuthEventOutcome::Failure,
+            user_id,
+            detail: Some(detail),
+            username: None,
+            actor_id: None,
+        }
+    }
+
+    pub fn with_detail(self, detail: &'a str) -> Self {
+        AuthEvent {
+            detail: Some(detail),
+            ..self
+        }
+    }
+
+    pub fn with_username(self, username: &'a str) -> Self {
+        AuthEvent {
+            username: Some(username),
+            ..self
+        }
+    }
+
+    pub fn with_actor(self, actor_id: Option<i32>) -> Self {
+        AuthEvent { actor_id, ..self }
+    }
+
+    /// Appends the event, with the device it came from. Pass the transaction making the change
+    /// where there is one, so the change and its record commit together.
+    pub async fn record<'e>(
+        self,
+        executor: impl PgExecutor<'e>,
+        client: &ClientInfo,
+    ) -> AppResult<()> {
+        sqlx::query!(
+            "INSERT INTO auth_events
+                (user_id, event_type, outcome, detail, username, actor_id, ip_address, user_agent)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+            self.user_id,
+            self.kind as AuthEventKind,
+            self.outcome as AuthEventOutcome,
+            self.detail,
+            self.username,
+            self.actor_id,
+            client.ip_address,
+            client.user_agent,
+        )
+        .execute(executor)
+        .await?;
+        Ok(())
+    }
+
+    /// Appends the event for something that has already happened outside a transaction, such as
+    /// tokens already handed out, where failing the request would only hide that it went through.
+    pub async fn record_or_log(self, pool: &PgPool, client: &ClientInfo) {
+        let (kind, user_id) = (self.kind, self.user_id);
+        if let Err(e) = self.record(pool, client).await {
+            tracing::warn!(?kind, ?user_id, error = %e, "failed to record auth event");
+        }
+    }
+}
+
+/// The fields an account update touched, e.g. `username,email`, for an event's detail.
+pub fn changed_fields(fields: &[(&str, bool)]) -> String {
+    fields
+        .iter()
+        .filter(|(_, changed)| *changed)
+        .map(|(field, _)| *field)
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changed_fields_lists_only_changed_ones() {
+        assert_eq!(
+            changed_fields(&[("username", true), ("email", false), ("role", true)]),
+            "username,role"
+        );
+        assert_eq!(changed_fields(&[("username", false)]), "");
+    }
+}
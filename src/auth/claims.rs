@@ -1,8 +1,46 @@
+use crate::auth::roles::Role;
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
+    pub sub: i32,  // user id
+    pub jti: Uuid, // unique token id, so a single token can be revoked
+    pub exp: u64,  // expiration time
+    pub iat: u64,  // issued at time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iat_us: Option<u64>, // issued at time in microseconds, to order it against revocations
+    pub role: Role, // role at the time the token was issued
+    pub scope: String, // space-delimited scopes this token grants
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<Uuid>, // OAuth client the token was issued to, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub act: Option<Actor>, // admin acting as `sub`, on impersonation tokens (RFC 8693)
+}
+
+impl Claims {
+    /// When the token was issued, in Unix microseconds. Tokens without `iat_us` count as issued
+    /// at the start of their second.
+    pub fn issued_at_us(&self) -> u64 {
+        self.iat_us.unwrap_or(self.iat * 1_000_000)
+    }
+}
+
+/// The party actually making requests with a token issued for someone else.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Actor {
     pub sub: i32, // user id
-    pub exp: u64, // expiration time
-    pub iat: u64, // issued at time
+}
+
+pub const MFA_AUDIENCE: &str = "mfa";
+
+/// Short-lived proof that a user passed the password step of a two-factor login.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MfaClaims {
+    pub sub: i32,    // user id
+    pub exp: u64,    // expiration time
+    pub iat: u64,    // issued at time
+    pub aud: String, // always MFA_AUDIENCE, so it can't pass as an access token
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>, // scope requested at login, applied once the second factor passes
 }
@@ -0,0 +1,55 @@
+use axum::{
+    extract::{ConnectInfo, FromRequestParts},
+    http::{header::USER_AGENT, request::Parts},
+};
+use std::{convert::Infallible, net::SocketAddr};
+
+/// Best-effort description of the device making a request.
+#[derive(Debug, Clone, Default)]
+pub struct ClientInfo {
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl<S> FromRequestParts<S> for ClientInfo
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let user_agent = parts
+            .headers
+            .get(USER_AGENT)
+            .and_then(|h| h.to_str().ok())
+            .map(str::to_string);
+
+        // X-Forwarded-For is client-controlled, so only believe it when a trusted proxy sets it.
+        let forwarded_for = trust_proxy_headers()
+            .then(|| {
+                parts
+                    .headers
+                    .get("X-Forwarded-For")
+                    .and_then(|h| h.to_str().ok())
+                    .and_then(|h| h.split(',').next())
+                    .map(|ip| ip.trim().to_string())
+            })
+            .flatten();
+
+        let ip_address = forwarded_for.or_else(|| {
+            parts
+                .extensions
+                .get::<ConnectInfo<SocketAddr>>()
+                .map(|ConnectInfo(addr)| addr.ip().to_string())
+        });
+
+        Ok(ClientInfo {
+            ip_address,
+            user_agent,
+        })
+    }
+}
+
+fn trust_proxy_headers() -> bool {
+    std::env::var("TRUST_PROXY_HEADERS").is_ok_and(|v| v == "true")
+}
@@ -0,0 +1,307 @@
+use crate::{
+    auth::{jwt::ACCESS_TOKEN_TTL_SECS, refresh_tokens::REFRESH_TOKEN_TTL_DAYS},
+    error::{AppError, AppResult},
+};
+use axum::{
+    extract::FromRequestParts,
+    http::{HeaderMap, Method, request::Parts},
+};
+use axum_extra::extract::cookie::{Cookie, CookieJar, SameSite};
+use rand::RngCore;
+use std::sync::Arc;
+
+pub const ACCESS_COOKIE: &str = "access_token";
+pub const REFRESH_COOKIE: &str = "refresh_token";
+pub const CSRF_COOKIE: &str = "csrf_token";
+pub const CSRF_HEADER: &str = "X-CSRF-Token";
+
+/// The refresh cookie is only sent to the endpoints that consume it.
+const REFRESH_COOKIE_PATH: &str = "/auth";
+
+/// How browser sessions carry their tokens, read from `AUTH_COOKIE*` environment variables.
+#[derive(Debug, Clone)]
+pub struct CookieConfig {
+    /// Deliver session tokens as HttpOnly cookies instead of in response bodies.
+    pub enabled: bool,
+    /// Only worth turning off for local development over plain HTTP.
+    pub secure: bool,
+    pub same_site: SameSite,
+    pub domain: Option<String>,
+}
+
+pub type SharedCookieConfig = Arc<CookieConfig>;
+
+impl Default for CookieConfig {
+    fn default() -> Self {
+        CookieConfig {
+            enabled: false,
+            secure: true,
+            same_site: SameSite::Strict,
+            domain: None,
+        }
+    }
+}
+
+impl CookieConfig {
+    /// Reads `AUTH_COOKIES` (`true` turns cookie mode on), `AUTH_COOKIE_SECURE` (default
+    /// `true`), `AUTH_COOKIE_SAME_SITE` (`strict`, `lax` or `none`, default `strict`) and
+    /// `AUTH_COOKIE_DOMAIN`.
+    pub fn from_env() -> Self {
+        let default = CookieConfig::default();
+        let same_site = match std::env::var("AUTH_COOKIE_SAME_SITE").as_deref() {
+            Ok("lax") => SameSite::Lax,
+            Ok("none") => SameSite::None,
+            _ => default.same_site,
+        };
+
+        CookieConfig {
+            enabled: std::env::var("AUTH_COOKIES").is_ok_and(|v| v == "true"),
+            secure: std::env::var("AUTH_COOKIE_SECURE").map_or(default.secure, |v| v != "false"),
+            same_site,
+            domain: std::env::var("AUTH_COOKIE_DOMAIN").ok(),
+        }
+    }
+
+    fn build(&self, name: &'static str, value: String, path: &'static str) -> Cookie<'static> {
+        let mut cookie = Cookie::build((name, value))
+            .path(path)
+            .secure(self.secure)
+            .same_site(self.same_site)
+            .build();
+        if let Some(domain) = &self.domain {
+            cookie.set_domain(domain.clone());
+        }
+        cookie
+    }
+
+    /// In cookie mode, moves a freshly issued token pair out of the response body and into
+    /// cookies, alongside a new CSRF token for the front end to echo back.
+    pub fn store_session(
+        &self,
+        jar: CookieJar,
+        access_token: &mut Option<String>,
+        refresh_token: &mut Option<String>,
+    ) -> CookieJar {
+        if !self.enabled {
+            return jar;
+        }
+        let (Some(access_token), Some(refresh_token)) = (access_token.take(), refresh_token.take())
+        else {
+            return jar;
+        };
+
+        let mut access = self.build(ACCESS_COOKIE, access_token, "/");
+        access.set_http_only(true);
+        access.set_max_age(time::Duration::seconds(ACCESS_TOKEN_TTL_SECS as i64));
+
+        let mut refresh = self.build(REFRESH_COOKIE, refresh_token, REFRESH_COOKIE_PATH);
+        refresh.set_http_only(true);
+        refresh.set_max_age(time::Duration::days(REFRESH_TOKEN_TTL_DAYS));
+
+        // Readable by scripts on purpose: that's how the front end learns what to send back.
+        let mut csrf = self.build(CSRF_COOKIE, generate_csrf_token(), "/");
+        csrf.set_max_age(time::Duration::days(REFRESH_TOKEN_TTL_DAYS));
+
+        jar.add(access).add(refresh).add(csrf)
+    }
+
+    /// Expires every session cookie.
+    pub fn clear_session(&self, jar: CookieJar) -> CookieJar {
+        if !self.enabled {
+            return jar;
+        }
+        jar.remove(self.build(ACCESS_COOKIE, String::new(), "/"))
+            .remove(self.build(REFRESH_COOKIE, String::new(), REFRESH_COOKIE_PATH))
+            .remove(self.build(CSRF_COOKIE, String::new(), "/"))
+    }
+
+    /// The `name` session cookie, if cookie mode is on and the browser sent one. Cookies ride
+    /// along on cross-site requests too, so anything but a safe method must also echo the CSRF
+    /// cookie in the `X-CSRF-Token` header.
+    pub fn session_token(
+        &self,
+        name: &str,
+        method: &Method,
+        headers: &HeaderMap,
+    ) -> AppResult<Option<String>> {
+        if !self.enabled {
+            return Ok(None);
+        }
+        let jar = CookieJar::from_headers(headers);
+        let Some(token) = jar.get(name).map(|c| c.value().to_string()) else {
+            return Ok(None);
+        };
+
+        if !method.is_safe() {
+            let expected = jar.get(CSRF_COOKIE).map(|c| c.value()).unwrap_or_default();
+            let provided = headers
+                .get(CSRF_HEADER)
+                .and_then(|h| h.to_str().ok())
+                .unwrap_or_default();
+            if expected.is_empty() || !constant_time_eq(expected.as_bytes(), provided.as_bytes()) {
+                return Err(AppError::Forbidden(
+                    "Missing or invalid CSRF token".to_string(),
+                ));
+            }
+        }
+
+        Ok(Some(token))
+    }
+}
+
+/// Cookie settings plus the cookies a request arrived with, for handlers that start or end a
+/// session.
+pub struct SessionCookies {
+    config: SharedCookieConfig,
+    jar: CookieJar,
+    method: Method,
+    headers: HeaderMap,
+}
+
+impl SessionCookies {
+    /// See [`CookieConfig::store_session`].
+    pub fn store(
+        self,
+        access_token: &mut Option<String>,
+        refresh_token: &mut Option<String>,
+    ) -> CookieJar {
+        self.config
+            .store_session(self.jar, access_token, refresh_token)
+    }
+
+    pub fn clear(self) -> CookieJar {
+        self.config.clear_session(self.jar)
+    }
+
+    /// See [`CookieConfig::session_token`].
+    pub fn token(&self, name: &str) -> AppResult<Option<String>> {
+        self.config.session_token(name, &self.method, &self.headers)
+    }
+}
+
+impl<S> FromRequestParts<S> for SessionCookies
+where
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let config = parts
+            .extensions
+            .get::<SharedCookieConfig>()
+            .cloned()
+            .ok_or_else(|| AppError::Internal("cookie config extension missing".to_string()))?;
+
+        Ok(SessionCookies {
+            config,
+            jar: CookieJar::from_headers(&parts.headers),
+            method: parts.method.clone(),
+            headers: parts.headers.clone(),
+        })
+    }
+}
+
+fn generate_csrf_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::{HeaderValue, header::COOKIE};
+
+    fn enabled() -> CookieConfig {
+        CookieConfig {
+            enabled: true,
+            ..CookieConfig::default()
+        }
+    }
+
+    fn headers(cookie: &str, csrf: Option<&str>) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(COOKIE, HeaderValue::from_str(cookie).unwrap());
+        if let Some(csrf) = csrf {
+            headers.insert(CSRF_HEADER, HeaderValue::from_str(csrf).unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn store_session_moves_tokens_into_cookies() {
+        let (mut access, mut refresh) = (Some("a".to_string()), Some("r".to_string()));
+        let jar = enabled().store_session(CookieJar::new(), &mut access, &mut refresh);
+
+        assert!(access.is_none() && refresh.is_none());
+        let access = jar.get(ACCESS_COOKIE).unwrap();
+        assert_eq!(access.value(), "a");
+        assert_eq!(access.http_only(), Some(true));
+        assert_eq!(access.secure(), Some(true));
+        assert_eq!(access.same_site(), Some(SameSite::Strict));
+        assert_eq!(jar.get(REFRESH_COOKIE).unwrap().path(), Some("/auth"));
+        assert_eq!(jar.get(CSRF_COOKIE).unwrap().value().len(), 64);
+    }
+
+    #[test]
+    fn disabled_mode_leaves_tokens_in_the_body() {
+        let (mut access, mut refresh) = (Some("a".to_string()), Some("r".to_string()));
+        let jar =
+            CookieConfig::default().store_session(CookieJar::new(), &mut access, &mut refresh);
+
+        assert!(access.is_some() && refresh.is_some());
+        assert!(jar.iter().next().is_none());
+    }
+
+    #[test]
+    fn safe_methods_skip_the_csrf_check() {
+        let headers = headers("access_token=a", None);
+        let token = enabled()
+            .session_token(ACCESS_COOKIE, &Method::GET, &headers)
+            .unwrap();
+        assert_eq!(token.as_deref(), Some("a"));
+    }
+
+    #[test]
+    fn unsafe_methods_require_matching_csrf_header() {
+        let config = enabled();
+        let cookie = "access_token=a; csrf_token=expected";
+
+        assert!(
+            config
+                .session_token(ACCESS_COOKIE, &Method::POST, &headers(cookie, None))
+                .is_err()
+        );
+        assert!(
+            config
+                .session_token(
+                    ACCESS_COOKIE,
+                    &Method::POST,
+                    &headers(cookie, Some("wrong"))
+                )
+                .is_err()
+        );
+        assert!(
+            config
+                .session_token(
+                    ACCESS_COOKIE,
+                    &Method::DELETE,
+                    &headers(cookie, Some("expected"))
+                )
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn cookies_are_ignored_when_disabled() {
+        let headers = headers("access_token=a", None);
+        let token = CookieConfig::default()
+            .session_token(ACCESS_COOKIE, &Method::POST, &headers)
+            .unwrap();
+        assert!(token.is_none());
+    }
+}
@@ -1,71 +1,265 @@
-use crate::{auth::claims::Claims, models::ErrorResponse};
-use axum::{
-    extract::FromRequestParts,
-    http::{StatusCode, request::Parts},
+use crate::{
+    auth::{
+        api_keys::{authenticate_api_key, is_api_key},
+        claims::{Actor, Claims, MFA_AUDIENCE, MfaClaims},
+        cookies::{ACCESS_COOKIE, SharedCookieConfig},
+        keys::{JwtKeys, SharedJwtKeys},
+        revocation::SharedRevocationStore,
+        roles::Role,
+        scopes::{Scope, format_scope, parse_scope},
+    },
+    error::AppError,
 };
-use jsonwebtoken::{DecodingKey, EncodingKey, Header, Validation, decode, encode};
+use axum::{extract::FromRequestParts, http::request::Parts};
 use rand::RngCore;
 use sha2::{Digest, Sha256};
+use sqlx::PgPool;
 use std::time::{SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+pub const ACCESS_TOKEN_TTL_SECS: u64 = 15 * 60;
+/// Impersonation tokens can't be refreshed; the admin asks for a new one.
+pub const IMPERSONATION_TOKEN_TTL_SECS: u64 = 10 * 60;
 
 #[derive(Debug)]
 pub struct AuthUser {
     pub user_id: i32,
+    pub role: Role,
+    pub scopes: Vec<Scope>,
+    /// Set when the caller authenticated with an API key rather than a login session.
+    pub api_key_id: Option<Uuid>,
+    /// Set when the token was issued to a third-party OAuth client acting for the user.
+    pub oauth_client_id: Option<Uuid>,
+    /// The admin actually making the request, when they are impersonating `user_id`.
+    pub impersonator_id: Option<i32>,
+}
+
+impl AuthUser {
+    pub fn has_scope(&self, scope: Scope) -> bool {
+        self.scopes.contains(&scope)
+    }
+
+    /// Rejects API keys and OAuth client tokens for account-management actions that need a
+    /// signed-in user.
+    pub fn require_session(&self) -> Result<(), AppError> {
+        if self.api_key_id.is_some() {
+            return Err(AppError::Forbidden(
+                "This action requires a login session and cannot be performed with an API key"
+                    .to_string(),
+            ));
+        }
+        if self.oauth_client_id.is_some() {
+            return Err(AppError::Forbidden(
+                "This action requires a login session and cannot be performed by an OAuth client"
+                    .to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Rejects actions an admin must not take on a user's behalf, like changing their password.
+    pub fn forbid_impersonation(&self) -> Result<(), AppError> {
+        if self.impersonator_id.is_some() {
+            return Err(AppError::Forbidden(
+                "This action cannot be performed while impersonating a user".to_string(),
+            ));
+        }
+        Ok(())
+    }
 }
 
 impl<S> FromRequestParts<S> for AuthUser
 where
     S: Send + Sync,
 {
-    type Rejection = StatusCode;
+    type Rejection = AppError;
 
-    async fn from_request_parts(
-        parts: &mut Parts,
-        _state: &S,
-    ) -> Result<Self, Self::Rejection> {
-        let auth_header = parts
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let bearer = parts
             .headers
             .get("Authorization")
             .and_then(|h| h.to_str().ok())
-            .and_then(|h| h.strip_prefix("Bearer "))
-            .ok_or(StatusCode::UNAUTHORIZED)?;
-
-        let secret = std::env::var("JWT_SECRET").expect("JWT_SECRET must be set");
-        let token_data = decode::<Claims>(
-            auth_header,
-            &DecodingKey::from_secret(secret.as_ref()),
-            &Validation::default(),
-        )
-        .map_err(|_| StatusCode::UNAUTHORIZED)?;
+            .and_then(|h| h.strip_prefix("Bearer "));
+
+        if let Some(key) = bearer.filter(|token| is_api_key(token)) {
+            let pool = parts
+                .extensions
+                .get::<PgPool>()
+                .ok_or_else(|| AppError::Internal("database pool extension missing".to_string()))?;
+            return authenticate_api_key(pool, key).await;
+        }
+
+        // Browsers in cookie mode send the access token as a cookie instead.
+        let token = match bearer {
+            Some(token) => token.to_string(),
+            None => match parts.extensions.get::<SharedCookieConfig>() {
+                Some(cookies) => {
+                    cookies.session_token(ACCESS_COOKIE, &parts.method, &parts.headers)?
+                }
+                None => None,
+            }
+            .ok_or_else(|| {
+                AppError::Unauthorized("Missing or invalid Authorization header".to_string())
+            })?,
+        };
+
+        let keys = parts
+            .extensions
+            .get::<SharedJwtKeys>()
+            .ok_or_else(|| AppError::Internal("JWT keys extension missing".to_string()))?;
+        let claims = keys
+            .decode::<Claims>(&token, None)
+            .map_err(|_| AppError::Unauthorized("Invalid or expired token".to_string()))?;
+
+        let scopes = parse_scope(&claims.scope)
+            .map_err(|_| AppError::Unauthorized("Invalid or expired token".to_string()))?;
+
+        let pool = parts
+            .extensions
+            .get::<PgPool>()
+            .ok_or_else(|| AppError::Internal("database pool extension missing".to_string()))?;
+        let revocations = parts
+            .extensions
+            .get::<SharedRevocationStore>()
+            .ok_or_else(|| AppError::Internal("revocation store extension missing".to_string()))?;
+        if revocations
+            .is_revoked(pool, claims.jti, claims.sub, claims.issued_at_us())
+            .await?
+        {
+            return Err(AppError::Unauthorized("Token has been revoked".to_string()));
+        }
+
+        let impersonator_id = claims.act.map(|actor| actor.sub);
+        if let Some(actor_id) = impersonator_id {
+            tracing::info!(
+                target: "security",
+                actor_id,
+                user_id = claims.sub,
+                method = %parts.method,
+                path = parts.uri.path(),
+                "impersonated request"
+            );
+        }
 
         Ok(AuthUser {
-            user_id: token_data.claims.sub,
+            user_id: claims.sub,
+            role: claims.role,
+            scopes,
+            api_key_id: None,
+            oauth_client_id: claims.client_id,
+            impersonator_id,
         })
     }
 }
 
-pub fn generate_token(user_id: i32, secret: &str) -> Result<String, ErrorResponse> {
+/// A signed access token and the `jti` it was issued with, for revoking it later.
+pub struct AccessToken {
+    pub token: String,
+    pub jti: Uuid,
+}
+
+pub fn generate_token(
+    user_id: i32,
+    role: Role,
+    scopes: &[Scope],
+    keys: &JwtKeys,
+) -> Result<String, AppError> {
+    Ok(generate_access_token(user_id, role, scopes, None, keys)?.token)
+}
+
+/// An access token for `user_id`, issued to `client_id` when a third-party client acts for them.
+pub fn generate_access_token(
+    user_id: i32,
+    role: Role,
+    scopes: &[Scope],
+    client_id: Option<Uuid>,
+    keys: &JwtKeys,
+) -> Result<AccessToken, AppError> {
+    sign_access_token(
+        user_id,
+        role,
+        scopes,
+        client_id,
+        None,
+        ACCESS_TOKEN_TTL_SECS,
+        keys,
+    )
+}
+
+/// A short-lived access token that lets the admin `actor_id` act as `user_id`.
+pub fn generate_impersonation_token(
+    user_id: i32,
+    role: Role,
+    scopes: &[Scope],
+    actor_id: i32,
+    keys: &JwtKeys,
+) -> Result<AccessToken, AppError> {
+    sign_access_token(
+        user_id,
+        role,
+        scopes,
+        None,
+        Some(Actor { sub: actor_id }),
+        IMPERSONATION_TOKEN_TTL_SECS,
+        keys,
+    )
+}
+
+fn sign_access_token(
+    user_id: i32,
+    role: Role,
+    scopes: &[Scope],
+    client_id: Option<Uuid>,
+    act: Option<Actor>,
+    ttl_secs: u64,
+    keys: &JwtKeys,
+) -> Result<AccessToken, AppError> {
+    let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+    let now = since_epoch.as_secs();
+
+    let jti = Uuid::new_v4();
+    let claims = Claims {
+        sub: user_id,
+        jti,
+        exp: now + ttl_secs,
+        iat: now,
+        iat_us: Some(since_epoch.as_micros() as u64),
+        role,
+        scope: format_scope(scopes),
+        client_id,
+        act,
+    };
+
+    Ok(AccessToken {
+        token: keys.encode(&claims)?,
+        jti,
+    })
+}
+
+pub fn generate_mfa_token(
+    user_id: i32,
+    scope: Option<&str>,
+    keys: &JwtKeys,
+) -> Result<String, AppError> {
     let now = SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .unwrap()
         .as_secs();
 
-    let claims = Claims {
+    let claims = MfaClaims {
         sub: user_id,
-        exp: now + 900, // 15 minutes
+        exp: now + 300, // 5 minutes to enter the code
         iat: now,
+        aud: MFA_AUDIENCE.to_string(),
+        scope: scope.map(str::to_string),
     };
 
-    encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(secret.as_ref()),
-    )
-    .map_err(|e| ErrorResponse {
-        error: e.to_string(),
-        message: "Failed to generate token".to_string(),
-        details: None,
-    })
+    keys.encode(&claims)
+}
+
+pub fn decode_mfa_token(token: &str, keys: &JwtKeys) -> Result<MfaClaims, AppError> {
+    keys.decode::<MfaClaims>(token, Some(MFA_AUDIENCE))
+        .map_err(|_| AppError::Unauthorized("Invalid or expired MFA token".to_string()))
 }
 
 pub fn generate_refresh_token() -> (String, String) {
@@ -84,6 +278,10 @@ pub fn hash_token(token: &str) -> String {
 mod tests {
     use super::*;
 
+    fn keys() -> JwtKeys {
+        JwtKeys::from_secret("test-secret")
+    }
+
     #[test]
     fn hash_token_is_deterministic() {
         assert_eq!(hash_token("abc"), hash_token("abc"));
@@ -123,7 +321,8 @@ mod tests {
 
     #[test]
     fn generate_token_round_trips() {
-        let token = generate_token(42, "test-secret").expect("token generation failed");
+        let token = generate_token(42, Role::Admin, &Scope::all_for(Role::Admin), &keys())
+            .expect("token generation failed");
         assert!(!token.is_empty());
 
         let decoded = jsonwebtoken::decode::<crate::auth::claims::Claims>(
@@ -134,5 +333,51 @@ mod tests {
         .expect("token decode failed");
 
         assert_eq!(decoded.claims.sub, 42);
+        assert_eq!(decoded.claims.role, Role::Admin);
+        assert_eq!(
+            decoded.claims.scope,
+            "posts:read posts:write users:read users:write users:admin"
+        );
+    }
+
+    #[test]
+    fn impersonation_token_names_the_actor() {
+        let token = generate_impersonation_token(7, Role::User, &[Scope::PostsRead], 1, &keys())
+            .unwrap()
+            .token;
+        let claims = keys().decode::<Claims>(&token, None).unwrap();
+
+        assert_eq!(claims.sub, 7);
+        assert_eq!(claims.act.map(|actor| actor.sub), Some(1));
+        assert_eq!(claims.exp - claims.iat, IMPERSONATION_TOKEN_TTL_SECS);
+    }
+
+    #[test]
+    fn regular_token_has_no_actor() {
+        let token = generate_token(7, Role::User, &[Scope::PostsRead], &keys()).unwrap();
+        assert!(keys().decode::<Claims>(&token, None).unwrap().act.is_none());
+    }
+
+    #[test]
+    fn mfa_token_round_trips() {
+        let token = generate_mfa_token(7, None, &keys()).unwrap();
+        assert_eq!(decode_mfa_token(&token, &keys()).unwrap().sub, 7);
+    }
+
+    #[test]
+    fn access_token_is_not_an_mfa_token() {
+        let token = generate_token(7, Role::User, &[Scope::PostsRead], &keys()).unwrap();
+        assert!(decode_mfa_token(&token, &keys()).is_err());
+    }
+
+    #[test]
+    fn mfa_token_is_not_an_access_token() {
+        let token = generate_mfa_token(7, None, &keys()).unwrap();
+        let decoded = jsonwebtoken::decode::<crate::auth::claims::Claims>(
+            &token,
+            &jsonwebtoken::DecodingKey::from_secret(b"test-secret"),
+            &jsonwebtoken::Validation::default(),
+        );
+        assert!(decoded.is_err());
     }
 }
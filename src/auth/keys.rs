@@ -0,0 +1,381 @@
+use crate::{
+    error::AppError,
+    models::jwks::{Jwk, JwkParams, JwkSet},
+};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use jsonwebtoken::{
+    Algorithm, DecodingKey, EncodingKey, Header, Validation, decode, decode_header, encode,
+    errors::ErrorKind,
+};
+use rsa::{
+    RsaPrivateKey, RsaPublicKey,
+    pkcs1::{DecodeRsaPrivateKey, DecodeRsaPublicKey},
+    pkcs8::{DecodePrivateKey, DecodePublicKey},
+    traits::PublicKeyParts,
+};
+use serde::{Serialize, de::DeserializeOwned};
+use sha2::{Digest, Sha256};
+use std::{fmt, sync::Arc};
+
+pub type SharedJwtKeys = Arc<JwtKeys>;
+
+#[derive(Debug)]
+pub struct KeyError(pub String);
+
+impl fmt::Display for KeyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid JWT key: {}", self.0)
+    }
+}
+
+impl std::error::Error for KeyError {}
+
+/// The key new tokens are signed with, plus every key tokens may still be verified with.
+///
+/// With `HS256` there is a single shared secret and no `kid`. With `RS256`/`EdDSA` each key is
+/// identified by a `kid` header, so retired keys can keep verifying tokens they signed until
+/// those expire, and the public halves are published as a JWKS.
+pub struct JwtKeys {
+    signing_kid: Option<String>,
+    signing_algorithm: Algorithm,
+    signing_key: EncodingKey,
+    verification: Vec<VerificationKey>,
+}
+
+struct VerificationKey {
+    kid: Option<String>,
+    algorithm: Algorithm,
+    key: DecodingKey,
+    jwk: Option<Jwk>,
+}
+
+enum PublicKey {
+    Rsa { n: Vec<u8>, e: Vec<u8> },
+    Ed25519([u8; 32]),
+}
+
+impl PublicKey {
+    fn from_private_pem(algorithm: Algorithm, pem: &str) -> Result<Self, KeyError> {
+        match algorithm {
+            Algorithm::RS256 => {
+                let key = RsaPrivateKey::from_pkcs8_pem(pem)
+                    .or_else(|_| RsaPrivateKey::from_pkcs1_pem(pem))
+                    .map_err(|e| KeyError(format!("not an RSA private key: {e}")))?;
+                Ok(Self::from_rsa(&key.to_public_key()))
+            }
+            Algorithm::EdDSA => {
+                let key = ed25519_dalek::SigningKey::from_pkcs8_pem(pem)
+                    .map_err(|e| KeyError(format!("not an Ed25519 private key: {e}")))?;
+                Ok(PublicKey::Ed25519(key.verifying_key().to_bytes()))
+            }
+            other => Err(KeyError(format!("unsupported algorithm {other:?}"))),
+        }
+    }
+
+    fn from_public_pem(pem: &str) -> Result<Self, KeyError> {
+        if let Ok(key) = ed25519_dalek::VerifyingKey::from_public_key_pem(pem) {
+            return Ok(PublicKey::Ed25519(key.to_bytes()));
+        }
+
+        RsaPublicKey::from_public_key_pem(pem)
+            .or_else(|_| RsaPublicKey::from_pkcs1_pem(pem))
+            .map(|key| Self::from_rsa(&key))
+            .map_err(|_| KeyError("expected an RSA or Ed25519 public key".to_string()))
+    }
+
+    fn from_rsa(key: &RsaPublicKey) -> Self {
+        PublicKey::Rsa {
+            n: key.n().to_bytes_be(),
+            e: key.e().to_bytes_be(),
+        }
+    }
+
+    fn algorithm(&self) -> Algorithm {
+        match self {
+            PublicKey::Rsa { .. } => Algorithm::RS256,
+            PublicKey::Ed25519(_) => Algorithm::EdDSA,
+        }
+    }
+
+    fn params(&self) -> JwkParams {
+        match self {
+            PublicKey::Rsa { n, e } => JwkParams::Rsa {
+                n: URL_SAFE_NO_PAD.encode(n),
+                e: URL_SAFE_NO_PAD.encode(e),
+            },
+            PublicKey::Ed25519(x) => JwkParams::Okp {
+                crv: "Ed25519",
+                x: URL_SAFE_NO_PAD.encode(x),
+            },
+        }
+    }
+
+    /// RFC 7638 thumbprint, used as the `kid` when none is configured.
+    fn thumbprint(&self) -> String {
+        let canonical = match self.params() {
+            JwkParams::Rsa { n, e } => format!(r#"{{"e":"{e}","kty":"RSA","n":"{n}"}}"#),
+            JwkParams::Okp { crv, x } => format!(r#"{{"crv":"{crv}","kty":"OKP","x":"{x}"}}"#),
+        };
+        URL_SAFE_NO_PAD.encode(Sha256::digest(canonical.as_bytes()))
+    }
+
+    fn into_verification_key(self, kid: Option<String>) -> Result<VerificationKey, KeyError> {
+        let kid = kid.unwrap_or_else(|| self.thumbprint());
+        let params = self.params();
+
+        let key = match &params {
+            JwkParams::Rsa { n, e } => DecodingKey::from_rsa_components(n, e),
+            JwkParams::Okp { x, .. } => DecodingKey::from_ed_components(x),
+        }
+        .map_err(|e| KeyError(e.to_string()))?;
+
+        Ok(VerificationKey {
+            kid: Some(kid.clone()),
+            algorithm: self.algorithm(),
+            key,
+            jwk: Some(Jwk {
+                params,
+                kid,
+                alg: match self.algorithm() {
+                    Algorithm::EdDSA => "EdDSA",
+                    _ => "RS256",
+                },
+                key_use: "sig",
+            }),
+        })
+    }
+}
+
+impl JwtKeys {
+    /// Signs and verifies with a single shared HS256 secret.
+    pub fn from_secret(secret: &str) -> Self {
+        JwtKeys {
+            signing_kid: None,
+            signing_algorithm: Algorithm::HS256,
+            signing_key: EncodingKey::from_secret(secret.as_ref()),
+            verification: vec![VerificationKey {
+                kid: None,
+                algorithm: Algorithm::HS256,
+                key: DecodingKey::from_secret(secret.as_ref()),
+                jwk: None,
+            }],
+        }
+    }
+
+    /// Signs with an `RS256` or `EdDSA` private key. `kid` defaults to the key's thumbprint.
+    pub fn from_private_pem(
+        algorithm: Algorithm,
+        pem: &str,
+        kid: Option<String>,
+    ) -> Result<Self, KeyError> {
+        let signing_key = match algorithm {
+            Algorithm::RS256 => EncodingKey::from_rsa_pem(pem.as_bytes()),
+            Algorithm::EdDSA => EncodingKey::from_ed_pem(pem.as_bytes()),
+            other => return Err(KeyError(format!("unsupported algorithm {other:?}"))),
+        }
+        .map_err(|e| KeyError(e.to_string()))?;
+
+        let verification =
+            PublicKey::from_private_pem(algorithm, pem)?.into_verification_key(kid)?;
+
+        Ok(JwtKeys {
+            signing_kid: verification.kid.clone(),
+            signing_algorithm: algorithm,
+            signing_key,
+            verification: vec![verification],
+        })
+    }
+
+    /// Also accepts tokens signed by the private half of `pem`, e.g. the key being rotated out.
+    pub fn with_verification_key(
+        mut self,
+        pem: &str,
+        kid: Option<String>,
+    ) -> Result<Self, KeyError> {
+        let key = PublicKey::from_public_pem(pem)?.into_verification_key(kid)?;
+        if self
+            .verification
+            .iter()
+            .any(|existing| existing.kid == key.kid)
+        {
+            return Err(KeyError(format!(
+                "duplicate key id {}",
+                key.kid.unwrap_or_default()
+            )));
+        }
+        self.verification.push(key);
+        Ok(self)
+    }
+
+    /// Loads keys from `JWT_ALGORITHM` (default `HS256`) and the variables that algorithm needs.
+    pub fn from_env() -> Self {
+        let algorithm = std::env::var("JWT_ALGORITHM").unwrap_or_else(|_| "HS256".to_string());
+
+        let algorithm = match algorithm.as_str() {
+            "HS256" => {
+                let secret = std::env::var("JWT_SECRET").expect("JWT_SECRET must be set");
+                return JwtKeys::from_secret(&secret);
+            }
+            "RS256" => Algorithm::RS256,
+            "EdDSA" => Algorithm::EdDSA,
+            other => panic!("unsupported JWT_ALGORITHM {other}, expected HS256, RS256 or EdDSA"),
+        };
+
+        let path = std::env::var("JWT_PRIVATE_KEY_FILE").expect("JWT_PRIVATE_KEY_FILE must be set");
+        let pem = read_pem(&path);
+        let kid = std::env::var("JWT_KEY_ID").ok();
+        let mut keys = JwtKeys::from_private_pem(algorithm, &pem, kid)
+            .unwrap_or_else(|e| panic!("{path}: {e}"));
+
+        // Comma-separated `path` or `kid=path` entries for retired keys that should still verify.
+        let extra = std::env::var("JWT_VERIFICATION_KEY_FILES").unwrap_or_default();
+        for entry in extra.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (kid, path) = match entry.split_once('=') {
+                Some((kid, path)) => (Some(kid.to_string()), path),
+                None => (None, entry),
+            };
+            keys = keys
+                .with_verification_key(&read_pem(path), kid)
+                .unwrap_or_else(|e| panic!("{path}: {e}"));
+        }
+
+        keys
+    }
+
+    pub fn encode<T: Serialize>(&self, claims: &T) -> Result<String, AppError> {
+        let mut header = Header::new(self.signing_algorithm);
+        header.kid = self.signing_kid.clone();
+        Ok(encode(&header, claims, &self.signing_key)?)
+    }
+
+    /// Verifies `token` against the key named by its `kid` header. Tokens with an `aud` claim
+    /// are rejected unless `audience` matches.
+    pub fn decode<T: DeserializeOwned>(
+        &self,
+        token: &str,
+        audience: Option<&str>,
+    ) -> Result<T, jsonwebtoken::errors::Error> {
+        let header = decode_header(token)?;
+        let key = self
+            .verification
+            .iter()
+            .find(|key| key.kid == header.kid && key.algorithm == header.alg)
+            .ok_or(ErrorKind::InvalidSignature)?;
+
+        let mut validation = Validation::new(key.algorithm);
+        if let Some(audience) = audience {
+            validation.set_audience(&[audience]);
+        }
+
+        Ok(decode::<T>(token, &key.key, &validation)?.claims)
+    }
+
+    pub fn jwks(&self) -> JwkSet {
+        JwkSet {
+            keys: self
+                .verification
+                .iter()
+                .filter_map(|key| key.jwk.clone())
+                .collect(),
+        }
+    }
+}
+
+fn read_pem(path: &str) -> String {
+    std::fs::read_to_string(path).unwrap_or_else(|e| panic!("failed to read {path}: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    const RSA_PRIVATE: &str = include_str!("../../tests/fixtures/jwt/rsa_private.pem");
+    const RSA_PREVIOUS_PRIVATE: &str =
+        include_str!("../../tests/fixtures/jwt/rsa_previous_private.pem");
+    const RSA_PREVIOUS_PUBLIC: &str =
+        include_str!("../../tests/fixtures/jwt/rsa_previous_public.pem");
+    const ED25519_PRIVATE: &str = include_str!("../../tests/fixtures/jwt/ed25519_private.pem");
+
+    #[derive(Serialize, Deserialize)]
+    struct TestClaims {
+        sub: i32,
+        exp: u64,
+    }
+
+    fn claims() -> TestClaims {
+        TestClaims {
+            sub: 7,
+            exp: jsonwebtoken::get_current_timestamp() + 60,
+        }
+    }
+
+    #[test]
+    fn rsa_tokens_carry_kid_and_round_trip() {
+        let keys =
+            JwtKeys::from_private_pem(Algorithm::RS256, RSA_PRIVATE, Some("k1".into())).unwrap();
+        let token = keys.encode(&claims()).unwrap();
+
+        let header = decode_header(&token).unwrap();
+        assert_eq!(header.alg, Algorithm::RS256);
+        assert_eq!(header.kid.as_deref(), Some("k1"));
+        assert_eq!(keys.decode::<TestClaims>(&token, None).unwrap().sub, 7);
+    }
+
+    #[test]
+    fn eddsa_tokens_round_trip_with_thumbprint_kid() {
+        let keys = JwtKeys::from_private_pem(Algorithm::EdDSA, ED25519_PRIVATE, None).unwrap();
+        let token = keys.encode(&claims()).unwrap();
+
+        let jwks = serde_json::to_value(keys.jwks()).unwrap();
+        assert_eq!(jwks["keys"][0]["kty"], "OKP");
+        assert_eq!(jwks["keys"][0]["crv"], "Ed25519");
+        assert_eq!(
+            decode_header(&token).unwrap().kid.unwrap(),
+            jwks["keys"][0]["kid"].as_str().unwrap()
+        );
+        assert_eq!(keys.decode::<TestClaims>(&token, None).unwrap().sub, 7);
+    }
+
+    #[test]
+    fn retired_keys_still_verify_after_rotation() {
+        let old = JwtKeys::from_private_pem(Algorithm::RS256, RSA_PREVIOUS_PRIVATE, None).unwrap();
+        let old_token = old.encode(&claims()).unwrap();
+
+        let new = JwtKeys::from_private_pem(Algorithm::RS256, RSA_PRIVATE, None).unwrap();
+        assert!(new.decode::<TestClaims>(&old_token, None).is_err());
+
+        let rotated = new
+            .with_verification_key(RSA_PREVIOUS_PUBLIC, None)
+            .unwrap();
+        assert_eq!(
+            rotated.decode::<TestClaims>(&old_token, None).unwrap().sub,
+            7
+        );
+        assert_eq!(rotated.jwks().keys.len(), 2);
+    }
+
+    #[test]
+    fn hs256_keys_are_not_published() {
+        let keys = JwtKeys::from_secret("test-secret");
+        let token = keys.encode(&claims()).unwrap();
+        assert!(decode_header(&token).unwrap().kid.is_none());
+        assert!(keys.decode::<TestClaims>(&token, None).is_ok());
+        assert!(keys.jwks().keys.is_empty());
+    }
+
+    #[test]
+    fn tokens_from_other_keys_are_rejected() {
+        let rsa = JwtKeys::from_private_pem(Algorithm::RS256, RSA_PRIVATE, None).unwrap();
+        let hs = JwtKeys::from_secret("test-secret");
+
+        assert!(
+            rsa.decode::<TestClaims>(&hs.encode(&claims()).unwrap(), None)
+                .is_err()
+        );
+        assert!(
+            hs.decode::<TestClaims>(&rsa.encode(&claims()).unwrap(), None)
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,224 @@
+use crate::{
+    auth::client_info::ClientInfo,
+    error::{AppError, AppResult},
+};
+use sqlx::PgPool;
+use std::sync::Arc;
+
+/// Limits on failed logins, read from `LOGIN_*` environment variables.
+#[derive(Debug, Clone)]
+pub struct LockoutPolicy {
+    /// Consecutive failures before an account is locked.
+    pub threshold: i32,
+    /// Length of the first lockout; each further failure doubles it.
+    pub base_lockout_secs: u64,
+    pub max_lockout_secs: u64,
+    /// Failures allowed from one IP address within `ip_window_secs`, across all accounts.
+    pub ip_max_failures: i64,
+    pub ip_window_secs: u64,
+}
+
+pub type SharedLockoutPolicy = Arc<LockoutPolicy>;
+
+impl Default for LockoutPolicy {
+    fn default() -> Self {
+        LockoutPolicy {
+            threshold: 5,
+            base_lockout_secs: 60,
+            max_lockout_secs: 3600,
+            ip_max_failures: 20,
+            ip_window_secs: 900,
+        }
+    }
+}
+
+impl LockoutPolicy {
+    pub fn from_env() -> Self {
+        fn var<T: std::str::FromStr>(name: &str, default: T) -> T {
+            std::env::var(name)
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(default)
+        }
+
+        let default = LockoutPolicy::default();
+        LockoutPolicy {
+            threshold: var("LOGIN_LOCKOUT_THRESHOLD", default.threshold),
+            base_lockout_secs: var("LOGIN_LOCKOUT_BASE_SECONDS", default.base_lockout_secs),
+            max_lockout_secs: var("LOGIN_LOCKOUT_MAX_SECONDS", default.max_lockout_secs),
+            ip_max_failures: var("LOGIN_IP_MAX_FAILURES", default.ip_max_failures),
+            ip_window_secs: var("LOGIN_IP_WINDOW_SECONDS", default.ip_window_secs),
+        }
+    }
+
+    /// How long to lock an account after its `failures`th consecutive failure, if at all.
+    pub fn lockout_for(&self, failures: i32) -> Option<u64> {
+        if failures < self.threshold {
+            return None;
+        }
+        let factor = 1u64
+            .checked_shl((failures - self.threshold) as u32)
+            .unwrap_or(u64::MAX);
+        Some(
+            self.base_lockout_secs
+                .saturating_mul(factor)
+                .min(self.max_lockout_secs),
+        )
+    }
+}
+
+fn too_many_requests(retry_after_secs: i64) -> AppError {
+    AppError::TooManyRequests {
+        message: "Too many failed login attempts, please try again later".to_string(),
+        retry_after_secs: retry_after_secs.max(1) as u64,
+    }
+}
+
+/// Rejects the request if its IP address has failed too often recently.
+pub async fn check_ip(pool: &PgPool, policy: &LockoutPolicy, client: &ClientInfo) -> AppResult<()> {
+    let Some(ip_address) = &client.ip_address else {
+        return Ok(());
+    };
+    let now = chrono::Utc::now().naive_utc();
+    let window_start = now - chrono::Duration::seconds(policy.ip_window_secs as i64);
+
+    let failures = sqlx::query!(
+        r#"SELECT COUNT(*) AS "count!", MIN(created_at) AS oldest FROM login_attempts
+        WHERE ip_address = $1 AND NOT succeeded AND created_at > $2"#,
+        ip_address,
+        window_start
+    )
+    .fetch_one(pool)
+    .await?;
+
+    match failures.oldest {
+        Some(oldest) if failures.count >= policy.ip_max_failures => {
+            let retry_after = (oldest - window_start).num_seconds();
+            Err(too_many_requests(retry_after))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Rejects the request while `user_id` is locked, before any password work is done.
+pub async fn check_account(pool: &PgPool, user_id: i32) -> AppResult<()> {
+    let now = chrono::Utc::now().naive_utc();
+
+    let locked_until = sqlx::query_scalar!("SELECT locked_until FROM users WHERE id = $1", user_id)
+        .fetch_optional(pool)
+        .await?
+        .flatten();
+
+    match locked_until {
+        Some(until) if until > now => Err(too_many_requests((until - now).num_seconds() + 1)),
+        _ => Ok(()),
+    }
+}
+
+async fn record_attempt(
+    pool: &PgPool,
+    user_id: Option<i32>,
+    username: &str,
+    client: &ClientInfo,
+    succeeded: bool,
+) -> AppResult<()> {
+    sqlx::query!(
+        "INSERT INTO login_attempts (user_id, username, ip_address, user_agent, succeeded)
+        VALUES ($1, $2, $3, $4, $5)",
+        user_id,
+        username,
+        client.ip_address,
+        client.user_agent,
+        succeeded
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Records a failed attempt and locks the account once it crosses the threshold.
+pub async fn record_failure(
+    pool: &PgPool,
+    policy: &LockoutPolicy,
+    user_id: Option<i32>,
+    username: &str,
+    client: &ClientInfo,
+) -> AppResult<()> {
+    record_attempt(pool, user_id, username, client, false).await?;
+
+    let Some(user_id) = user_id else {
+        return Ok(());
+    };
+
+    let failures = sqlx::query_scalar!(
+        "UPDATE users SET failed_login_count = failed_login_count + 1 WHERE id = $1
+        RETURNING failed_login_count",
+        user_id
+    )
+    .fetch_one(pool)
+    .await?;
+
+    if let Some(secs) = policy.lockout_for(failures) {
+        let locked_until = chrono::Utc::now().naive_utc() + chrono::Duration::seconds(secs as i64);
+        sqlx::query!(
+            "UPDATE users SET locked_until = $1 WHERE id = $2",
+            locked_until,
+            user_id
+        )
+        .execute(pool)
+        .await?;
+
+        tracing::warn!(
+            target: "security",
+            user_id,
+            failures,
+            lockout_secs = secs,
+            ip_address = client.ip_address.as_deref(),
+            "account locked after repeated failed logins"
+        );
+    }
+
+    Ok(())
+}
+
+/// Records a successful attempt and clears the account's failure count.
+pub async fn record_success(
+    pool: &PgPool,
+    user_id: i32,
+    username: &str,
+    client: &ClientInfo,
+) -> AppResult<()> {
+    record_attempt(pool, Some(user_id), username, client, true).await?;
+
+    sqlx::query!(
+        "UPDATE users SET failed_login_count = 0, locked_until = NULL
+        WHERE id = $1 AND (failed_login_count <> 0 OR locked_until IS NOT NULL)",
+        user_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lockout_starts_at_threshold_and_doubles() {
+        let policy = LockoutPolicy::default();
+        assert_eq!(policy.lockout_for(4), None);
+        assert_eq!(policy.lockout_for(5), Some(60));
+        assert_eq!(policy.lockout_for(6), Some(120));
+        assert_eq!(policy.lockout_for(7), Some(240));
+    }
+
+    #[test]
+    fn lockout_is_capped() {
+        let policy = LockoutPolicy::default();
+        assert_eq!(policy.lockout_for(11), Some(3600));
+        assert_eq!(policy.lockout_for(500), Some(3600));
+    }
+}
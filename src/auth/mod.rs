@@ -1,2 +1,19 @@
+pub mod account_deletion;
+pub mod api_keys;
+pub mod audit;
 pub mod claims;
+pub mod client_info;
+pub mod cookies;
 pub mod jwt;
+pub mod keys;
+pub mod lockout;
+pub mod oauth;
+pub mod oidc;
+pub mod password_hasher;
+pub mod password_policy;
+pub mod providers;
+pub mod refresh_tokens;
+pub mod revocation;
+pub mod roles;
+pub mod scopes;
+pub mod totp;
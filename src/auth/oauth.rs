@@ -0,0 +1,220 @@
+use crate::{
+    auth::{jwt::hash_token, scopes::Scope},
+    error::AppError,
+};
+use axum::{
+    Json,
+    http::{
+        HeaderMap, HeaderValue, StatusCode,
+        header::{AUTHORIZATION, CACHE_CONTROL, WWW_AUTHENTICATE},
+    },
+    response::{IntoResponse, Response},
+};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use reqwest::Url;
+use serde_json::json;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+pub const AUTHORIZATION_CODE_TTL_MINUTES: i64 = 10;
+
+/// An error from the token, introspection or revocation endpoints, in the RFC 6749 shape
+/// (`{"error": ..., "error_description": ...}`) that OAuth client libraries expect.
+#[derive(Debug)]
+pub struct OAuthError {
+    status: StatusCode,
+    error: &'static str,
+    description: String,
+}
+
+impl OAuthError {
+    fn new(status: StatusCode, error: &'static str, description: impl Into<String>) -> Self {
+        OAuthError {
+            status,
+            error,
+            description: description.into(),
+        }
+    }
+
+    pub fn invalid_request(description: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, "invalid_request", description)
+    }
+
+    pub fn invalid_client() -> Self {
+        Self::new(
+            StatusCode::UNAUTHORIZED,
+            "invalid_client",
+            "Client authentication failed",
+        )
+    }
+
+    pub fn invalid_grant(description: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, "invalid_grant", description)
+    }
+
+    pub fn unauthorized_client(description: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, "unauthorized_client", description)
+    }
+
+    pub fn invalid_scope(description: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, "invalid_scope", description)
+    }
+
+    pub fn unsupported_grant_type(grant_type: &str) -> Self {
+        Self::new(
+            StatusCode::BAD_REQUEST,
+            "unsupported_grant_type",
+            format!("Unsupported grant_type '{grant_type}'"),
+        )
+    }
+
+    pub fn error(&self) -> &'static str {
+        self.error
+    }
+}
+
+impl IntoResponse for OAuthError {
+    fn into_response(self) -> Response {
+        let mut response = (
+            self.status,
+            [(CACHE_CONTROL, HeaderValue::from_static("no-store"))],
+            Json(json!({ "error": self.error, "error_description": self.description })),
+        )
+            .into_response();
+        if self.status == StatusCode::UNAUTHORIZED {
+            response
+                .headers_mut()
+                .insert(WWW_AUTHENTICATE, HeaderValue::from_static("Basic"));
+        }
+        response
+    }
+}
+
+/// Client-facing failures become `invalid_request`; internal ones are logged and reported as
+/// `server_error` without detail.
+impl From<AppError> for OAuthError {
+    fn from(e: AppError) -> Self {
+        if e.status_code().is_server_error() {
+            tracing::error!(error = %e, "OAuth endpoint failed");
+            return Self::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "server_error",
+                "Something went wrong, please try again later",
+            );
+        }
+        Self::invalid_request(e.to_string())
+    }
+}
+
+impl From<sqlx::Error> for OAuthError {
+    fn from(e: sqlx::Error) -> Self {
+        AppError::from(e).into()
+    }
+}
+
+/// A registered client that proved who it is at one of the back-channel endpoints.
+#[derive(Debug)]
+pub struct AuthenticatedClient {
+    pub id: Uuid,
+    pub owner_id: i32,
+    pub scopes: Vec<Scope>,
+    /// Whether the client authenticated with a secret, as opposed to a public client that only
+    /// names itself.
+    pub confidential: bool,
+}
+
+/// Authenticates the calling client from HTTP Basic credentials or, failing that, the
+/// `client_id`/`client_secret` form fields. A client registered with a secret must present it.
+pub async fn authenticate_client(
+    pool: &PgPool,
+    headers: &HeaderMap,
+    client_id: Option<&str>,
+    client_secret: Option<&str>,
+) -> Result<AuthenticatedClient, OAuthError> {
+    let basic = headers
+        .get(AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Basic "))
+        .and_then(|encoded| STANDARD.decode(encoded).ok())
+        .and_then(|decoded| String::from_utf8(decoded).ok());
+    let (client_id, client_secret) = match &basic {
+        Some(credentials) => {
+            let (id, secret) = credentials
+                .split_once(':')
+                .ok_or_else(OAuthError::invalid_client)?;
+            (Some(id), Some(secret))
+        }
+        None => (client_id, client_secret),
+    };
+
+    let client_id = client_id
+        .and_then(|id| id.parse::<Uuid>().ok())
+        .ok_or_else(OAuthError::invalid_client)?;
+    let client = sqlx::query!(
+        r#"SELECT id, owner_id, secret_hash, scopes AS "scopes: Vec<Scope>"
+        FROM oauth_clients WHERE id = $1"#,
+        client_id
+    )
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(OAuthError::invalid_client)?;
+
+    let confidential = match (&client.secret_hash, client_secret) {
+        (Some(expected), Some(secret)) if *expected == hash_token(secret) => true,
+        (None, None) => false,
+        _ => return Err(OAuthError::invalid_client()),
+    };
+
+    Ok(AuthenticatedClient {
+        id: client.id,
+        owner_id: client.owner_id,
+        scopes: client.scopes,
+        confidential,
+    })
+}
+
+/// Redirect URIs must be absolute and fragment-free, and use HTTPS unless they point back at
+/// the user's own machine (for native apps and local development).
+pub fn validate_redirect_uri(uri: &str) -> Result<(), AppError> {
+    let invalid = || AppError::Validation(format!("'{uri}' is not a valid redirect URI"));
+    let url = Url::parse(uri).map_err(|_| invalid())?;
+    if url.fragment().is_some() {
+        return Err(invalid());
+    }
+
+    let loopback = matches!(url.host_str(), Some("localhost" | "127.0.0.1" | "[::1]"));
+    match url.scheme() {
+        "https" => Ok(()),
+        "http" if loopback => Ok(()),
+        _ => Err(invalid()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redirect_uris_must_be_https_outside_loopback() {
+        assert!(validate_redirect_uri("https://app.example.com/callback").is_ok());
+        assert!(validate_redirect_uri("http://localhost:8080/callback").is_ok());
+        assert!(validate_redirect_uri("http://127.0.0.1/callback").is_ok());
+        assert!(validate_redirect_uri("http://app.example.com/callback").is_err());
+        assert!(validate_redirect_uri("https://app.example.com/cb#frag").is_err());
+        assert!(validate_redirect_uri("/relative").is_err());
+    }
+
+    #[test]
+    fn server_errors_are_not_echoed() {
+        let error = OAuthError::from(AppError::Internal("secret detail".into()));
+        assert_eq!(error.error(), "server_error");
+        assert!(!error.description.contains("secret"));
+    }
+
+    #[test]
+    fn client_errors_become_invalid_request() {
+        let error = OAuthError::from(AppError::Validation("bad".into()));
+        assert_eq!(error.status, StatusCode::BAD_REQUEST);
+        assert_eq!(error.error(), "invalid_request");
+    }
+}
@@ -0,0 +1,349 @@
+use crate::error::{AppError, AppResult};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use jsonwebtoken::{
+    Algorithm, DecodingKey, Validation, decode, decode_header,
+    jwk::{Jwk, JwkSet},
+};
+use rand::RngCore;
+use reqwest::Url;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tokio::sync::OnceCell;
+
+/// Signature algorithms accepted on ID tokens. Symmetric ones are left out: they would let
+/// anyone holding the client secret mint tokens.
+const ID_TOKEN_ALGORITHMS: &[Algorithm] = &[
+    Algorithm::RS256,
+    Algorithm::RS384,
+    Algorithm::RS512,
+    Algorithm::PS256,
+    Algorithm::PS384,
+    Algorithm::PS512,
+    Algorithm::ES256,
+    Algorithm::ES384,
+    Algorithm::EdDSA,
+];
+
+/// The parts of a provider's `/.well-known/openid-configuration` we rely on.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProviderMetadata {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub jwks_uri: String,
+}
+
+/// Claims read from a validated ID token.
+#[derive(Debug, Deserialize)]
+pub struct IdTokenClaims {
+    pub sub: String,
+    pub email: Option<String>,
+    #[serde(default)]
+    pub email_verified: bool,
+    pub preferred_username: Option<String>,
+    nonce: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+/// One OpenID Connect provider users can sign in with.
+#[derive(Debug)]
+pub struct OidcProvider {
+    pub name: String,
+    pub issuer: String,
+    pub client_id: String,
+    /// Omitted for public clients, which rely on PKCE alone.
+    pub client_secret: Option<String>,
+    /// Where the provider sends the browser back to; the front end posts the code on to us.
+    pub redirect_uri: String,
+    pub scopes: String,
+    metadata: OnceCell<ProviderMetadata>,
+}
+
+impl OidcProvider {
+    pub fn new(
+        name: &str,
+        issuer: &str,
+        client_id: &str,
+        client_secret: Option<String>,
+        redirect_uri: &str,
+    ) -> Self {
+        OidcProvider {
+            name: name.to_string(),
+            issuer: issuer.trim_end_matches('/').to_string(),
+            client_id: client_id.to_string(),
+            client_secret,
+            redirect_uri: redirect_uri.to_string(),
+            scopes: "openid email profile".to_string(),
+            metadata: OnceCell::new(),
+        }
+    }
+
+    /// Reads `OIDC_<NAME>_ISSUER`, `OIDC_<NAME>_CLIENT_ID`, `OIDC_<NAME>_CLIENT_SECRET`,
+    /// `OIDC_<NAME>_REDIRECT_URI` (default `{FRONTEND_ORIGIN}/oidc/<name>/callback`) and
+    /// `OIDC_<NAME>_SCOPES`.
+    fn from_env(name: &str) -> Self {
+        let prefix = format!("OIDC_{}", name.to_uppercase());
+        let var = |suffix: &str| std::env::var(format!("{prefix}_{suffix}")).ok();
+        let required =
+            |suffix: &str| var(suffix).unwrap_or_else(|| panic!("{prefix}_{suffix} must be set"));
+
+        let redirect_uri = var("REDIRECT_URI").unwrap_or_else(|| {
+            let origin = std::env::var("FRONTEND_ORIGIN").expect("FRONTEND_ORIGIN must be set");
+            format!("{origin}/oidc/{name}/callback")
+        });
+        let mut provider = OidcProvider::new(
+            name,
+            &required("ISSUER"),
+            &required("CLIENT_ID"),
+            var("CLIENT_SECRET"),
+            &redirect_uri,
+        );
+        if let Some(scopes) = var("SCOPES") {
+            provider.scopes = scopes;
+        }
+        provider
+    }
+}
+
+/// The configured providers and the HTTP client used to talk to them.
+pub struct OidcProviders {
+    http: reqwest::Client,
+    providers: HashMap<String, OidcProvider>,
+}
+
+pub type SharedOidcProviders = Arc<OidcProviders>;
+
+impl OidcProviders {
+    pub fn new(providers: Vec<OidcProvider>) -> Self {
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .expect("failed to build HTTP client");
+
+        OidcProviders {
+            http,
+            providers: providers
+                .into_iter()
+                .map(|provider| (provider.name.clone(), provider))
+                .collect(),
+        }
+    }
+
+    /// Providers named in the comma-separated `OIDC_PROVIDERS`, each configured as described on
+    /// [`OidcProvider::from_env`]. Empty when unset.
+    pub fn from_env() -> Self {
+        let names = std::env::var("OIDC_PROVIDERS").unwrap_or_default();
+        OidcProviders::new(
+            names
+                .split(',')
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(OidcProvider::from_env)
+                .collect(),
+        )
+    }
+
+    pub fn get(&self, name: &str) -> AppResult<&OidcProvider> {
+        self.providers
+            .get(name)
+            .ok_or_else(|| AppError::NotFound(format!("Unknown identity provider '{name}'")))
+    }
+
+    /// Fetched once per provider and then reused.
+    async fn metadata<'a>(&self, provider: &'a OidcProvider) -> AppResult<&'a ProviderMetadata> {
+        provider
+            .metadata
+            .get_or_try_init(|| async {
+                let url = format!("{}/.well-known/openid-configuration", provider.issuer);
+                let metadata: ProviderMetadata = self
+                    .http
+                    .get(&url)
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json()
+                    .await?;
+
+                // Stops a compromised discovery document from vouching for another issuer.
+                if metadata.issuer.trim_end_matches('/') != provider.issuer {
+                    return Err(AppError::Internal(format!(
+                        "discovery document for {} names issuer {}",
+                        provider.issuer, metadata.issuer
+                    )));
+                }
+                Ok(metadata)
+            })
+            .await
+    }
+
+    /// Where to send the browser to sign in, bound to `state`, the PKCE `code_challenge` and
+    /// `nonce`.
+    pub async fn authorization_url(
+        &self,
+        provider: &OidcProvider,
+        state: &str,
+        code_challenge: &str,
+        nonce: &str,
+    ) -> AppResult<String> {
+        let metadata = self.metadata(provider).await?;
+        let url = Url::parse_with_params(
+            &metadata.authorization_endpoint,
+            [
+                ("response_type", "code"),
+                ("client_id", provider.client_id.as_str()),
+                ("redirect_uri", provider.redirect_uri.as_str()),
+                ("scope", provider.scopes.as_str()),
+                ("state", state),
+                ("code_challenge", code_challenge),
+                ("code_challenge_method", "S256"),
+                ("nonce", nonce),
+            ],
+        )
+        .map_err(|e| AppError::Internal(format!("invalid authorization endpoint: {e}")))?;
+
+        Ok(url.into())
+    }
+
+    /// Redeems an authorization code and returns the claims of the validated ID token.
+    pub async fn exchange_code(
+        &self,
+        provider: &OidcProvider,
+        code: &str,
+        code_verifier: &str,
+        nonce: &str,
+    ) -> AppResult<IdTokenClaims> {
+        let rejected =
+            || AppError::Unauthorized("Sign-in with the identity provider failed".into());
+        let metadata = self.metadata(provider).await?;
+
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", provider.redirect_uri.as_str()),
+            ("client_id", provider.client_id.as_str()),
+            ("code_verifier", code_verifier),
+        ];
+        if let Some(secret) = &provider.client_secret {
+            form.push(("client_secret", secret));
+        }
+
+        let response = self
+            .http
+            .post(&metadata.token_endpoint)
+            .form(&form)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            tracing::warn!(
+                provider = provider.name,
+                status = %response.status(),
+                "identity provider rejected authorization code"
+            );
+            return Err(rejected());
+        }
+        let tokens: TokenResponse = response.json().await?;
+
+        let claims = self
+            .validate_id_token(provider, metadata, &tokens.id_token)
+            .await
+            .map_err(|e| {
+                tracing::warn!(provider = provider.name, error = %e, "invalid ID token");
+                rejected()
+            })?;
+
+        // Ties the token to the sign-in we started, so a token captured elsewhere can't be
+        // replayed into it.
+        if claims.nonce.as_deref() != Some(nonce) {
+            tracing::warn!(provider = provider.name, "ID token nonce mismatch");
+            return Err(rejected());
+        }
+        Ok(claims)
+    }
+
+    async fn validate_id_token(
+        &self,
+        provider: &OidcProvider,
+        metadata: &ProviderMetadata,
+        id_token: &str,
+    ) -> AppResult<IdTokenClaims> {
+        let header = decode_header(id_token)?;
+        if !ID_TOKEN_ALGORITHMS.contains(&header.alg) {
+            return Err(AppError::Unauthorized(format!(
+                "ID token signed with unsupported algorithm {:?}",
+                header.alg
+            )));
+        }
+
+        // Fetched every time so a provider's key rotation is picked up straight away.
+        let jwks: JwkSet = self
+            .http
+            .get(&metadata.jwks_uri)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let jwk: &Jwk = match &header.kid {
+            Some(kid) => jwks.find(kid),
+            None if jwks.keys.len() == 1 => jwks.keys.first(),
+            None => None,
+        }
+        .ok_or_else(|| AppError::Unauthorized("ID token signing key not found".to_string()))?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&metadata.issuer]);
+        validation.set_audience(&[&provider.client_id]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+
+        Ok(decode::<IdTokenClaims>(id_token, &DecodingKey::from_jwk(jwk)?, &validation)?.claims)
+    }
+}
+
+/// A random URL-safe value, for `state`, `nonce` and PKCE verifiers.
+pub fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// The S256 PKCE challenge for `verifier` (RFC 7636).
+pub fn pkce_challenge(verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pkce_challenge_matches_rfc_7636_example() {
+        assert_eq!(
+            pkce_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"),
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        );
+    }
+
+    #[test]
+    fn random_tokens_are_url_safe_and_unique() {
+        let token = random_token();
+        assert_eq!(token.len(), 43);
+        assert!(
+            token
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        );
+        assert_ne!(token, random_token());
+    }
+
+    #[test]
+    fn unknown_providers_are_not_found() {
+        let providers = OidcProviders::new(vec![]);
+        assert!(matches!(providers.get("nope"), Err(AppError::NotFound(_))));
+    }
+}
@@ -0,0 +1,226 @@
+use crate::error::{AppError, AppResult};
+use argon2::{
+    Algorithm, Argon2, Params, PasswordHash, PasswordHasher as _, PasswordVerifier, Version,
+    password_hash::SaltString,
+};
+use rand::rngs::OsRng;
+use std::sync::LazyLock;
+
+/// One password hashing algorithm, recognised by the format of the hashes it writes.
+pub trait PasswordScheme: Send + Sync {
+    /// Whether `hash` was produced by this scheme.
+    fn matches(&self, hash: &str) -> bool;
+    fn hash(&self, password: &str) -> AppResult<String>;
+    fn verify(&self, password: &str, hash: &str) -> bool;
+    /// Whether `hash` was produced with weaker settings than this scheme now uses.
+    fn is_outdated(&self, hash: &str) -> bool;
+}
+
+/// Argon2id, written as a PHC string such as `$argon2id$v=19$m=19456,t=2,p=1$...`.
+pub struct Argon2idScheme {
+    params: Params,
+}
+
+impl Argon2idScheme {
+    pub fn new(params: Params) -> Self {
+        Argon2idScheme { params }
+    }
+
+    /// Reads `ARGON2_MEMORY_KIB`, `ARGON2_ITERATIONS` and `ARGON2_PARALLELISM`, defaulting to
+    /// the OWASP-recommended 19 MiB, 2 iterations, 1 lane.
+    pub fn from_env() -> Self {
+        fn var(name: &str, default: u32) -> u32 {
+            std::env::var(name)
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(default)
+        }
+
+        let params = Params::new(
+            var("ARGON2_MEMORY_KIB", 19 * 1024),
+            var("ARGON2_ITERATIONS", 2),
+            var("ARGON2_PARALLELISM", 1),
+            None,
+        )
+        .expect("invalid ARGON2_* settings");
+        Argon2idScheme::new(params)
+    }
+
+    fn argon2(&self) -> Argon2<'_> {
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, self.params.clone())
+    }
+}
+
+impl PasswordScheme for Argon2idScheme {
+    fn matches(&self, hash: &str) -> bool {
+        hash.starts_with("$argon2id$")
+    }
+
+    fn hash(&self, password: &str) -> AppResult<String> {
+        let salt = SaltString::generate(&mut OsRng);
+        self.argon2()
+            .hash_password(password.as_bytes(), &salt)
+            .map(|hash| hash.to_string())
+            .map_err(|e| AppError::Internal(format!("password hashing failed: {e}")))
+    }
+
+    fn verify(&self, password: &str, hash: &str) -> bool {
+        // The parameters are read back from the hash, so older settings still verify.
+        PasswordHash::new(hash).is_ok_and(|parsed| {
+            self.argon2()
+                .verify_password(password.as_bytes(), &parsed)
+                .is_ok()
+        })
+    }
+
+    fn is_outdated(&self, hash: &str) -> bool {
+        let Ok(parsed) = PasswordHash::new(hash) else {
+            return true;
+        };
+        let Ok(params) = Params::try_from(&parsed) else {
+            return true;
+        };
+
+        parsed.version != Some(Version::V0x13.into())
+            || params.m_cost() < self.params.m_cost()
+            || params.t_cost() < self.params.t_cost()
+            || params.p_cost() < self.params.p_cost()
+    }
+}
+
+/// bcrypt, as written by earlier versions of the app (`$2b$12$...`).
+pub struct BcryptScheme {
+    cost: u32,
+}
+
+impl BcryptScheme {
+    pub fn new(cost: u32) -> Self {
+        BcryptScheme { cost }
+    }
+}
+
+impl PasswordScheme for BcryptScheme {
+    fn matches(&self, hash: &str) -> bool {
+        ["$2a$", "$2b$", "$2x$", "$2y$"]
+            .iter()
+            .any(|prefix| hash.starts_with(prefix))
+    }
+
+    fn hash(&self, password: &str) -> AppResult<String> {
+        Ok(bcrypt::hash(password, self.cost)?)
+    }
+
+    fn verify(&self, password: &str, hash: &str) -> bool {
+        bcrypt::verify(password, hash).unwrap_or(false)
+    }
+
+    fn is_outdated(&self, hash: &str) -> bool {
+        hash.get(4..6)
+            .and_then(|cost| cost.parse::<u32>().ok())
+            .is_none_or(|cost| cost < self.cost)
+    }
+}
+
+/// Hashes new passwords with the current scheme while still accepting hashes from older ones.
+pub struct PasswordHasher {
+    current: Box<dyn PasswordScheme>,
+    legacy: Vec<Box<dyn PasswordScheme>>,
+}
+
+impl PasswordHasher {
+    pub fn new(current: Box<dyn PasswordScheme>, legacy: Vec<Box<dyn PasswordScheme>>) -> Self {
+        PasswordHasher { current, legacy }
+    }
+
+    pub fn from_env() -> Self {
+        PasswordHasher::new(
+            Box::new(Argon2idScheme::from_env()),
+            vec![Box::new(BcryptScheme::new(bcrypt::DEFAULT_COST))],
+        )
+    }
+
+    fn scheme_for(&self, hash: &str) -> Option<&dyn PasswordScheme> {
+        std::iter::once(&self.current)
+            .chain(&self.legacy)
+            .find(|scheme| scheme.matches(hash))
+            .map(|scheme| scheme.as_ref())
+    }
+
+    pub fn hash(&self, password: &str) -> AppResult<String> {
+        self.current.hash(password)
+    }
+
+    /// Unrecognised hashes, including the empty placeholder some older accounts have, never match.
+    pub fn verify(&self, password: &str, hash: &str) -> bool {
+        self.scheme_for(hash)
+            .is_some_and(|scheme| scheme.verify(password, hash))
+    }
+
+    /// Whether `hash` should be replaced with a fresh one the next time the password is known.
+    pub fn needs_rehash(&self, hash: &str) -> bool {
+        !self.current.matches(hash) || self.current.is_outdated(hash)
+    }
+}
+
+static PASSWORD_HASHER: LazyLock<PasswordHasher> = LazyLock::new(PasswordHasher::from_env);
+
+/// The process-wide hasher configured from the environment.
+pub fn password_hasher() -> &'static PasswordHasher {
+    &PASSWORD_HASHER
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Deliberately cheap parameters so the tests stay fast.
+    fn hasher_with(m_cost: u32) -> PasswordHasher {
+        PasswordHasher::new(
+            Box::new(Argon2idScheme::new(
+                Params::new(m_cost, 1, 1, None).unwrap(),
+            )),
+            vec![Box::new(BcryptScheme::new(4))],
+        )
+    }
+
+    #[test]
+    fn new_hashes_are_argon2id() {
+        let hasher = hasher_with(1024);
+        let hash = hasher.hash("hunter2").unwrap();
+        assert!(hash.starts_with("$argon2id$v=19$m=1024,t=1,p=1$"));
+        assert!(hasher.verify("hunter2", &hash));
+        assert!(!hasher.verify("wrong", &hash));
+        assert!(!hasher.needs_rehash(&hash));
+    }
+
+    #[test]
+    fn legacy_bcrypt_hashes_verify_but_need_rehash() {
+        let hasher = hasher_with(1024);
+        let hash = bcrypt::hash("hunter2", 4).unwrap();
+        assert!(hasher.verify("hunter2", &hash));
+        assert!(!hasher.verify("wrong", &hash));
+        assert!(hasher.needs_rehash(&hash));
+    }
+
+    #[test]
+    fn weaker_argon2_parameters_need_rehash() {
+        let old = hasher_with(1024).hash("hunter2").unwrap();
+        let hasher = hasher_with(2048);
+        assert!(hasher.verify("hunter2", &old));
+        assert!(hasher.needs_rehash(&old));
+    }
+
+    #[test]
+    fn unknown_hashes_never_verify() {
+        let hasher = hasher_with(1024);
+        assert!(!hasher.verify("", ""));
+        assert!(!hasher.verify("hunter2", "hunter2"));
+    }
+
+    #[test]
+    fn bcrypt_cost_is_read_from_the_hash() {
+        let scheme = BcryptScheme::new(10);
+        assert!(scheme.is_outdated("$2b$04$abcdefghijklmnopqrstuu"));
+        assert!(!scheme.is_outdated("$2b$12$abcdefghijklmnopqrstuu"));
+    }
+}
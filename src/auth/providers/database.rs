@@ -0,0 +1,52 @@
+use crate::{
+    auth::providers::AuthProvider,
+    error::{AppError, AppResult},
+    models::users::User,
+};
+use async_trait::async_trait;
+use sqlx::PgPool;
+
+/// Checks passwords against the hashes in `users.password_hash`.
+pub struct DatabaseProvider;
+
+#[async_trait]
+impl AuthProvider for DatabaseProvider {
+    async fn authenticate(
+        &self,
+        pool: &PgPool,
+        user: Option<User>,
+        _username: &str,
+        password: &str,
+    ) -> AppResult<Option<User>> {
+        let Some(user) = user.filter(|user| user.verify_password(password)) else {
+            return Ok(None);
+        };
+
+        if user.password_needs_rehash() {
+            upgrade_password_hash(pool, &user, password).await;
+        }
+        Ok(Some(user))
+    }
+}
+
+/// Replaces an outdated hash now that we know the password. Failures only cost us the upgrade.
+async fn upgrade_password_hash(pool: &PgPool, user: &User, password: &str) {
+    let result = async {
+        let password_hash = User::hash_password(password)?;
+        // Matching on the old hash avoids clobbering a password change that raced this login.
+        sqlx::query!(
+            "UPDATE users SET password_hash = $1 WHERE id = $2 AND password_hash = $3",
+            password_hash,
+            user.id,
+            user.password_hash
+        )
+        .execute(pool)
+        .await?;
+        Ok::<_, AppError>(())
+    }
+    .await;
+
+    if let Err(e) = result {
+        tracing::warn!(user_id = user.id, error = %e, "failed to upgrade password hash");
+    }
+}
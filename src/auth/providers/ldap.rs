@@ -0,0 +1,383 @@
+use crate::{
+    auth::{providers::AuthProvider, roles::Role},
+    error::{AppError, AppResult},
+    models::users::User,
+};
+use async_trait::async_trait;
+use ldap3::{LdapConnAsync, LdapConnSettings, Scope, SearchEntry, ldap_escape};
+use sqlx::PgPool;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// `user_identities.provider` for accounts linked to a directory entry.
+pub const LDAP_PROVIDER: &str = "ldap";
+
+/// LDAP result code for a failed bind.
+const INVALID_CREDENTIALS: u32 = 49;
+
+/// A directory entry whose password was just verified.
+#[derive(Debug, Clone)]
+pub struct DirectoryUser {
+    pub dn: String,
+    pub email: Option<String>,
+}
+
+/// Where the LDAP provider verifies passwords, so tests can swap in an in-process stub.
+#[async_trait]
+pub trait Directory: Send + Sync {
+    /// The entry for `username` if `password` is correct for it.
+    async fn authenticate(
+        &self,
+        username: &str,
+        password: &str,
+    ) -> AppResult<Option<DirectoryUser>>;
+}
+
+/// Signs users in against a directory, creating a local account the first time each one logs in.
+///
+/// Local accounts are linked to their directory entry through `user_identities`, like OpenID
+/// Connect sign-ins. An existing account with the same username is only linked if the directory
+/// has the same email address for it.
+pub struct LdapProvider {
+    directory: Arc<dyn Directory>,
+}
+
+impl LdapProvider {
+    pub fn new(directory: Arc<dyn Directory>) -> Self {
+        LdapProvider { directory }
+    }
+}
+
+#[async_trait]
+impl AuthProvider for LdapProvider {
+    async fn authenticate(
+        &self,
+        pool: &PgPool,
+        user: Option<User>,
+        username: &str,
+        password: &str,
+    ) -> AppResult<Option<User>> {
+        let Some(entry) = self.directory.authenticate(username, password).await? else {
+            return Ok(None);
+        };
+        find_or_provision_user(pool, user, username, &entry)
+            .await
+            .map(Some)
+    }
+}
+
+async fn find_or_provision_user(
+    pool: &PgPool,
+    user: Option<User>,
+    username: &str,
+    entry: &DirectoryUser,
+) -> AppResult<User> {
+    let now = chrono::Utc::now().naive_utc();
+    let mut tx = pool.begin().await?;
+
+    // Once linked, the entry keeps signing in to the same account even if it's renamed locally.
+    let linked = sqlx::query_as!(
+        User,
+        r#"UPDATE user_identities i SET last_login_at = $3, email = COALESCE($4, i.email)
+        FROM users u
+        WHERE u.id = i.user_id AND i.provider = $1 AND i.subject = $2 AND u.deleted_at IS NULL
+        RETURNING u.id, u.username, u.email, u.created_at, u.password_hash,
+            u.role AS "role: Role", u.email_verified_at, u.totp_enabled_at"#,
+        LDAP_PROVIDER,
+        entry.dn,
+        now,
+        entry.email,
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+    if let Some(linked) = linked {
+        tx.commit().await?;
+        return Ok(linked);
+    }
+
+    let email = entry.email.as_deref().ok_or_else(|| {
+        AppError::Validation("The directory entry has no email address".to_string())
+    })?;
+
+    let user = match user {
+        // Otherwise whoever holds this name in the directory could take over a local account.
+        Some(user) if !user.email.eq_ignore_ascii_case(email) => {
+            return Err(AppError::Conflict(
+                "An account with this username already exists".to_string(),
+            ));
+        }
+        // Whoever registered it never proved they own the address, and would keep any sessions
+        // and API keys they hold on the account once it's linked.
+        Some(user) if user.email_verified_at.is_none() => {
+            return Err(AppError::Conflict(
+                "An unverified account with this email address already exists".to_string(),
+            ));
+        }
+        Some(user) => user,
+        None => {
+            let email_taken = sqlx::query_scalar!(
+                r#"SELECT EXISTS(SELECT 1 FROM users WHERE LOWER(email) = LOWER($1)) AS "taken!""#,
+                email
+            )
+            .fetch_one(&mut *tx)
+            .await?;
+            if email_taken {
+                return Err(AppError::Conflict(
+                    "An account with this email already exists".to_string(),
+                ));
+            }
+
+            // No password: the directory checks it on every login. The directory also vouches
+            // for the email address.
+            sqlx::query_as!(
+                User,
+                r#"INSERT INTO users (username, email, password_hash, email_verified_at)
+                VALUES ($1, $2, '', $3)
+                RETURNING id, username, email, created_at, password_hash,
+                    role AS "role: Role", email_verified_at, totp_enabled_at"#,
+                username,
+                email,
+                now,
+            )
+            .fetch_one(&mut *tx)
+            .await?
+        }
+    };
+
+    sqlx::query!(
+        "INSERT INTO user_identities (user_id, provider, subject, email, last_login_at)
+        VALUES ($1, $2, $3, $4, $5)",
+        user.id,
+        LDAP_PROVIDER,
+        entry.dn,
+        email,
+        now,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    tracing::info!(
+        target: "security",
+        user_id = user.id,
+        dn = %entry.dn,
+        "linked directory account"
+    );
+    Ok(user)
+}
+
+/// Verifies passwords with an LDAP search-then-bind: find the user's entry (as a service account
+/// if one is configured), then bind as that entry with the password given.
+pub struct LdapDirectory {
+    url: String,
+    starttls: bool,
+    bind_dn: Option<String>,
+    bind_password: String,
+    base_dn: String,
+    /// Search filter with `{username}` standing in for the escaped username.
+    user_filter: String,
+    email_attribute: String,
+}
+
+impl LdapDirectory {
+    /// Reads `LDAP_URL`, `LDAP_BASE_DN`, and optionally `LDAP_STARTTLS`, `LDAP_BIND_DN`,
+    /// `LDAP_BIND_PASSWORD`, `LDAP_USER_FILTER` (default `(uid={username})`) and
+    /// `LDAP_EMAIL_ATTRIBUTE` (default `mail`).
+    pub fn from_env() -> Self {
+        LdapDirectory {
+            url: std::env::var("LDAP_URL").expect("LDAP_URL must be set"),
+            starttls: std::env::var("LDAP_STARTTLS").is_ok_and(|v| v == "true"),
+            bind_dn: std::env::var("LDAP_BIND_DN").ok(),
+            bind_password: std::env::var("LDAP_BIND_PASSWORD").unwrap_or_default(),
+            base_dn: std::env::var("LDAP_BASE_DN").expect("LDAP_BASE_DN must be set"),
+            user_filter: std::env::var("LDAP_USER_FILTER")
+                .unwrap_or_else(|_| "(uid={username})".to_string()),
+            email_attribute: std::env::var("LDAP_EMAIL_ATTRIBUTE")
+                .unwrap_or_else(|_| "mail".to_string()),
+        }
+    }
+
+    fn user_filter(&self, username: &str) -> String {
+        self.user_filter
+            .replace("{username}", &ldap_escape(username))
+    }
+}
+
+#[async_trait]
+impl Directory for LdapDirectory {
+    async fn authenticate(
+        &self,
+        username: &str,
+        password: &str,
+    ) -> AppResult<Option<DirectoryUser>> {
+        // An empty password makes the bind "unauthenticated", which servers accept for any DN.
+        if password.is_empty() {
+            return Ok(None);
+        }
+
+        let settings = LdapConnSettings::new()
+            .set_conn_timeout(Duration::from_secs(5))
+            .set_starttls(self.starttls);
+        let (conn, mut ldap) = LdapConnAsync::with_settings(settings, &self.url)
+            .await
+            .map_err(ldap_error)?;
+        ldap3::drive!(conn);
+
+        if let Some(bind_dn) = &self.bind_dn {
+            ldap.simple_bind(bind_dn, &self.bind_password)
+                .await
+                .and_then(|result| result.success())
+                .map_err(ldap_error)?;
+        }
+
+        let (entries, _) = ldap
+            .search(
+                &self.base_dn,
+                Scope::Subtree,
+                &self.user_filter(username),
+                vec![self.email_attribute.as_str()],
+            )
+            .await
+            .and_then(|result| result.success())
+            .map_err(ldap_error)?;
+        // No match, or a filter too loose to tell which entry is meant.
+        let mut entries = entries.into_iter();
+        let (Some(entry), None) = (entries.next(), entries.next()) else {
+            let _ = ldap.unbind().await;
+            return Ok(None);
+        };
+        let entry = SearchEntry::construct(entry);
+
+        let bind = ldap
+            .simple_bind(&entry.dn, password)
+            .await
+            .map_err(ldap_error)?;
+        let _ = ldap.unbind().await;
+        match bind.rc {
+            0 => {}
+            INVALID_CREDENTIALS => return Ok(None),
+            rc => return Err(ldap_error(format!("bind returned {rc}: {}", bind.text))),
+        }
+
+        let email = entry
+            .attrs
+            .get(&self.email_attribute)
+            .and_then(|values| values.first())
+            .cloned();
+        Ok(Some(DirectoryUser {
+            dn: entry.dn,
+            email,
+        }))
+    }
+}
+
+fn ldap_error(e: impl std::fmt::Display) -> AppError {
+    AppError::Internal(format!("LDAP error: {e}"))
+}
+
+/// A directory held in memory, for tests; clones share the same entries.
+#[derive(Clone, Default)]
+pub struct InMemoryDirectory {
+    entries: Arc<Mutex<HashMap<String, (String, DirectoryUser)>>>,
+}
+
+impl InMemoryDirectory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an entry `uid=<username>,ou=people,dc=example,dc=com`.
+    pub fn add_user(&self, username: &str, password: &str, email: Option<&str>) {
+        let entry = DirectoryUser {
+            dn: format!("uid={username},ou=people,dc=example,dc=com"),
+            email: email.map(str::to_string),
+        };
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(username.to_string(), (password.to_string(), entry));
+    }
+}
+
+#[async_trait]
+impl Directory for InMemoryDirectory {
+    async fn authenticate(
+        &self,
+        username: &str,
+        password: &str,
+    ) -> AppResult<Option<DirectoryUser>> {
+        Ok(self
+            .entries
+            .lock()
+            .unwrap()
+            .get(username)
+            .filter(|(stored, _)| !password.is_empty() && stored == password)
+            .map(|(_, entry)| entry.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn directory(user_filter: &str) -> LdapDirectory {
+        LdapDirectory {
+            url: "ldap://localhost:389".to_string(),
+            starttls: false,
+            bind_dn: None,
+            bind_password: String::new(),
+            base_dn: "dc=example,dc=com".to_string(),
+            user_filter: user_filter.to_string(),
+            email_attribute: "mail".to_string(),
+        }
+    }
+
+    #[test]
+    fn user_filter_substitutes_username() {
+        assert_eq!(
+            directory("(&(objectClass=person)(uid={username}))").user_filter("alice"),
+            "(&(objectClass=person)(uid=alice))"
+        );
+    }
+
+    #[test]
+    fn user_filter_escapes_filter_syntax() {
+        // Otherwise `*` would match any entry and `)(` could widen the filter.
+        assert_eq!(
+            directory("(uid={username})").user_filter("*)(uid=admin"),
+            r"(uid=\2a\29\28uid=admin)"
+        );
+    }
+
+    #[tokio::test]
+    async fn in_memory_directory_rejects_wrong_and_empty_passwords() {
+        let directory = InMemoryDirectory::new();
+        directory.add_user("alice", "secret", Some("alice@example.com"));
+
+        assert!(
+            directory
+                .authenticate("alice", "secret")
+                .await
+                .unwrap()
+                .is_some()
+        );
+        assert!(
+            directory
+                .authenticate("alice", "wrong")
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            directory
+                .authenticate("bob", "secret")
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+}
@@ -0,0 +1,40 @@
+pub mod database;
+pub mod ldap;
+
+use crate::{error::AppResult, models::users::User};
+use async_trait::async_trait;
+use sqlx::PgPool;
+use std::sync::Arc;
+
+pub use database::DatabaseProvider;
+pub use ldap::{InMemoryDirectory, LdapDirectory, LdapProvider};
+
+/// Checks the password step of a login.
+///
+/// `login` looks up the local account and enforces lockouts itself; the provider only decides
+/// whether the password is right.
+#[async_trait]
+pub trait AuthProvider: Send + Sync {
+    /// Returns the account to sign in if `password` is correct for `username`. `user` is the
+    /// local account with that username, if there is one; a provider that vouches for users
+    /// itself may create it.
+    async fn authenticate(
+        &self,
+        pool: &PgPool,
+        user: Option<User>,
+        username: &str,
+        password: &str,
+    ) -> AppResult<Option<User>>;
+}
+
+pub type SharedAuthProvider = Arc<dyn AuthProvider>;
+
+/// Builds the provider selected by `AUTH_PROVIDER` (`database` or `ldap`, defaulting to
+/// `database`).
+pub fn from_env() -> SharedAuthProvider {
+    match std::env::var("AUTH_PROVIDER").as_deref() {
+        Ok("database") | Err(_) => Arc::new(DatabaseProvider),
+        Ok("ldap") => Arc::new(LdapProvider::new(Arc::new(LdapDirectory::from_env()))),
+        Ok(other) => panic!("AUTH_PROVIDER must be `database` or `ldap`, got `{other}`"),
+    }
+}
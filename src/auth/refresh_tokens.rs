@@ -0,0 +1,183 @@
+use crate::{
+    auth::{
+        client_info::ClientInfo,
+        jwt::{generate_access_token, generate_refresh_token, hash_token},
+        keys::JwtKeys,
+        revocation::RevocationStore,
+        roles::Role,
+        scopes::{Scope, effective_scopes},
+    },
+    error::{AppError, AppResult},
+};
+use sqlx::{PgConnection, PgPool};
+use uuid::Uuid;
+
+pub const REFRESH_TOKEN_TTL_DAYS: i64 = 7;
+
+/// Starts a new token family (a fresh login) for `user_id` and returns the plaintext token.
+///
+/// `scopes` is the restriction requested at login; `None` grants whatever the user's role allows.
+/// `access_jti` identifies the access token issued alongside it.
+pub async fn issue_refresh_token(
+    conn: &mut PgConnection,
+    user_id: i32,
+    scopes: Option<&[Scope]>,
+    access_jti: Uuid,
+    client: &ClientInfo,
+) -> AppResult<String> {
+    insert_refresh_token(
+        conn,
+        user_id,
+        Uuid::new_v4(),
+        scopes,
+        None,
+        access_jti,
+        client,
+    )
+    .await
+}
+
+/// Stores a new refresh token in `family_id` and returns its plaintext for the client.
+///
+/// Every token produced by rotating a login shares that login's family, so replaying any
+/// consumed member can revoke the whole chain. `oauth_client_id` is set when the family was
+/// granted to a third-party application rather than started by a first-party login.
+/// `access_jti` is recorded so ending the session can also revoke that access token.
+pub async fn insert_refresh_token(
+    conn: &mut PgConnection,
+    user_id: i32,
+    family_id: Uuid,
+    scopes: Option<&[Scope]>,
+    oauth_client_id: Option<Uuid>,
+    access_jti: Uuid,
+    client: &ClientInfo,
+) -> AppResult<String> {
+    let (refresh_plaintext, refresh_hash) = generate_refresh_token();
+    let expires_at =
+        chrono::Utc::now().naive_utc() + chrono::Duration::days(REFRESH_TOKEN_TTL_DAYS);
+
+    sqlx::query!(
+        "INSERT INTO refresh_tokens (user_id, token_hash, expires_at, family_id, user_agent, ip_address, scopes, oauth_client_id, access_jti)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+        user_id,
+        refresh_hash,
+        expires_at,
+        family_id,
+        client.user_agent,
+        client.ip_address,
+        scopes as Option<&[Scope]>,
+        oauth_client_id,
+        access_jti,
+    )
+    .execute(conn)
+    .await?;
+
+    Ok(refresh_plaintext)
+}
+
+/// The token pair that replaces a redeemed refresh token.
+pub struct RotatedRefreshToken {
+    pub user_id: i32,
+    /// Re-derived from the user's current role, so a demotion also narrows existing sessions.
+    pub scopes: Vec<Scope>,
+    pub access_token: String,
+    pub refresh_token: String,
+}
+
+/// Consumes `refresh_token` and issues a new access token and its successor in the same family.
+///
+/// `oauth_client_id` must name the client the family was granted to, or be `None` for a
+/// first-party login, so a token can only be redeemed where it was issued.
+pub async fn rotate_refresh_token(
+    pool: &PgPool,
+    revocations: &RevocationStore,
+    keys: &JwtKeys,
+    refresh_token: &str,
+    oauth_client_id: Option<Uuid>,
+    client: &ClientInfo,
+) -> AppResult<RotatedRefreshToken> {
+    let invalid_token = || {
+        AppError::Unauthorized("Invalid or expired refresh token, please log in again".to_string())
+    };
+    let now = chrono::Utc::now().naive_utc();
+
+    let mut tx = pool.begin().await?;
+
+    // Lock the row so two concurrent refreshes with the same token can't both rotate it.
+    let record = sqlx::query!(
+        r#"SELECT rt.id, rt.user_id, rt.family_id, rt.used_at, rt.expires_at, rt.oauth_client_id,
+            rt.scopes AS "scopes: Vec<Scope>", u.role AS "role: Role"
+        FROM refresh_tokens rt JOIN users u ON u.id = rt.user_id
+        WHERE rt.token_hash = $1
+        FOR UPDATE OF rt"#,
+        hash_token(refresh_token),
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(invalid_token)?;
+
+    if record.oauth_client_id != oauth_client_id {
+        return Err(invalid_token());
+    }
+
+    if record.used_at.is_some() {
+        // A consumed token only comes back if someone kept a copy: treat the family as stolen.
+        let revoked = revocations.revoke_family(&mut tx, record.family_id).await?;
+        sqlx::query!(
+            "DELETE FROM refresh_tokens WHERE family_id = $1",
+            record.family_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        revocations.apply(revoked);
+
+        tracing::warn!(
+            target: "security",
+            user_id = record.user_id,
+            family_id = %record.family_id,
+            "refresh token reuse detected, revoked token family"
+        );
+        return Err(invalid_token());
+    }
+
+    if record.expires_at <= now {
+        return Err(invalid_token());
+    }
+
+    sqlx::query!(
+        "UPDATE refresh_tokens SET used_at = $1 WHERE id = $2",
+        now,
+        record.id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    let scopes = effective_scopes(record.scopes.as_deref(), record.role);
+    let access = generate_access_token(
+        record.user_id,
+        record.role,
+        &scopes,
+        record.oauth_client_id,
+        keys,
+    )?;
+    let refresh_plaintext = insert_refresh_token(
+        &mut tx,
+        record.user_id,
+        record.family_id,
+        record.scopes.as_deref(),
+        record.oauth_client_id,
+        access.jti,
+        client,
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(RotatedRefreshToken {
+        user_id: record.user_id,
+        scopes,
+        access_token: access.token,
+        refresh_token: refresh_plaintext,
+    })
+}
@@ -0,0 +1,244 @@
+use crate::{auth::jwt::ACCESS_TOKEN_TTL_SECS, error::AppResult};
+use chrono::NaiveDateTime;
+use sqlx::{PgConnection, PgPool};
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc, RwLock,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+/// Access tokens revoked before they expire, checked by `AuthUser` on every request.
+///
+/// Postgres is the source of truth so every instance sees every revocation. Each instance keeps
+/// an in-memory copy, applies its own revocations to it as soon as they commit and reloads the
+/// rest every `sync_interval`. Only entries young enough to affect an unexpired token are kept,
+/// so the copy stays small.
+pub struct RevocationStore {
+    sync_interval: Duration,
+    cache: RwLock<Cache>,
+    created: Instant,
+    /// Milliseconds after `created` of the last reload, or 0 before the first. Checked without
+    /// locking, so requests only wait on each other when a reload is due.
+    last_sync_ms: AtomicU64,
+    /// Held while reloading, so concurrent requests don't all query the database at once.
+    reloading: Mutex<()>,
+}
+
+pub type SharedRevocationStore = Arc<RevocationStore>;
+
+#[derive(Default)]
+struct Cache {
+    /// Revoked token IDs, with when each token expires.
+    denied: HashMap<Uuid, u64>,
+    /// Per-user time up to which every issued token is rejected, in Unix microseconds.
+    watermarks: HashMap<i32, u64>,
+}
+
+/// Revocations written in a transaction. Hand them to [`RevocationStore::apply`] once it commits
+/// so this instance enforces them straight away; dropped ones wait for the next reload.
+#[must_use = "apply the revocations once the transaction commits"]
+#[derive(Default)]
+pub struct Revoked {
+    denied: Vec<(Uuid, u64)>,
+    watermarks: Vec<(i32, u64)>,
+}
+
+impl Revoked {
+    pub fn merge(&mut self, other: Revoked) {
+        self.denied.extend(other.denied);
+        self.watermarks.extend(other.watermarks);
+    }
+}
+
+impl RevocationStore {
+    pub fn new(sync_interval: Duration) -> Self {
+        RevocationStore {
+            sync_interval,
+            cache: RwLock::default(),
+            created: Instant::now(),
+            last_sync_ms: AtomicU64::new(0),
+            reloading: Mutex::new(()),
+        }
+    }
+
+    /// Reads `TOKEN_REVOCATION_SYNC_SECONDS` (default 5): how stale another instance's view of
+    /// a revocation may be.
+    pub fn from_env() -> Self {
+        let secs = std::env::var("TOKEN_REVOCATION_SYNC_SECONDS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(5);
+        RevocationStore::new(Duration::from_secs(secs))
+    }
+
+    /// Whether a token with `jti`, issued to `user_id` at `issued_at_us` (Unix microseconds),
+    /// has been revoked.
+    pub async fn is_revoked(
+        &self,
+        pool: &PgPool,
+        jti: Uuid,
+        user_id: i32,
+        issued_at_us: u64,
+    ) -> AppResult<bool> {
+        self.sync(pool).await?;
+
+        let cache = self.cache.read().unwrap();
+        let before_watermark = cache
+            .watermarks
+            .get(&user_id)
+            .is_some_and(|&watermark| issued_at_us <= watermark);
+        Ok(before_watermark || cache.denied.contains_key(&jti))
+    }
+
+    /// Adds revocations from a committed transaction to the in-memory copy.
+    pub fn apply(&self, revoked: Revoked) {
+        let mut cache = self.cache.write().unwrap();
+        for (jti, expires) in revoked.denied {
+            cache.denied.insert(jti, expires);
+        }
+        for (user_id, watermark) in revoked.watermarks {
+            let entry = cache.watermarks.entry(user_id).or_default();
+            *entry = (*entry).max(watermark);
+        }
+    }
+
+    /// Revokes the access tokens issued alongside any refresh token in `family_id`, i.e. ends a
+    /// session's access as well as its ability to refresh. Call before deleting the family.
+    pub async fn revoke_family(
+        &self,
+        conn: &mut PgConnection,
+        family_id: Uuid,
+    ) -> AppResult<Revoked> {
+        let now = chrono::Utc::now().naive_utc();
+        let revoked = sqlx::query!(
+            r#"INSERT INTO revoked_access_tokens (jti, expires_at)
+            SELECT access_jti, created_at + make_interval(secs => $2)
+            FROM refresh_tokens
+            WHERE family_id = $1 AND access_jti IS NOT NULL
+                AND created_at + make_interval(secs => $2) > $3
+            ON CONFLICT DO NOTHING
+            RETURNING jti, expires_at"#,
+            family_id,
+            ACCESS_TOKEN_TTL_SECS as f64,
+            now,
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+
+        self.prune(conn, now).await?;
+        Ok(Revoked {
+            denied: revoked
+                .into_iter()
+                .map(|row| (row.jti, unix_secs(row.expires_at)))
+                .collect(),
+            watermarks: Vec::new(),
+        })
+    }
+
+    /// Rejects every access token issued to `user_id` until now, e.g. on logging out
+    /// everywhere, changing the password or deleting the account.
+    pub async fn revoke_all_for_user(
+        &self,
+        conn: &mut PgConnection,
+        user_id: i32,
+    ) -> AppResult<Revoked> {
+        let now = chrono::Utc::now().naive_utc();
+        sqlx::query!(
+            "INSERT INTO access_token_watermarks (user_id, issued_before) VALUES ($1, $2)
+            ON CONFLICT (user_id) DO UPDATE SET issued_before = EXCLUDED.issued_before",
+            user_id,
+            now,
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        self.prune(conn, now).await?;
+        Ok(Revoked {
+            denied: Vec::new(),
+            watermarks: vec![(user_id, unix_micros(now))],
+        })
+    }
+
+    /// Drops rows for tokens that have expired anyway.
+    async fn prune(&self, conn: &mut PgConnection, now: NaiveDateTime) -> AppResult<()> {
+        sqlx::query!(
+            "DELETE FROM revoked_access_tokens WHERE expires_at <= $1",
+            now
+        )
+        .execute(conn)
+        .await?;
+        Ok(())
+    }
+
+    fn sync_due(&self) -> bool {
+        let last_sync_ms = self.last_sync_ms.load(Ordering::Acquire);
+        last_sync_ms == 0
+            || self.created.elapsed().as_millis() as u64
+                >= last_sync_ms + self.sync_interval.as_millis() as u64
+    }
+
+    /// Reloads entries other instances added, once `sync_interval` has passed. Entries already
+    /// in memory are kept until they can no longer matter, so a reload never un-revokes a token.
+    async fn sync(&self, pool: &PgPool) -> AppResult<()> {
+        if !self.sync_due() {
+            return Ok(());
+        }
+        let _reloading = self.reloading.lock().await;
+        // Another request may have reloaded while this one waited.
+        if !self.sync_due() {
+            return Ok(());
+        }
+
+        let now = chrono::Utc::now().naive_utc();
+        let oldest_live_token = now - chrono::Duration::seconds(ACCESS_TOKEN_TTL_SECS as i64);
+        let denied = sqlx::query!(
+            "SELECT jti, expires_at FROM revoked_access_tokens WHERE expires_at > $1",
+            now
+        )
+        .fetch_all(pool)
+        .await?;
+        let watermarks = sqlx::query!(
+            "SELECT user_id, issued_before FROM access_token_watermarks WHERE issued_before > $1",
+            oldest_live_token
+        )
+        .fetch_all(pool)
+        .await?;
+
+        let now_secs = unix_secs(now);
+        let oldest_live_us = unix_micros(oldest_live_token);
+        let mut cache = self.cache.write().unwrap();
+        cache.denied.retain(|_, &mut expires| expires > now_secs);
+        cache
+            .watermarks
+            .retain(|_, &mut watermark| watermark > oldest_live_us);
+        for row in denied {
+            cache.denied.insert(row.jti, unix_secs(row.expires_at));
+        }
+        for row in watermarks {
+            let watermark = unix_micros(row.issued_before);
+            let entry = cache.watermarks.entry(row.user_id).or_default();
+            *entry = (*entry).max(watermark);
+        }
+
+        drop(cache);
+
+        // Never 0, which means "not yet".
+        let elapsed_ms = self.created.elapsed().as_millis() as u64;
+        self.last_sync_ms
+            .store(elapsed_ms.max(1), Ordering::Release);
+        Ok(())
+    }
+}
+
+fn unix_secs(time: NaiveDateTime) -> u64 {
+    time.and_utc().timestamp().max(0) as u64
+}
+
+fn unix_micros(time: NaiveDateTime) -> u64 {
+    time.and_utc().timestamp_micros().max(0) as u64
+}
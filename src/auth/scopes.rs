@@ -0,0 +1,259 @@
+use crate::{
+    auth::{jwt::AuthUser, roles::Role},
+    error::{AppError, AppResult},
+};
+use axum::{extract::FromRequestParts, http::request::Parts};
+use serde::{Deserialize, Serialize};
+use std::{fmt, marker::PhantomData, str::FromStr};
+
+/// A permission a credential may be limited to, e.g. `posts:read`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "text")]
+pub enum Scope {
+    #[serde(rename = "posts:read")]
+    #[sqlx(rename = "posts:read")]
+    PostsRead,
+    #[serde(rename = "posts:write")]
+    #[sqlx(rename = "posts:write")]
+    PostsWrite,
+    #[serde(rename = "users:read")]
+    #[sqlx(rename = "users:read")]
+    UsersRead,
+    #[serde(rename = "users:write")]
+    #[sqlx(rename = "users:write")]
+    UsersWrite,
+    #[serde(rename = "users:admin")]
+    #[sqlx(rename = "users:admin")]
+    UsersAdmin,
+}
+
+impl Scope {
+    pub const ALL: [Scope; 5] = [
+        Scope::PostsRead,
+        Scope::PostsWrite,
+        Scope::UsersRead,
+        Scope::UsersWrite,
+        Scope::UsersAdmin,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Scope::PostsRead => "posts:read",
+            Scope::PostsWrite => "posts:write",
+            Scope::UsersRead => "users:read",
+            Scope::UsersWrite => "users:write",
+            Scope::UsersAdmin => "users:admin",
+        }
+    }
+
+    /// Whether a user with `role` may hold this scope at all.
+    pub fn allowed_for(self, role: Role) -> bool {
+        match self {
+            Scope::UsersAdmin => role >= Role::Admin,
+            _ => true,
+        }
+    }
+
+    /// Every scope a user with `role` may hold.
+    pub fn all_for(role: Role) -> Vec<Scope> {
+        Scope::ALL
+            .into_iter()
+            .filter(|scope| scope.allowed_for(role))
+            .collect()
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Scope {
+    type Err = AppError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Scope::ALL
+            .into_iter()
+            .find(|scope| scope.as_str() == s)
+            .ok_or_else(|| AppError::Validation(format!("Unknown scope '{s}'")))
+    }
+}
+
+/// Parses a space-delimited `scope` string, e.g. `"posts:read posts:write"`.
+pub fn parse_scope(scope: &str) -> AppResult<Vec<Scope>> {
+    let mut scopes = Vec::new();
+    for name in scope.split_whitespace() {
+        let scope = name.parse()?;
+        if !scopes.contains(&scope) {
+            scopes.push(scope);
+        }
+    }
+    Ok(scopes)
+}
+
+pub fn format_scope(scopes: &[Scope]) -> String {
+    scopes
+        .iter()
+        .map(|scope| scope.as_str())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Validates the scope a client asked for at login. `None` means no restriction was requested.
+pub fn resolve_requested_scope(
+    requested: Option<&str>,
+    role: Role,
+) -> AppResult<Option<Vec<Scope>>> {
+    let Some(requested) = requested else {
+        return Ok(None);
+    };
+
+    let scopes = parse_scope(requested)?;
+    if scopes.is_empty() {
+        return Err(AppError::Validation(
+            "scope must name at least one scope".to_string(),
+        ));
+    }
+
+    if let Some(scope) = scopes.iter().find(|scope| !scope.allowed_for(role)) {
+        return Err(AppError::Forbidden(format!(
+            "You can't request the '{scope}' scope"
+        )));
+    }
+
+    Ok(Some(scopes))
+}
+
+/// The scopes a session grants right now: its restriction if it has one, everything otherwise,
+/// and never more than `role` currently allows.
+pub fn effective_scopes(restriction: Option<&[Scope]>, role: Role) -> Vec<Scope> {
+    match restriction {
+        Some(scopes) => scopes
+            .iter()
+            .copied()
+            .filter(|scope| scope.allowed_for(role))
+            .collect(),
+        None => Scope::all_for(role),
+    }
+}
+
+pub trait RequiredScope {
+    const SCOPE: Scope;
+}
+
+pub struct PostsRead;
+pub struct PostsWrite;
+pub struct UsersRead;
+pub struct UsersWrite;
+pub struct UsersAdmin;
+
+impl RequiredScope for PostsRead {
+    const SCOPE: Scope = Scope::PostsRead;
+}
+
+impl RequiredScope for PostsWrite {
+    const SCOPE: Scope = Scope::PostsWrite;
+}
+
+impl RequiredScope for UsersRead {
+    const SCOPE: Scope = Scope::UsersRead;
+}
+
+impl RequiredScope for UsersWrite {
+    const SCOPE: Scope = Scope::UsersWrite;
+}
+
+impl RequiredScope for UsersAdmin {
+    const SCOPE: Scope = Scope::UsersAdmin;
+}
+
+/// Extractor that authenticates the caller and rejects them with 403 unless their credential
+/// grants `S`.
+pub struct RequireScope<S: RequiredScope> {
+    pub user: AuthUser,
+    _scope: PhantomData<S>,
+}
+
+impl<St, S> FromRequestParts<St> for RequireScope<S>
+where
+    St: Send + Sync,
+    S: RequiredScope,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &St) -> Result<Self, Self::Rejection> {
+        let user = AuthUser::from_request_parts(parts, state).await?;
+
+        if !user.has_scope(S::SCOPE) {
+            return Err(AppError::Forbidden(format!(
+                "This credential is missing the required scope '{}'",
+                S::SCOPE
+            )));
+        }
+
+        Ok(RequireScope {
+            user,
+            _scope: PhantomData,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scope_serializes_as_resource_action() {
+        assert_eq!(
+            serde_json::to_string(&Scope::PostsWrite).unwrap(),
+            "\"posts:write\""
+        );
+        assert_eq!(
+            serde_json::from_str::<Scope>("\"users:read\"").unwrap(),
+            Scope::UsersRead
+        );
+        assert!(serde_json::from_str::<Scope>("\"posts:delete\"").is_err());
+    }
+
+    #[test]
+    fn scope_strings_round_trip() {
+        let scopes = parse_scope("posts:read  posts:write posts:read").unwrap();
+        assert_eq!(scopes, vec![Scope::PostsRead, Scope::PostsWrite]);
+        assert_eq!(format_scope(&scopes), "posts:read posts:write");
+        assert!(parse_scope("posts:read posts:delete").is_err());
+    }
+
+    #[test]
+    fn requested_scope_is_checked_against_role() {
+        assert_eq!(resolve_requested_scope(None, Role::User).unwrap(), None);
+        assert_eq!(
+            resolve_requested_scope(Some("users:read"), Role::User).unwrap(),
+            Some(vec![Scope::UsersRead])
+        );
+        assert!(matches!(
+            resolve_requested_scope(Some("users:admin"), Role::User),
+            Err(AppError::Forbidden(_))
+        ));
+        assert!(matches!(
+            resolve_requested_scope(Some(" "), Role::User),
+            Err(AppError::Validation(_))
+        ));
+    }
+
+    #[test]
+    fn effective_scopes_follow_current_role() {
+        let restriction = [Scope::PostsRead, Scope::UsersAdmin];
+        assert_eq!(
+            effective_scopes(Some(&restriction), Role::User),
+            vec![Scope::PostsRead]
+        );
+        assert_eq!(effective_scopes(None, Role::Admin), Scope::ALL.to_vec());
+    }
+
+    #[test]
+    fn admin_scope_requires_admin_role() {
+        assert!(!Scope::all_for(Role::User).contains(&Scope::UsersAdmin));
+        assert_eq!(Scope::all_for(Role::Admin).len(), Scope::ALL.len());
+    }
+}
@@ -0,0 +1,120 @@
+use crate::error::AppError;
+use rand::RngCore;
+use totp_rs::{Algorithm, Secret, TOTP};
+
+const DIGITS: usize = 6;
+const STEP_SECONDS: u64 = 30;
+/// Codes from one step either side of now are accepted to tolerate clock drift.
+const SKEW_STEPS: i64 = 1;
+pub const RECOVERY_CODE_COUNT: usize = 10;
+
+fn issuer() -> String {
+    std::env::var("TOTP_ISSUER").unwrap_or_else(|_| "rust-axum-rest-api".to_string())
+}
+
+fn build(secret_base32: &str, account_name: &str) -> Result<TOTP, AppError> {
+    let secret = Secret::Encoded(secret_base32.to_string())
+        .to_bytes()
+        .map_err(|e| AppError::Internal(format!("invalid TOTP secret: {e:?}")))?;
+
+    TOTP::new(
+        Algorithm::SHA1,
+        DIGITS,
+        SKEW_STEPS as u8,
+        STEP_SECONDS,
+        secret,
+        Some(issuer()),
+        account_name.to_string(),
+    )
+    .map_err(|e| AppError::Internal(format!("invalid TOTP parameters: {e}")))
+}
+
+/// Generates a new random base32-encoded TOTP secret.
+pub fn generate_secret() -> String {
+    Secret::generate_secret().to_encoded().to_string()
+}
+
+/// The `otpauth://` URI authenticator apps scan to enroll the secret.
+pub fn provisioning_uri(secret_base32: &str, account_name: &str) -> Result<String, AppError> {
+    Ok(build(secret_base32, account_name)?.get_url())
+}
+
+/// Checks `code` against the steps around `now` and returns the matching time step, which callers
+/// persist so the same code can't be replayed.
+pub fn verify_code(secret_base32: &str, code: &str, now: u64) -> Result<Option<i64>, AppError> {
+    let totp = build(secret_base32, "verify")?;
+    let current_step = (now / STEP_SECONDS) as i64;
+    let code = code.trim();
+
+    Ok((-SKEW_STEPS..=SKEW_STEPS)
+        .map(|offset| current_step + offset)
+        .find(|step| totp.generate(*step as u64 * STEP_SECONDS) == code))
+}
+
+/// Generates single-use recovery codes such as `3f9a1-c07be`.
+pub fn generate_recovery_codes() -> Vec<String> {
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let mut bytes = [0u8; 5];
+            rand::thread_rng().fill_bytes(&mut bytes);
+            let hex: String = bytes.iter().map(|b| format!("{b:02x}")).collect();
+            format!("{}-{}", &hex[..5], &hex[5..])
+        })
+        .collect()
+}
+
+/// Recovery codes are compared case-insensitively and without surrounding whitespace.
+pub fn normalize_recovery_code(code: &str) -> String {
+    code.trim().to_ascii_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_700_000_000;
+
+    fn code_at(secret: &str, time: u64) -> String {
+        build(secret, "test").unwrap().generate(time)
+    }
+
+    #[test]
+    fn accepts_current_and_adjacent_codes() {
+        let secret = generate_secret();
+        let step = (NOW / STEP_SECONDS) as i64;
+
+        let current = code_at(&secret, NOW);
+        assert_eq!(verify_code(&secret, &current, NOW).unwrap(), Some(step));
+
+        let previous = code_at(&secret, NOW - STEP_SECONDS);
+        assert_eq!(
+            verify_code(&secret, &previous, NOW).unwrap(),
+            Some(step - 1)
+        );
+    }
+
+    #[test]
+    fn rejects_stale_codes() {
+        let secret = generate_secret();
+        let stale = code_at(&secret, NOW - 5 * STEP_SECONDS);
+        assert_eq!(verify_code(&secret, &stale, NOW).unwrap(), None);
+    }
+
+    #[test]
+    fn provisioning_uri_contains_issuer_and_secret() {
+        let secret = generate_secret();
+        let uri = provisioning_uri(&secret, "alice").unwrap();
+        assert!(uri.starts_with("otpauth://totp/"));
+        assert!(uri.contains(&format!("secret={secret}")));
+        assert!(uri.contains("alice"));
+    }
+
+    #[test]
+    fn recovery_codes_are_unique() {
+        let codes = generate_recovery_codes();
+        assert_eq!(codes.len(), RECOVERY_CODE_COUNT);
+        let unique: std::collections::HashSet<_> = codes.iter().collect();
+        assert_eq!(unique.len(), RECOVERY_CODE_COUNT);
+        assert_eq!(normalize_recovery_code(" AB12C-DEF34 "), "ab12c-def34");
+    }
+}
@@ -0,0 +1,244 @@
+use crate::models::{ErrorResponse, FieldError};
+use axum::{
+    Json,
+    http::{HeaderValue, StatusCode, header::RETRY_AFTER},
+    response::{IntoResponse, Response},
+};
+use std::fmt;
+
+#[derive(Debug)]
+pub enum AppError {
+    NotFound(String),
+    Unauthorized(String),
+    Forbidden(String),
+    Conflict(String),
+    Validation(String),
+    /// A 422 that lists each invalid field separately.
+    InvalidFields(Vec<FieldError>),
+    TooManyRequests {
+        message: String,
+        retry_after_secs: u64,
+    },
+    Database(sqlx::Error),
+    Internal(String),
+}
+
+pub type AppResult<T> = Result<T, AppError>;
+
+impl AppError {
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::Conflict(_) => StatusCode::CONFLICT,
+            AppError::Validation(_) | AppError::InvalidFields(_) => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            }
+            AppError::TooManyRequests { .. } => StatusCode::TOO_MANY_REQUESTS,
+            AppError::Database(_) | AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn title(&self) -> &'static str {
+        match self {
+            AppError::NotFound(_) => "Not found",
+            AppError::Unauthorized(_) => "Unauthorized",
+            AppError::Forbidden(_) => "Forbidden",
+            AppError::Conflict(_) => "Conflict",
+            AppError::Validation(_) | AppError::InvalidFields(_) => "Validation failed",
+            AppError::TooManyRequests { .. } => "Too many requests",
+            AppError::Database(_) | AppError::Internal(_) => "Internal server error",
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::Database(e) => write!(f, "{}: {e}", self.title()),
+            AppError::InvalidFields(errors) => {
+                let messages: Vec<&str> = errors.iter().map(|e| e.message.as_str()).collect();
+                write!(f, "{}: {}", self.title(), messages.join("; "))
+            }
+            AppError::NotFound(msg)
+            | AppError::Unauthorized(msg)
+            | AppError::Forbidden(msg)
+            | AppError::Conflict(msg)
+            | AppError::Validation(msg)
+            | AppError::TooManyRequests { message: msg, .. }
+            | AppError::Internal(msg) => write!(f, "{}: {msg}", self.title()),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let status = self.status_code();
+        let retry_after = match self {
+            AppError::TooManyRequests {
+                retry_after_secs, ..
+            } => Some(retry_after_secs),
+            _ => None,
+        };
+        let details = match self {
+            AppError::InvalidFields(ref errors) => Some(errors.clone()),
+            _ => None,
+        };
+        let message = match self {
+            AppError::InvalidFields(_) => "One or more fields are invalid".to_string(),
+            // Internal failures are logged, never echoed back to the client.
+            AppError::Database(ref e) => {
+                tracing::error!(error = %e, "database error");
+                "Something went wrong, please try again later".to_string()
+            }
+            AppError::Internal(ref msg) => {
+                tracing::error!(error = %msg, "internal error");
+                "Something went wrong, please try again later".to_string()
+            }
+            AppError::NotFound(ref msg)
+            | AppError::Unauthorized(ref msg)
+            | AppError::Forbidden(ref msg)
+            | AppError::Conflict(ref msg)
+            | AppError::Validation(ref msg)
+            | AppError::TooManyRequests {
+                message: ref msg, ..
+            } => msg.clone(),
+        };
+
+        let body = ErrorResponse {
+            error: self.title().to_string(),
+            message,
+            details,
+        };
+
+        let mut response = (status, Json(body)).into_response();
+        if let Some(secs) = retry_after {
+            response
+                .headers_mut()
+                .insert(RETRY_AFTER, HeaderValue::from(secs));
+        }
+        response
+    }
+}
+
+impl From<sqlx::Error> for AppError {
+    fn from(e: sqlx::Error) -> Self {
+        match e {
+            sqlx::Error::Database(ref db) if db.is_unique_violation() => {
+                AppError::Conflict(conflict_message(db.constraint()).to_string())
+            }
+            _ => AppError::Database(e),
+        }
+    }
+}
+
+impl From<bcrypt::BcryptError> for AppError {
+    fn from(e: bcrypt::BcryptError) -> Self {
+        AppError::Internal(format!("password hashing failed: {e}"))
+    }
+}
+
+impl From<jsonwebtoken::errors::Error> for AppError {
+    fn from(e: jsonwebtoken::errors::Error) -> Self {
+        AppError::Internal(format!("token encoding failed: {e}"))
+    }
+}
+
+impl From<reqwest::Error> for AppError {
+    fn from(e: reqwest::Error) -> Self {
+        AppError::Internal(format!("identity provider request failed: {e}"))
+    }
+}
+
+fn conflict_message(constraint: Option<&str>) -> &'static str {
+    match constraint {
+        Some("users_username_key") => "Username is already taken",
+        Some("users_email_key") => "Email is already registered",
+        _ => "Resource already exists",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_codes_match_variants() {
+        assert_eq!(
+            AppError::NotFound("x".into()).status_code(),
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            AppError::Unauthorized("x".into()).status_code(),
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            AppError::Forbidden("x".into()).status_code(),
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            AppError::Conflict("x".into()).status_code(),
+            StatusCode::CONFLICT
+        );
+        assert_eq!(
+            AppError::Validation("x".into()).status_code(),
+            StatusCode::UNPROCESSABLE_ENTITY
+        );
+        assert_eq!(
+            AppError::TooManyRequests {
+                message: "x".into(),
+                retry_after_secs: 1
+            }
+            .status_code(),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+        assert_eq!(
+            AppError::Internal("x".into()).status_code(),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+
+    #[test]
+    fn too_many_requests_sets_retry_after() {
+        let response = AppError::TooManyRequests {
+            message: "slow down".into(),
+            retry_after_secs: 42,
+        }
+        .into_response();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[RETRY_AFTER], "42");
+    }
+
+    #[test]
+    fn invalid_fields_is_unprocessable() {
+        let err = AppError::InvalidFields(vec![FieldError {
+            field: "password".into(),
+            code: "too_short".into(),
+            message: "Password is too short".into(),
+        }]);
+        assert_eq!(err.status_code(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(err.to_string(), "Validation failed: Password is too short");
+    }
+
+    #[test]
+    fn non_unique_sqlx_errors_map_to_database() {
+        let err: AppError = sqlx::Error::RowNotFound.into();
+        assert!(matches!(err, AppError::Database(_)));
+    }
+
+    #[test]
+    fn conflict_message_names_known_constraints() {
+        assert_eq!(
+            conflict_message(Some("users_username_key")),
+            "Username is already taken"
+        );
+        assert_eq!(
+            conflict_message(Some("users_email_key")),
+            "Email is already registered"
+        );
+        assert_eq!(conflict_message(None), "Resource already exists");
+    }
+}
@@ -0,0 +1,22 @@
+use crate::{
+    auth::{account_deletion::redeem_restore_token, client_info::ClientInfo},
+    error::{AppError, AppResult},
+    models::{SuccessResponse, users::RestoreAccountRequest},
+};
+use axum::{Json, extract::Extension};
+use sqlx::PgPool;
+
+/// Undoes a user's own deletion with the link emailed when they deleted their account.
+pub async fn restore_account(
+    Extension(pool): Extension<PgPool>,
+    client: ClientInfo,
+    Json(body): Json<RestoreAccountRequest>,
+) -> AppResult<Json<SuccessResponse>> {
+    redeem_restore_token(&pool, &body.token, &client)
+        .await?
+        .ok_or_else(|| AppError::Unauthorized("Invalid or expired restore link".to_string()))?;
+
+    Ok(Json(SuccessResponse {
+        message: "Your account has been restored; sign in to continue".to_string(),
+    }))
+}
@@ -0,0 +1,345 @@
+use crate::{
+    FrontendOrigin,
+    auth::{
+        account_deletion::{SharedDeletionPolicy, deactivate_account, reactivate_account},
+        audit::{AuthEvent, AuthEventKind, AuthEventOutcome, changed_fields},
+        client_info::ClientInfo,
+        jwt::{IMPERSONATION_TOKEN_TTL_SECS, generate_impersonation_token},
+        keys::SharedJwtKeys,
+        revocation::{Revoked, SharedRevocationStore},
+        roles::Role,
+        scopes::{RequireScope, Scope, UsersAdmin, format_scope},
+    },
+    error::{AppError, AppResult},
+    handlers::email_verification::send_verification_email,
+    mail::SharedMailer,
+    models::{
+        SuccessResponse,
+        auth_events::{AuthEventQuery, AuthEventRecord},
+        login_attempts::LoginAttempt,
+        pagination::{Cursor, MAX_PAGE_LIMIT, Page, PageParams},
+        users::{AdminUpdateUser, ImpersonationResponse, User},
+    },
+};
+use axum::{
+    Json,
+    extract::{Extension, Path, Query},
+};
+use sqlx::PgPool;
+
+pub async fn list_users(
+    _admin: RequireScope<UsersAdmin>,
+    Extension(pool): Extension<PgPool>,
+    Query(params): Query<PageParams>,
+) -> AppResult<Json<Page<User>>> {
+    let (cursor, limit) = params.resolve()?;
+
+    let users = sqlx::query_as!(
+        User,
+        r#"SELECT id, username, email, created_at, password_hash,
+            role AS "role: Role", email_verified_at, totp_enabled_at
+        FROM users
+        WHERE deleted_at IS NULL AND ($1::timestamp IS NULL OR (created_at, id) < ($1, $2::int))
+        ORDER BY created_at DESC, id DESC
+        LIMIT $3"#,
+        cursor.map(|c| c.created_at),
+        cursor.map(|c| c.id),
+        limit + 1
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    Ok(Json(Page::from_rows(users, limit, |user| Cursor {
+        created_at: user.created_at,
+        id: user.id,
+    })))
+}
+
+pub async fn update_user(
+    RequireScope { user: admin, .. }: RequireScope<UsersAdmin>,
+    Extension(pool): Extension<PgPool>,
+    Extension(revocations): Extension<SharedRevocationStore>,
+    (Extension(mailer), Extension(FrontendOrigin(frontend_origin))): (
+        Extension<SharedMailer>,
+        Extension<FrontendOrigin>,
+    ),
+    client: ClientInfo,
+    Path(id): Path<i32>,
+    Json(user): Json<AdminUpdateUser>,
+) -> AppResult<Json<User>> {
+    let changed = changed_fields(&[
+        ("username", user.username.is_some()),
+        ("email", user.email.is_some()),
+        ("role", user.role.is_some()),
+    ]);
+
+    let mut tx = pool.begin().await?;
+
+    // As when users change it themselves, a new address has to be verified by its owner.
+    let row = sqlx::query!(
+        r#"UPDATE users u
+        SET username = COALESCE($1, u.username), email = COALESCE($2, u.email),
+            role = COALESCE($3, u.role),
+            email_verified_at = CASE WHEN $2 <> old.email
+                THEN NULL ELSE u.email_verified_at END
+        FROM (SELECT id, email, role FROM users WHERE id = $4 AND deleted_at IS NULL FOR UPDATE) old
+        WHERE u.id = old.id
+        RETURNING u.id, u.username, u.email, u.created_at, u.password_hash,
+            u.role AS "role: Role", u.email_verified_at, u.totp_enabled_at,
+            u.email <> old.email AS "email_changed!", u.role <> old.role AS "role_changed!""#,
+        user.username,
+        user.email,
+        user.role as Option<Role>,
+        id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| AppError::NotFound(format!("User with id {id} not found")))?;
+
+    if row.email_changed {
+        sqlx::query!(
+            "DELETE FROM email_verification_tokens WHERE user_id = $1",
+            id
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+    // Access tokens carry the role, so outstanding ones would keep the old one until they
+    // expire. Refreshing reads the role afresh.
+    let revoked = if row.role_changed {
+        revocations.revoke_all_for_user(&mut tx, id).await?
+    } else {
+        Revoked::default()
+    };
+    AuthEvent::success(AuthEventKind::AccountUpdated, id)
+        .with_detail(&changed)
+        .with_actor(Some(admin.user_id))
+        .record(&mut *tx, &client)
+        .await?;
+
+    tx.commit().await?;
+    revocations.apply(revoked);
+
+    if row.email_changed
+        && let Err(e) =
+            send_verification_email(&pool, &mailer, &frontend_origin, id, &row.email).await
+    {
+        tracing::warn!(user_id = id, error = %e, "failed to send verification email");
+    }
+
+    Ok(Json(User {
+        id: row.id,
+        username: row.username,
+        email: row.email,
+        created_at: row.created_at,
+        password_hash: Some(row.password_hash),
+        role: row.role,
+        email_verified_at: row.email_verified_at,
+        totp_enabled_at: row.totp_enabled_at,
+    }))
+}
+
+pub async fn delete_user(
+    RequireScope { user: admin, .. }: RequireScope<UsersAdmin>,
+    Extension(pool): Extension<PgPool>,
+    Extension(revocations): Extension<SharedRevocationStore>,
+    Extension(deletion): Extension<SharedDeletionPolicy>,
+    client: ClientInfo,
+    Path(id): Path<i32>,
+) -> AppResult<Json<SuccessResponse>> {
+    let mut tx = pool.begin().await?;
+
+    // No restore link for the user: only an admin can undo this.
+    let account = deactivate_account(&mut tx, &revocations, &deletion, id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("User with id {id} not found")))?;
+    AuthEvent::success(AuthEventKind::AccountDeleted, id)
+        .with_actor(Some(admin.user_id))
+        .record(&mut *tx, &client)
+        .await?;
+
+    tx.commit().await?;
+    revocations.apply(account.revoked);
+
+    Ok(Json(SuccessResponse {
+        message: format!("User with id {id} successfully deleted"),
+    }))
+}
+
+/// Undoes a deletion that hasn't been purged yet.
+pub async fn restore_user(
+    RequireScope { user: admin, .. }: RequireScope<UsersAdmin>,
+    Extension(pool): Extension<PgPool>,
+    client: ClientInfo,
+    Path(id): Path<i32>,
+) -> AppResult<Json<SuccessResponse>> {
+    let mut tx = pool.begin().await?;
+
+    if !reactivate_account(&mut tx, id).await? {
+        return Err(AppError::NotFound(format!(
+            "No deleted user with id {id} to restore"
+        )));
+    }
+    AuthEvent::success(AuthEventKind::AccountRestored, id)
+        .with_actor(Some(admin.user_id))
+        .record(&mut *tx, &client)
+        .await?;
+
+    tx.commit().await?;
+
+    Ok(Json(SuccessResponse {
+        message: format!("User with id {id} restored"),
+    }))
+}
+
+pub async fn list_login_attempts(
+    _admin: RequireScope<UsersAdmin>,
+    Extension(pool): Extension<PgPool>,
+    Path(id): Path<i32>,
+) -> AppResult<Json<Vec<LoginAttempt>>> {
+    let attempts = sqlx::query_as!(
+        LoginAttempt,
+        "SELECT id, ip_address, user_agent, succeeded, created_at FROM login_attempts
+        WHERE user_id = $1
+        ORDER BY created_at DESC, id DESC
+        LIMIT $2",
+        id,
+        MAX_PAGE_LIMIT
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    Ok(Json(attempts))
+}
+
+pub async fn list_auth_events(
+    _admin: RequireScope<UsersAdmin>,
+    Extension(pool): Extension<PgPool>,
+    Query(query): Query<AuthEventQuery>,
+) -> AppResult<Json<Page<AuthEventRecord>>> {
+    let (cursor, limit) = PageParams {
+        cursor: query.cursor,
+        limit: query.limit,
+    }
+    .resolve()?;
+
+    let events = sqlx::query_as!(
+        AuthEventRecord,
+        r#"SELECT id, user_id, event_type AS "event_type: AuthEventKind",
+            outcome AS "outcome: AuthEventOutcome", detail, username, actor_id, ip_address,
+            user_agent, created_at
+        FROM auth_events
+        WHERE ($1::int IS NULL OR user_id = $1)
+            AND ($2::text IS NULL OR event_type = $2)
+            AND ($3::text IS NULL OR outcome = $3)
+            AND ($4::text IS NULL OR ip_address = $4)
+            AND ($5::timestamp IS NULL OR created_at >= $5)
+            AND ($6::timestamp IS NULL OR created_at < $6)
+            AND ($7::timestamp IS NULL OR (created_at, id) < ($7, $8::int))
+        ORDER BY created_at DESC, id DESC
+        LIMIT $9"#,
+        query.user_id,
+        query.event_type as Option<AuthEventKind>,
+        query.outcome as Option<AuthEventOutcome>,
+        query.ip_address,
+        query.since,
+        query.until,
+        cursor.map(|c| c.created_at),
+        cursor.map(|c| c.id),
+        limit + 1
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    Ok(Json(Page::from_rows(events, limit, |event| Cursor {
+        created_at: event.created_at,
+        id: event.id,
+    })))
+}
+
+pub async fn unlock_user(
+    RequireScope { user: admin, .. }: RequireScope<UsersAdmin>,
+    Extension(pool): Extension<PgPool>,
+    Path(id): Path<i32>,
+) -> AppResult<Json<SuccessResponse>> {
+    let result = sqlx::query!(
+        "UPDATE users SET failed_login_count = 0, locked_until = NULL WHERE id = $1",
+        id
+    )
+    .execute(&pool)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound(format!("User with id {id} not found")));
+    }
+
+    tracing::info!(
+        target: "security",
+        admin_id = admin.user_id,
+        user_id = id,
+        "account unlocked by admin"
+    );
+
+    Ok(Json(SuccessResponse {
+        message: format!("User with id {id} successfully unlocked"),
+    }))
+}
+
+pub async fn impersonate_user(
+    RequireScope { user: admin, .. }: RequireScope<UsersAdmin>,
+    Extension(pool): Extension<PgPool>,
+    Extension(keys): Extension<SharedJwtKeys>,
+    client: ClientInfo,
+    Path(id): Path<i32>,
+) -> AppResult<Json<ImpersonationResponse>> {
+    admin.require_session()?;
+    admin.forbid_impersonation()?;
+
+    if id == admin.user_id {
+        return Err(AppError::Validation(
+            "You can't impersonate yourself".to_string(),
+        ));
+    }
+
+    let user = sqlx::query_as!(
+        User,
+        r#"SELECT id, username, email, created_at, password_hash,
+            role AS "role: Role", email_verified_at, totp_enabled_at
+        FROM users WHERE id = $1 AND deleted_at IS NULL"#,
+        id
+    )
+    .fetch_optional(&pool)
+    .await?
+    .ok_or_else(|| AppError::NotFound(format!("User with id {id} not found")))?;
+
+    // Otherwise impersonation would be a way around another admin's second factor.
+    if user.role == Role::Admin {
+        return Err(AppError::Forbidden(
+            "Admins can't be impersonated".to_string(),
+        ));
+    }
+
+    let scopes = Scope::all_for(user.role);
+    let access = generate_impersonation_token(user.id, user.role, &scopes, admin.user_id, &keys)?;
+
+    // No token leaves without its record.
+    AuthEvent::success(AuthEventKind::ImpersonationStarted, user.id)
+        .with_actor(Some(admin.user_id))
+        .record(&pool, &client)
+        .await?;
+
+    tracing::warn!(
+        target: "security",
+        admin_id = admin.user_id,
+        user_id = user.id,
+        jti = %access.jti,
+        "impersonation started"
+    );
+
+    Ok(Json(ImpersonationResponse {
+        access_token: access.token,
+        expires_in: IMPERSONATION_TOKEN_TTL_SECS,
+        scope: format_scope(&scopes),
+        user: user.into(),
+    }))
+}
@@ -0,0 +1,141 @@
+use crate::{
+    auth::{
+        api_keys::generate_api_key,
+        scopes::{RequireScope, Scope, UsersWrite},
+    },
+    error::{AppError, AppResult},
+    models::{
+        SuccessResponse,
+        api_keys::{ApiKey, CreateApiKey, CreatedApiKey},
+    },
+};
+use axum::{
+    Json,
+    extract::{Extension, Path},
+};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+const MAX_API_KEY_NAME_LENGTH: usize = 100;
+const MAX_API_KEY_LIFETIME_DAYS: i64 = 365;
+
+pub async fn create_api_key(
+    RequireScope {
+        user: auth_user, ..
+    }: RequireScope<UsersWrite>,
+    Extension(pool): Extension<PgPool>,
+    Json(body): Json<CreateApiKey>,
+) -> AppResult<Json<CreatedApiKey>> {
+    auth_user.require_session()?;
+    auth_user.forbid_impersonation()?;
+
+    let name = body.name.trim();
+    if name.is_empty() || name.chars().count() > MAX_API_KEY_NAME_LENGTH {
+        return Err(AppError::Validation(format!(
+            "name must be between 1 and {MAX_API_KEY_NAME_LENGTH} characters"
+        )));
+    }
+
+    if body.scopes.is_empty() {
+        return Err(AppError::Validation(
+            "At least one scope is required".to_string(),
+        ));
+    }
+
+    // Checked against the session rather than the role, so a key never outranks the session
+    // that created it.
+    if let Some(scope) = body.scopes.iter().find(|s| !auth_user.has_scope(**s)) {
+        return Err(AppError::Forbidden(format!(
+            "You can't grant the '{scope}' scope"
+        )));
+    }
+
+    let expires_at = match body.expires_in_days {
+        Some(days) if !(1..=MAX_API_KEY_LIFETIME_DAYS).contains(&days) => {
+            return Err(AppError::Validation(format!(
+                "expires_in_days must be between 1 and {MAX_API_KEY_LIFETIME_DAYS}"
+            )));
+        }
+        Some(days) => Some(chrono::Utc::now().naive_utc() + chrono::Duration::days(days)),
+        None => None,
+    };
+
+    let mut scopes: Vec<Scope> = Vec::new();
+    for scope in body.scopes {
+        if !scopes.contains(&scope) {
+            scopes.push(scope);
+        }
+    }
+
+    let (key, prefix, key_hash) = generate_api_key();
+
+    let api_key = sqlx::query_as!(
+        ApiKey,
+        r#"INSERT INTO api_keys (user_id, name, prefix, key_hash, scopes, expires_at)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        RETURNING id, name, prefix, scopes AS "scopes: Vec<Scope>", expires_at, last_used_at,
+            created_at"#,
+        auth_user.user_id,
+        name,
+        prefix,
+        key_hash,
+        &scopes as &[Scope],
+        expires_at,
+    )
+    .fetch_one(&pool)
+    .await?;
+
+    Ok(Json(CreatedApiKey { key, api_key }))
+}
+
+pub async fn list_api_keys(
+    RequireScope {
+        user: auth_user, ..
+    }: RequireScope<UsersWrite>,
+    Extension(pool): Extension<PgPool>,
+) -> AppResult<Json<Vec<ApiKey>>> {
+    auth_user.require_session()?;
+
+    let api_keys = sqlx::query_as!(
+        ApiKey,
+        r#"SELECT id, name, prefix, scopes AS "scopes: Vec<Scope>", expires_at, last_used_at,
+            created_at
+        FROM api_keys WHERE user_id = $1
+        ORDER BY created_at DESC"#,
+        auth_user.user_id
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    Ok(Json(api_keys))
+}
+
+pub async fn revoke_api_key(
+    RequireScope {
+        user: auth_user, ..
+    }: RequireScope<UsersWrite>,
+    Extension(pool): Extension<PgPool>,
+    Path(id): Path<Uuid>,
+) -> AppResult<Json<SuccessResponse>> {
+    // A key may revoke itself, e.g. a CI job cleaning up after a leak. Anything else, OAuth
+    // client tokens included, needs a login session.
+    if auth_user.api_key_id != Some(id) {
+        auth_user.require_session()?;
+    }
+
+    let result = sqlx::query!(
+        "DELETE FROM api_keys WHERE id = $1 AND user_id = $2",
+        id,
+        auth_user.user_id
+    )
+    .execute(&pool)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound(format!("API key {id} not found")));
+    }
+
+    Ok(Json(SuccessResponse {
+        message: format!("API key {id} successfully revoked"),
+    }))
+}
@@ -0,0 +1,150 @@
+use crate::{
+    FrontendOrigin,
+    auth::jwt::{generate_refresh_token, hash_token},
+    error::{AppError, AppResult},
+    mail::{Email, SharedMailer},
+    models::{
+        SuccessResponse,
+        users::{ResendVerificationRequest, VerifyEmailRequest},
+    },
+};
+use axum::{Json, extract::Extension};
+use sqlx::PgPool;
+
+const VERIFICATION_TOKEN_TTL_HOURS: i64 = 24;
+const RESEND_COOLDOWN_SECONDS: i64 = 60;
+
+/// Issues a fresh single-use verification token for `user_id` and emails the link to `email`.
+pub(crate) async fn send_verification_email(
+    pool: &PgPool,
+    mailer: &SharedMailer,
+    frontend_origin: &str,
+    user_id: i32,
+    email: &str,
+) -> AppResult<()> {
+    let (token_plaintext, token_hash) = generate_refresh_token();
+    let expires_at =
+        chrono::Utc::now().naive_utc() + chrono::Duration::hours(VERIFICATION_TOKEN_TTL_HOURS);
+
+    sqlx::query!(
+        "INSERT INTO email_verification_tokens (user_id, token_hash, expires_at) VALUES ($1, $2, $3)",
+        user_id,
+        token_hash,
+        expires_at,
+    )
+    .execute(pool)
+    .await?;
+
+    mailer
+        .send(Email {
+            to: email.to_string(),
+            subject: "Verify your email address".to_string(),
+            body: format!(
+                "Confirm your email address by opening the link below. It expires in {VERIFICATION_TOKEN_TTL_HOURS} hours.\n\n\
+                {frontend_origin}/verify-email?token={token_plaintext}\n"
+            ),
+        })
+        .await?;
+
+    Ok(())
+}
+
+pub async fn verify_email(
+    Extension(pool): Extension<PgPool>,
+    Json(body): Json<VerifyEmailRequest>,
+) -> AppResult<Json<SuccessResponse>> {
+    let token_hash = hash_token(&body.token);
+    let now = chrono::Utc::now().naive_utc();
+    let invalid_token =
+        || AppError::Unauthorized("Invalid or expired verification token".to_string());
+
+    // Deleting the row up front makes the token single-use even under concurrent requests.
+    let record = sqlx::query!(
+        "DELETE FROM email_verification_tokens WHERE token_hash = $1 RETURNING user_id, expires_at",
+        token_hash,
+    )
+    .fetch_optional(&pool)
+    .await?
+    .ok_or_else(invalid_token)?;
+
+    if record.expires_at <= now {
+        return Err(invalid_token());
+    }
+
+    sqlx::query!(
+        "UPDATE users SET email_verified_at = COALESCE(email_verified_at, $1) WHERE id = $2",
+        now,
+        record.user_id,
+    )
+    .execute(&pool)
+    .await?;
+
+    sqlx::query!(
+        "DELETE FROM email_verification_tokens WHERE user_id = $1",
+        record.user_id,
+    )
+    .execute(&pool)
+    .await?;
+
+    Ok(Json(SuccessResponse {
+        message: "Email address successfully verified".to_string(),
+    }))
+}
+
+pub async fn resend_verification(
+    Extension(pool): Extension<PgPool>,
+    Extension(mailer): Extension<SharedMailer>,
+    Extension(FrontendOrigin(frontend_origin)): Extension<FrontendOrigin>,
+    Json(body): Json<ResendVerificationRequest>,
+) -> AppResult<Json<SuccessResponse>> {
+    // The response is identical whether or not the address exists, so it can't be used to
+    // discover which emails are registered.
+    let response = Json(SuccessResponse {
+        message:
+            "If an unverified account exists for that email, a new verification link has been sent"
+                .to_string(),
+    });
+
+    let user = sqlx::query!(
+        "SELECT id, email FROM users
+        WHERE email = $1 AND email_verified_at IS NULL AND deleted_at IS NULL",
+        body.email,
+    )
+    .fetch_optional(&pool)
+    .await?;
+
+    let Some(user) = user else {
+        return Ok(response);
+    };
+
+    let recently_sent = sqlx::query_scalar!(
+        r#"SELECT EXISTS(
+            SELECT 1 FROM email_verification_tokens
+            WHERE user_id = $1 AND created_at > NOW() - make_interval(secs => $2)
+        ) AS "exists!""#,
+        user.id,
+        RESEND_COOLDOWN_SECONDS as f64,
+    )
+    .fetch_one(&pool)
+    .await?;
+
+    if recently_sent {
+        return Ok(response);
+    }
+
+    sqlx::query!(
+        "DELETE FROM email_verification_tokens WHERE user_id = $1",
+        user.id,
+    )
+    .execute(&pool)
+    .await?;
+
+    // Like the lookup above, a failure here mustn't reveal that the account exists.
+    if let Err(e) =
+        send_verification_email(&pool, &mailer, &frontend_origin, user.id, &user.email).await
+    {
+        tracing::warn!(user_id = user.id, error = %e, "failed to send verification email");
+    }
+
+    Ok(response)
+}
@@ -0,0 +1,8 @@
+use crate::{auth::keys::SharedJwtKeys, models::jwks::JwkSet};
+use axum::{Json, extract::Extension, http::header::CACHE_CONTROL, response::IntoResponse};
+
+/// Public keys other services use to verify our access tokens. Empty when signing with HS256.
+pub async fn jwks(Extension(keys): Extension<SharedJwtKeys>) -> impl IntoResponse {
+    let jwks: JwkSet = keys.jwks();
+    ([(CACHE_CONTROL, "public, max-age=300")], Json(jwks))
+}
@@ -0,0 +1,182 @@
+use crate::{
+    FrontendOrigin,
+    auth::{
+        audit::{AuthEvent, AuthEventKind},
+        client_info::ClientInfo,
+        cookies::SessionCookies,
+        jwt::{generate_mfa_token, generate_refresh_token, hash_token},
+        keys::SharedJwtKeys,
+        lockout::record_success,
+        roles::Role,
+        scopes::resolve_requested_scope,
+    },
+    error::{AppError, AppResult},
+    handlers::users::issue_login_response,
+    mail::{Email, SharedMailer},
+    models::{
+        SuccessResponse,
+        mfa::MfaChallenge,
+        users::{LoginOutcome, MagicLinkRequest, MagicLinkVerifyRequest, User},
+    },
+};
+use axum::{Json, extract::Extension};
+use axum_extra::extract::cookie::CookieJar;
+use sqlx::PgPool;
+
+const MAGIC_LINK_TTL_MINUTES: i64 = 15;
+/// Minimum gap between two links for the same account.
+const MAGIC_LINK_COOLDOWN_SECONDS: i64 = 60;
+/// Most links sent to one account within an hour.
+const MAGIC_LINK_MAX_PER_HOUR: i64 = 5;
+
+pub async fn request_magic_link(
+    Extension(pool): Extension<PgPool>,
+    Extension(mailer): Extension<SharedMailer>,
+    Extension(FrontendOrigin(frontend_origin)): Extension<FrontendOrigin>,
+    Json(body): Json<MagicLinkRequest>,
+) -> AppResult<Json<SuccessResponse>> {
+    // Same response whether the account exists or the request was throttled, so this can't be
+    // used to probe emails.
+    let response = Json(SuccessResponse {
+        message: "If an account exists for that email, a sign-in link has been sent".to_string(),
+    });
+
+    let user = sqlx::query!(
+        "SELECT id, email FROM users WHERE email = $1 AND deleted_at IS NULL",
+        body.email
+    )
+    .fetch_optional(&pool)
+    .await?;
+
+    let Some(user) = user else {
+        return Ok(response);
+    };
+
+    let now = chrono::Utc::now().naive_utc();
+    let recent = sqlx::query!(
+        r#"SELECT COUNT(*) AS "sent!",
+            COUNT(*) FILTER (WHERE created_at > $3) AS "within_cooldown!"
+        FROM magic_link_tokens WHERE user_id = $1 AND created_at > $2"#,
+        user.id,
+        now - chrono::Duration::hours(1),
+        now - chrono::Duration::seconds(MAGIC_LINK_COOLDOWN_SECONDS),
+    )
+    .fetch_one(&pool)
+    .await?;
+
+    if recent.within_cooldown > 0 || recent.sent >= MAGIC_LINK_MAX_PER_HOUR {
+        tracing::warn!(
+            target: "security",
+            user_id = user.id,
+            "magic link request throttled"
+        );
+        return Ok(response);
+    }
+
+    let mut tx = pool.begin().await?;
+
+    // Only the most recently sent link stays valid. Old rows are kept for an hour to count
+    // toward the limit above.
+    sqlx::query!(
+        "UPDATE magic_link_tokens SET expires_at = $2
+        WHERE user_id = $1 AND used_at IS NULL AND expires_at > $2",
+        user.id,
+        now,
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM magic_link_tokens WHERE user_id = $1 AND created_at <= $2",
+        user.id,
+        now - chrono::Duration::hours(1),
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    let (token_plaintext, token_hash) = generate_refresh_token();
+    sqlx::query!(
+        "INSERT INTO magic_link_tokens (user_id, token_hash, expires_at) VALUES ($1, $2, $3)",
+        user.id,
+        token_hash,
+        now + chrono::Duration::minutes(MAGIC_LINK_TTL_MINUTES),
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    let sent = mailer
+        .send(Email {
+            to: user.email,
+            subject: "Your sign-in link".to_string(),
+            body: format!(
+                "Someone asked to sign in to your account. If that was you, open the link below within {MAGIC_LINK_TTL_MINUTES} minutes.\n\n\
+                {frontend_origin}/magic-link?token={token_plaintext}\n\n\
+                If you didn't ask for this you can ignore this email.\n"
+            ),
+        })
+        .await;
+    // Only registered addresses get this far, so an error would give them away.
+    if let Err(e) = sent {
+        tracing::warn!(user_id = user.id, error = %e, "failed to send magic link email");
+    }
+
+    Ok(response)
+}
+
+pub async fn verify_magic_link(
+    Extension(pool): Extension<PgPool>,
+    Extension(keys): Extension<SharedJwtKeys>,
+    client: ClientInfo,
+    cookies: SessionCookies,
+    Json(body): Json<MagicLinkVerifyRequest>,
+) -> AppResult<(CookieJar, Json<LoginOutcome>)> {
+    let now = chrono::Utc::now().naive_utc();
+    let invalid_token = || AppError::Unauthorized("Invalid or expired sign-in link".to_string());
+
+    // Single use: a second attempt with the same link finds nothing.
+    let user_id = sqlx::query_scalar!(
+        "UPDATE magic_link_tokens SET used_at = $2
+        WHERE token_hash = $1 AND used_at IS NULL AND expires_at > $2
+        RETURNING user_id",
+        hash_token(&body.token),
+        now,
+    )
+    .fetch_optional(&pool)
+    .await?
+    .ok_or_else(invalid_token)?;
+
+    // Following an emailed link also proves ownership of the address.
+    let user = sqlx::query_as!(
+        User,
+        r#"UPDATE users SET email_verified_at = COALESCE(email_verified_at, $2)
+        WHERE id = $1
+        RETURNING id, username, email, created_at, password_hash,
+            role AS "role: Role", email_verified_at, totp_enabled_at"#,
+        user_id,
+        now,
+    )
+    .fetch_one(&pool)
+    .await?;
+
+    let scopes = resolve_requested_scope(body.scope.as_deref(), user.role)?;
+
+    // The link stands in for the password only; a second factor is still required.
+    if user.totp_enabled_at.is_some() {
+        let challenge = MfaChallenge {
+            mfa_required: true,
+            mfa_token: generate_mfa_token(user.id, body.scope.as_deref(), &keys)?,
+        };
+        return Ok((CookieJar::new(), Json(LoginOutcome::MfaRequired(challenge))));
+    }
+
+    record_success(&pool, user.id, &user.username, &client).await?;
+    // The link is spent already, so a lost record mustn't cost the user their sign-in.
+    AuthEvent::success(AuthEventKind::Login, user.id)
+        .with_detail("magic_link")
+        .record_or_log(&pool, &client)
+        .await;
+    let mut response = issue_login_response(&pool, &keys, user, scopes, &client).await?;
+    let jar = cookies.store(&mut response.access_token, &mut response.refresh_token);
+    Ok((jar, Json(LoginOutcome::Authenticated(response))))
+}
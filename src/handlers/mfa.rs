@@ -0,0 +1,316 @@
+use crate::{
+    auth::{
+        audit::{AuthEvent, AuthEventKind},
+        client_info::ClientInfo,
+        cookies::SessionCookies,
+        jwt::{decode_mfa_token, hash_token},
+        keys::SharedJwtKeys,
+        lockout::{SharedLockoutPolicy, check_account, check_ip, record_failure, record_success},
+        roles::Role,
+        scopes::{RequireScope, UsersWrite, resolve_requested_scope},
+        totp,
+    },
+    error::{AppError, AppResult},
+    handlers::users::issue_login_response,
+    models::{
+        SuccessResponse,
+        mfa::{
+            DisableMfaRequest, MfaConfirmRequest, MfaConfirmResponse, MfaEnrollResponse,
+            MfaVerifyRequest,
+        },
+        users::{LoginResponse, User},
+    },
+};
+use axum::{Json, extract::Extension};
+use axum_extra::extract::cookie::CookieJar;
+use sqlx::PgPool;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+async fn fetch_user(pool: &PgPool, user_id: i32) -> AppResult<User> {
+    sqlx::query_as!(
+        User,
+        r#"SELECT id, username, email, created_at, password_hash,
+            role AS "role: Role", email_verified_at, totp_enabled_at
+        FROM users WHERE id = $1 AND deleted_at IS NULL"#,
+        user_id
+    )
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| AppError::NotFound("Authenticated user not found".to_string()))
+}
+
+/// Records `step` as used, failing if it (or a later step) was already accepted.
+async fn consume_totp_step(pool: &PgPool, user_id: i32, step: i64) -> AppResult<bool> {
+    let result = sqlx::query!(
+        "UPDATE users SET totp_last_used_step = $1
+        WHERE id = $2 AND (totp_last_used_step IS NULL OR totp_last_used_step < $1)",
+        step,
+        user_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+pub async fn enroll(
+    RequireScope {
+        user: auth_user, ..
+    }: RequireScope<UsersWrite>,
+    Extension(pool): Extension<PgPool>,
+) -> AppResult<Json<MfaEnrollResponse>> {
+    auth_user.require_session()?;
+    auth_user.forbid_impersonation()?;
+
+    let user = fetch_user(&pool, auth_user.user_id).await?;
+    if user.totp_enabled_at.is_some() {
+        return Err(AppError::Conflict(
+            "Two-factor authentication is already enabled".to_string(),
+        ));
+    }
+
+    // Starting over replaces any earlier, unconfirmed secret.
+    let secret = totp::generate_secret();
+    let otpauth_uri = totp::provisioning_uri(&secret, &user.username)?;
+
+    sqlx::query!(
+        "UPDATE users SET totp_secret = $1, totp_last_used_step = NULL WHERE id = $2",
+        secret,
+        user.id
+    )
+    .execute(&pool)
+    .await?;
+
+    Ok(Json(MfaEnrollResponse {
+        secret,
+        otpauth_uri,
+    }))
+}
+
+pub async fn confirm(
+    RequireScope {
+        user: auth_user, ..
+    }: RequireScope<UsersWrite>,
+    Extension(pool): Extension<PgPool>,
+    Json(body): Json<MfaConfirmRequest>,
+) -> AppResult<Json<MfaConfirmResponse>> {
+    auth_user.require_session()?;
+    auth_user.forbid_impersonation()?;
+
+    let pending = sqlx::query!(
+        "SELECT totp_secret, totp_enabled_at FROM users WHERE id = $1",
+        auth_user.user_id
+    )
+    .fetch_one(&pool)
+    .await?;
+
+    if pending.totp_enabled_at.is_some() {
+        return Err(AppError::Conflict(
+            "Two-factor authentication is already enabled".to_string(),
+        ));
+    }
+
+    let secret = pending.totp_secret.ok_or_else(|| {
+        AppError::Validation("Start enrollment before confirming a code".to_string())
+    })?;
+
+    let step = totp::verify_code(&secret, &body.code, unix_now())?
+        .ok_or_else(|| AppError::Validation("Invalid authentication code".to_string()))?;
+
+    let recovery_codes = totp::generate_recovery_codes();
+    let code_hashes: Vec<String> = recovery_codes
+        .iter()
+        .map(|code| hash_token(&totp::normalize_recovery_code(code)))
+        .collect();
+    let now = chrono::Utc::now().naive_utc();
+
+    let mut tx = pool.begin().await?;
+
+    sqlx::query!(
+        "UPDATE users SET totp_enabled_at = $1, totp_last_used_step = $2 WHERE id = $3",
+        now,
+        step,
+        auth_user.user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        "DELETE FROM mfa_recovery_codes WHERE user_id = $1",
+        auth_user.user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        "INSERT INTO mfa_recovery_codes (user_id, code_hash) SELECT $1, UNNEST($2::text[])",
+        auth_user.user_id,
+        &code_hashes,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Json(MfaConfirmResponse { recovery_codes }))
+}
+
+/// Checks a TOTP or recovery code, consuming it if valid.
+async fn check_second_factor(
+    pool: &PgPool,
+    user_id: i32,
+    secret: &str,
+    body: &MfaVerifyRequest,
+) -> AppResult<bool> {
+    match (body.code.as_deref(), body.recovery_code.as_deref()) {
+        (Some(code), _) => match totp::verify_code(secret, code, unix_now())? {
+            Some(step) => consume_totp_step(pool, user_id, step).await,
+            None => Ok(false),
+        },
+        (None, Some(recovery_code)) => {
+            let code_hash = hash_token(&totp::normalize_recovery_code(recovery_code));
+            let now = chrono::Utc::now().naive_utc();
+
+            let result = sqlx::query!(
+                "UPDATE mfa_recovery_codes SET used_at = $1
+                WHERE user_id = $2 AND code_hash = $3 AND used_at IS NULL",
+                now,
+                user_id,
+                code_hash
+            )
+            .execute(pool)
+            .await?;
+
+            Ok(result.rows_affected() == 1)
+        }
+        (None, None) => Err(AppError::Validation(
+            "Provide either code or recovery_code".to_string(),
+        )),
+    }
+}
+
+pub async fn verify(
+    Extension(pool): Extension<PgPool>,
+    Extension(keys): Extension<SharedJwtKeys>,
+    Extension(policy): Extension<SharedLockoutPolicy>,
+    client: ClientInfo,
+    cookies: SessionCookies,
+    Json(body): Json<MfaVerifyRequest>,
+) -> AppResult<(CookieJar, Json<LoginResponse>)> {
+    let claims = decode_mfa_token(&body.mfa_token, &keys)?;
+    let invalid_code = || AppError::Unauthorized("Invalid authentication code".to_string());
+
+    // Wrong codes count towards the same lockout as wrong passwords.
+    check_ip(&pool, &policy, &client).await?;
+    check_account(&pool, claims.sub).await?;
+
+    let secret = sqlx::query_scalar!(
+        "SELECT totp_secret FROM users WHERE id = $1 AND totp_enabled_at IS NOT NULL",
+        claims.sub
+    )
+    .fetch_optional(&pool)
+    .await?
+    .flatten()
+    .ok_or_else(invalid_code)?;
+
+    let user = fetch_user(&pool, claims.sub).await?;
+
+    if !check_second_factor(&pool, user.id, &secret, &body).await? {
+        record_failure(&pool, &policy, Some(user.id), &user.username, &client).await?;
+        AuthEvent::failure(AuthEventKind::Login, Some(user.id), "invalid_mfa_code")
+            .with_username(&user.username)
+            .record(&pool, &client)
+            .await?;
+        return Err(invalid_code());
+    }
+
+    record_success(&pool, user.id, &user.username, &client).await?;
+    // The code is spent already, so a lost record mustn't cost the user their sign-in.
+    AuthEvent::success(AuthEventKind::Login, user.id)
+        .with_detail("mfa")
+        .record_or_log(&pool, &client)
+        .await;
+    let scopes = resolve_requested_scope(claims.scope.as_deref(), user.role)?;
+    let mut response = issue_login_response(&pool, &keys, user, scopes, &client).await?;
+    let jar = cookies.store(&mut response.access_token, &mut response.refresh_token);
+    Ok((jar, Json(response)))
+}
+
+pub async fn disable(
+    RequireScope {
+        user: auth_user, ..
+    }: RequireScope<UsersWrite>,
+    Extension(pool): Extension<PgPool>,
+    Extension(policy): Extension<SharedLockoutPolicy>,
+    client: ClientInfo,
+    Json(body): Json<DisableMfaRequest>,
+) -> AppResult<Json<SuccessResponse>> {
+    auth_user.require_session()?;
+    auth_user.forbid_impersonation()?;
+
+    // Wrong passwords and codes count towards the same lockout as failed logins.
+    check_ip(&pool, &policy, &client).await?;
+    check_account(&pool, auth_user.user_id).await?;
+
+    let user = fetch_user(&pool, auth_user.user_id).await?;
+    let (verified, rejection) = if user.has_password() {
+        let password = body
+            .password
+            .as_deref()
+            .ok_or_else(|| AppError::Validation("password is required".to_string()))?;
+        (user.verify_password(password), "Password is incorrect")
+    } else {
+        // Accounts without a password confirm with the factor they're turning off instead.
+        let code = body.code.as_deref().ok_or_else(|| {
+            AppError::Validation("code is required for accounts without a password".to_string())
+        })?;
+        let secret = sqlx::query_scalar!(
+            "SELECT totp_secret FROM users WHERE id = $1 AND totp_enabled_at IS NOT NULL",
+            user.id
+        )
+        .fetch_optional(&pool)
+        .await?
+        .flatten()
+        .ok_or_else(|| {
+            AppError::Conflict("Two-factor authentication is not enabled".to_string())
+        })?;
+        let verified = match totp::verify_code(&secret, code, unix_now())? {
+            Some(step) => consume_totp_step(&pool, user.id, step).await?,
+            None => false,
+        };
+        (verified, "Invalid authentication code")
+    };
+
+    if !verified {
+        record_failure(&pool, &policy, Some(user.id), &user.username, &client).await?;
+        return Err(AppError::Unauthorized(rejection.to_string()));
+    }
+    record_success(&pool, user.id, &user.username, &client).await?;
+
+    let mut tx = pool.begin().await?;
+
+    sqlx::query!(
+        "UPDATE users SET totp_secret = NULL, totp_enabled_at = NULL, totp_last_used_step = NULL
+        WHERE id = $1",
+        user.id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!("DELETE FROM mfa_recovery_codes WHERE user_id = $1", user.id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+
+    Ok(Json(SuccessResponse {
+        message: "Two-factor authentication disabled".to_string(),
+    }))
+}
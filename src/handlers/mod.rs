@@ -1,2 +1,14 @@
+pub mod account_deletion;
+pub mod admin;
+pub mod api_keys;
+pub mod email_verification;
+pub mod jwks;
+pub mod magic_link;
+pub mod mfa;
+pub mod oauth;
+pub mod oidc;
+pub mod password;
 pub mod posts;
+pub mod security_events;
+pub mod sessions;
 pub mod users;
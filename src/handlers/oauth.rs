@@ -0,0 +1,594 @@
+use crate::{
+    auth::{
+        claims::Claims,
+        client_info::ClientInfo,
+        jwt::{
+            ACCESS_TOKEN_TTL_SECS, AuthUser, generate_access_token, generate_refresh_token,
+            hash_token,
+        },
+        keys::SharedJwtKeys,
+        oauth::{
+            AUTHORIZATION_CODE_TTL_MINUTES, AuthenticatedClient, OAuthError, authenticate_client,
+            validate_redirect_uri,
+        },
+        oidc::pkce_challenge,
+        refresh_tokens::{insert_refresh_token, rotate_refresh_token},
+        revocation::{Revoked, SharedRevocationStore},
+        roles::Role,
+        scopes::{RequireScope, Scope, UsersWrite, effective_scopes, format_scope, parse_scope},
+    },
+    error::{AppError, AppResult},
+    models::{
+        SuccessResponse,
+        oauth::{
+            AuthorizationRedirect, AuthorizationRequest, ConsentDecision, ConsentDetails,
+            CreateOAuthClient, CreatedOAuthClient, IntrospectionResponse, OAuthClient,
+            TokenLookupRequest, TokenRequest, TokenResponse,
+        },
+    },
+};
+use axum::{
+    Form, Json,
+    extract::{Extension, Path, Query},
+    http::{HeaderMap, header::CACHE_CONTROL},
+    response::{IntoResponse, Response},
+};
+use reqwest::Url;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+const MAX_CLIENT_NAME_LENGTH: usize = 100;
+const MAX_REDIRECT_URIS: usize = 10;
+
+pub async fn create_client(
+    RequireScope {
+        user: auth_user, ..
+    }: RequireScope<UsersWrite>,
+    Extension(pool): Extension<PgPool>,
+    Json(body): Json<CreateOAuthClient>,
+) -> AppResult<Json<CreatedOAuthClient>> {
+    auth_user.require_session()?;
+    auth_user.forbid_impersonation()?;
+
+    let name = body.name.trim();
+    if name.is_empty() || name.chars().count() > MAX_CLIENT_NAME_LENGTH {
+        return Err(AppError::Validation(format!(
+            "name must be between 1 and {MAX_CLIENT_NAME_LENGTH} characters"
+        )));
+    }
+
+    if body.redirect_uris.is_empty() || body.redirect_uris.len() > MAX_REDIRECT_URIS {
+        return Err(AppError::Validation(format!(
+            "Between 1 and {MAX_REDIRECT_URIS} redirect_uris are required"
+        )));
+    }
+    for uri in &body.redirect_uris {
+        validate_redirect_uri(uri)?;
+    }
+
+    if body.scopes.is_empty() {
+        return Err(AppError::Validation(
+            "At least one scope is required".to_string(),
+        ));
+    }
+    // The client's tokens are capped at these scopes, so registering one from a narrowed
+    // session mustn't give it scopes the session itself lacks.
+    if let Some(scope) = body.scopes.iter().find(|s| !auth_user.has_scope(**s)) {
+        return Err(AppError::Forbidden(format!(
+            "You can't grant the '{scope}' scope"
+        )));
+    }
+    let mut scopes: Vec<Scope> = Vec::new();
+    for scope in body.scopes {
+        if !scopes.contains(&scope) {
+            scopes.push(scope);
+        }
+    }
+
+    let (secret, secret_hash) = if body.confidential {
+        let (secret, hash) = generate_refresh_token();
+        (Some(secret), Some(hash))
+    } else {
+        (None, None)
+    };
+
+    let client = sqlx::query_as!(
+        OAuthClient,
+        r#"INSERT INTO oauth_clients (owner_id, name, secret_hash, redirect_uris, scopes)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING id, name, redirect_uris, scopes AS "scopes: Vec<Scope>",
+            secret_hash IS NOT NULL AS "confidential!", created_at"#,
+        auth_user.user_id,
+        name,
+        secret_hash,
+        &body.redirect_uris,
+        &scopes as &[Scope],
+    )
+    .fetch_one(&pool)
+    .await?;
+
+    Ok(Json(CreatedOAuthClient {
+        client_secret: secret,
+        client,
+    }))
+}
+
+pub async fn list_clients(
+    RequireScope {
+        user: auth_user, ..
+    }: RequireScope<UsersWrite>,
+    Extension(pool): Extension<PgPool>,
+) -> AppResult<Json<Vec<OAuthClient>>> {
+    auth_user.require_session()?;
+
+    let clients = sqlx::query_as!(
+        OAuthClient,
+        r#"SELECT id, name, redirect_uris, scopes AS "scopes: Vec<Scope>",
+            secret_hash IS NOT NULL AS "confidential!", created_at
+        FROM oauth_clients WHERE owner_id = $1
+        ORDER BY created_at DESC"#,
+        auth_user.user_id
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    Ok(Json(clients))
+}
+
+pub async fn delete_client(
+    RequireScope {
+        user: auth_user, ..
+    }: RequireScope<UsersWrite>,
+    Extension(pool): Extension<PgPool>,
+    Extension(revocations): Extension<SharedRevocationStore>,
+    Path(id): Path<Uuid>,
+) -> AppResult<Json<SuccessResponse>> {
+    auth_user.require_session()?;
+
+    let mut tx = pool.begin().await?;
+
+    // Access tokens from client_credentials grants have no session to revoke; they expire.
+    let families = sqlx::query_scalar!(
+        "SELECT DISTINCT rt.family_id FROM refresh_tokens rt
+        JOIN oauth_clients c ON c.id = rt.oauth_client_id
+        WHERE c.id = $1 AND c.owner_id = $2",
+        id,
+        auth_user.user_id
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+    let mut revoked = Revoked::default();
+    for family_id in families {
+        revoked.merge(revocations.revoke_family(&mut tx, family_id).await?);
+    }
+
+    // Cascades to the client's outstanding codes and refresh tokens.
+    let result = sqlx::query!(
+        "DELETE FROM oauth_clients WHERE id = $1 AND owner_id = $2",
+        id,
+        auth_user.user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound(format!("OAuth client {id} not found")));
+    }
+
+    tx.commit().await?;
+    revocations.apply(revoked);
+
+    Ok(Json(SuccessResponse {
+        message: format!("OAuth client {id} successfully deleted"),
+    }))
+}
+
+/// An authorization request that passed validation for the signed-in user.
+struct ValidAuthorization {
+    client_name: String,
+    scopes: Vec<Scope>,
+    code_challenge: String,
+}
+
+/// Checks an authorization request before the user is asked to consent. Errors are returned to
+/// the front end rather than redirected, since the redirect URI itself may be what's wrong.
+async fn validate_authorization(
+    pool: &PgPool,
+    auth_user: &AuthUser,
+    request: &AuthorizationRequest,
+) -> AppResult<ValidAuthorization> {
+    // Consent is the user's call, so it can't be given by a client or an API key.
+    auth_user.require_session()?;
+
+    let client = sqlx::query!(
+        r#"SELECT name, redirect_uris, scopes AS "scopes: Vec<Scope>"
+        FROM oauth_clients WHERE id = $1"#,
+        request.client_id
+    )
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| AppError::NotFound(format!("OAuth client {} not found", request.client_id)))?;
+
+    // Compared exactly: prefix or pattern matching is how open redirects creep in.
+    if !client.redirect_uris.contains(&request.redirect_uri) {
+        return Err(AppError::Validation(
+            "redirect_uri is not registered for this client".to_string(),
+        ));
+    }
+
+    if request.response_type != "code" {
+        return Err(AppError::Validation(
+            "response_type must be 'code'".to_string(),
+        ));
+    }
+
+    // Required of every client, confidential or not, as OAuth 2.1 recommends.
+    let code_challenge = match (
+        &request.code_challenge,
+        request.code_challenge_method.as_deref(),
+    ) {
+        (Some(challenge), Some("S256")) if (43..=128).contains(&challenge.len()) => {
+            challenge.clone()
+        }
+        _ => {
+            return Err(AppError::Validation(
+                "A PKCE code_challenge with code_challenge_method=S256 is required".to_string(),
+            ));
+        }
+    };
+
+    let requested = match request.scope.as_deref() {
+        Some(scope) => parse_scope(scope)?,
+        None => effective_scopes(Some(&client.scopes), auth_user.role)
+            .into_iter()
+            .filter(|scope| auth_user.has_scope(*scope))
+            .collect(),
+    };
+    if let Some(scope) = requested.iter().find(|s| !client.scopes.contains(s)) {
+        return Err(AppError::Validation(format!(
+            "This client isn't registered for the '{scope}' scope"
+        )));
+    }
+    if let Some(scope) = requested.iter().find(|s| !auth_user.has_scope(**s)) {
+        return Err(AppError::Forbidden(format!(
+            "You can't grant the '{scope}' scope"
+        )));
+    }
+    if requested.is_empty() {
+        return Err(AppError::Validation(
+            "scope must name at least one scope".to_string(),
+        ));
+    }
+
+    Ok(ValidAuthorization {
+        client_name: client.name,
+        scopes: requested,
+        code_challenge,
+    })
+}
+
+/// Validates an authorization request and returns what the consent screen should show.
+pub async fn authorize(
+    auth_user: AuthUser,
+    Extension(pool): Extension<PgPool>,
+    Query(request): Query<AuthorizationRequest>,
+) -> AppResult<Json<ConsentDetails>> {
+    let valid = validate_authorization(&pool, &auth_user, &request).await?;
+
+    Ok(Json(ConsentDetails {
+        client_id: request.client_id,
+        client_name: valid.client_name,
+        redirect_uri: request.redirect_uri,
+        scope: format_scope(&valid.scopes),
+    }))
+}
+
+/// Records the user's consent decision and tells the front end where to send the browser.
+pub async fn decide(
+    auth_user: AuthUser,
+    Extension(pool): Extension<PgPool>,
+    Json(decision): Json<ConsentDecision>,
+) -> AppResult<Json<AuthorizationRedirect>> {
+    // The consent, and the access it grants, would outlive the impersonation token.
+    auth_user.forbid_impersonation()?;
+    let request = decision.request;
+    let valid = validate_authorization(&pool, &auth_user, &request).await?;
+
+    let mut redirect = Url::parse(&request.redirect_uri)
+        .map_err(|_| AppError::Validation("redirect_uri is not a valid URL".to_string()))?;
+
+    if decision.approved {
+        let (code, code_hash) = generate_refresh_token();
+        sqlx::query!(
+            "INSERT INTO oauth_authorization_codes
+                (code_hash, client_id, user_id, redirect_uri, scopes, code_challenge, expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)",
+            code_hash,
+            request.client_id,
+            auth_user.user_id,
+            request.redirect_uri,
+            &valid.scopes as &[Scope],
+            valid.code_challenge,
+            chrono::Utc::now().naive_utc()
+                + chrono::Duration::minutes(AUTHORIZATION_CODE_TTL_MINUTES),
+        )
+        .execute(&pool)
+        .await?;
+        redirect.query_pairs_mut().append_pair("code", &code);
+    } else {
+        redirect
+            .query_pairs_mut()
+            .append_pair("error", "access_denied");
+    }
+    if let Some(state) = &request.state {
+        redirect.query_pairs_mut().append_pair("state", state);
+    }
+
+    Ok(Json(AuthorizationRedirect {
+        redirect_to: redirect.into(),
+    }))
+}
+
+pub async fn token(
+    Extension(pool): Extension<PgPool>,
+    Extension(keys): Extension<SharedJwtKeys>,
+    Extension(revocations): Extension<SharedRevocationStore>,
+    client_info: ClientInfo,
+    headers: HeaderMap,
+    Form(request): Form<TokenRequest>,
+) -> Result<Response, OAuthError> {
+    let client = authenticate_client(
+        &pool,
+        &headers,
+        request.client_id.as_deref(),
+        request.client_secret.as_deref(),
+    )
+    .await?;
+
+    let (scopes, access_token, refresh_token) = match request.grant_type.as_str() {
+        "authorization_code" => {
+            let granted = redeem_authorization_code(&pool, &client, &request).await?;
+            let scopes = effective_scopes(Some(&granted.scopes), granted.role);
+            let access = generate_access_token(
+                granted.user_id,
+                granted.role,
+                &scopes,
+                Some(client.id),
+                &keys,
+            )?;
+            let refresh_token = insert_refresh_token(
+                &mut *pool.acquire().await?,
+                granted.user_id,
+                Uuid::new_v4(),
+                Some(&granted.scopes),
+                Some(client.id),
+                access.jti,
+                &client_info,
+            )
+            .await?;
+            (scopes, access.token, Some(refresh_token))
+        }
+        "refresh_token" => {
+            let token = required(request.refresh_token.as_deref(), "refresh_token")?;
+            // Scopes are re-derived from the current role, as for first-party sessions.
+            let rotated = rotate_refresh_token(
+                &pool,
+                &revocations,
+                &keys,
+                token,
+                Some(client.id),
+                &client_info,
+            )
+            .await
+            .map_err(|e| match e {
+                AppError::Unauthorized(message) => OAuthError::invalid_grant(message),
+                e => e.into(),
+            })?;
+            (
+                rotated.scopes,
+                rotated.access_token,
+                Some(rotated.refresh_token),
+            )
+        }
+        "client_credentials" => {
+            if !client.confidential {
+                return Err(OAuthError::unauthorized_client(
+                    "Public clients can't use the client_credentials grant",
+                ));
+            }
+            // The client acts as the user who registered it, within the client's scopes.
+            let role = sqlx::query_scalar!(
+                r#"SELECT role AS "role: Role" FROM users WHERE id = $1 AND deleted_at IS NULL"#,
+                client.owner_id
+            )
+            .fetch_optional(&pool)
+            .await?
+            .ok_or_else(|| {
+                OAuthError::unauthorized_client(
+                    "The account that registered this client is deleted",
+                )
+            })?;
+            let mut scopes = effective_scopes(Some(&client.scopes), role);
+            if let Some(requested) = request.scope.as_deref() {
+                let requested =
+                    parse_scope(requested).map_err(|e| OAuthError::invalid_scope(e.to_string()))?;
+                if requested.iter().any(|scope| !scopes.contains(scope)) {
+                    return Err(OAuthError::invalid_scope(
+                        "scope exceeds what this client may be granted",
+                    ));
+                }
+                scopes = requested;
+            }
+            let access =
+                generate_access_token(client.owner_id, role, &scopes, Some(client.id), &keys)?;
+            (scopes, access.token, None)
+        }
+        other => return Err(OAuthError::unsupported_grant_type(other)),
+    };
+
+    let response = TokenResponse {
+        access_token,
+        token_type: "Bearer",
+        expires_in: ACCESS_TOKEN_TTL_SECS,
+        refresh_token,
+        scope: format_scope(&scopes),
+    };
+    Ok(([(CACHE_CONTROL, "no-store")], Json(response)).into_response())
+}
+
+struct GrantedCode {
+    user_id: i32,
+    role: Role,
+    scopes: Vec<Scope>,
+}
+
+async fn redeem_authorization_code(
+    pool: &PgPool,
+    client: &AuthenticatedClient,
+    request: &TokenRequest,
+) -> Result<GrantedCode, OAuthError> {
+    let code = required(request.code.as_deref(), "code")?;
+    let redirect_uri = required(request.redirect_uri.as_deref(), "redirect_uri")?;
+    let code_verifier = required(request.code_verifier.as_deref(), "code_verifier")?;
+    let invalid_code = || OAuthError::invalid_grant("Invalid or expired authorization code");
+
+    // Single use: a replayed code finds nothing.
+    let record = sqlx::query!(
+        r#"DELETE FROM oauth_authorization_codes c USING users u
+        WHERE c.code_hash = $1 AND u.id = c.user_id
+        RETURNING c.client_id, c.user_id, c.redirect_uri, c.scopes AS "scopes: Vec<Scope>",
+            c.code_challenge, c.expires_at, u.role AS "role: Role""#,
+        hash_token(code),
+    )
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(invalid_code)?;
+
+    if record.client_id != client.id
+        || record.redirect_uri != redirect_uri
+        || record.expires_at <= chrono::Utc::now().naive_utc()
+        || pkce_challenge(code_verifier) != record.code_challenge
+    {
+        return Err(invalid_code());
+    }
+
+    Ok(GrantedCode {
+        user_id: record.user_id,
+        role: record.role,
+        scopes: record.scopes,
+    })
+}
+
+fn required<'a>(value: Option<&'a str>, name: &str) -> Result<&'a str, OAuthError> {
+    value.ok_or_else(|| OAuthError::invalid_request(format!("{name} is required")))
+}
+
+/// Token introspection (RFC 7662). Clients only learn about tokens issued to themselves;
+/// anything else, including tokens that don't exist, is reported as inactive.
+pub async fn introspect(
+    Extension(pool): Extension<PgPool>,
+    Extension(keys): Extension<SharedJwtKeys>,
+    Extension(revocations): Extension<SharedRevocationStore>,
+    headers: HeaderMap,
+    Form(request): Form<TokenLookupRequest>,
+) -> Result<Response, OAuthError> {
+    let client = authenticate_client(
+        &pool,
+        &headers,
+        request.client_id.as_deref(),
+        request.client_secret.as_deref(),
+    )
+    .await?;
+
+    let response = if let Ok(claims) = keys.decode::<Claims>(&request.token, None) {
+        // A revoked token must read as inactive, or resource servers would keep honoring it.
+        let active = claims.client_id == Some(client.id)
+            && !revocations
+                .is_revoked(&pool, claims.jti, claims.sub, claims.issued_at_us())
+                .await?;
+        if active {
+            IntrospectionResponse {
+                active: true,
+                scope: Some(claims.scope),
+                client_id: claims.client_id,
+                sub: Some(claims.sub.to_string()),
+                token_type: Some("access_token"),
+                exp: Some(claims.exp as i64),
+                iat: Some(claims.iat as i64),
+            }
+        } else {
+            IntrospectionResponse::default()
+        }
+    } else {
+        let record = sqlx::query!(
+            r#"SELECT rt.user_id, rt.scopes AS "scopes: Vec<Scope>", rt.expires_at, rt.created_at,
+                u.role AS "role: Role"
+            FROM refresh_tokens rt JOIN users u ON u.id = rt.user_id
+            WHERE rt.token_hash = $1 AND rt.oauth_client_id = $2
+                AND rt.used_at IS NULL AND rt.expires_at > $3"#,
+            hash_token(&request.token),
+            client.id,
+            chrono::Utc::now().naive_utc(),
+        )
+        .fetch_optional(&pool)
+        .await?;
+
+        match record {
+            Some(record) => IntrospectionResponse {
+                active: true,
+                scope: Some(format_scope(&effective_scopes(
+                    record.scopes.as_deref(),
+                    record.role,
+                ))),
+                client_id: Some(client.id),
+                sub: Some(record.user_id.to_string()),
+                token_type: Some("refresh_token"),
+                exp: Some(record.expires_at.and_utc().timestamp()),
+                iat: Some(record.created_at.and_utc().timestamp()),
+            },
+            None => IntrospectionResponse::default(),
+        }
+    };
+
+    Ok(([(CACHE_CONTROL, "no-store")], Json(response)).into_response())
+}
+
+/// Token revocation (RFC 7009). Revoking a refresh token ends the whole grant it belongs to,
+/// including the access tokens issued with it.
+pub async fn revoke(
+    Extension(pool): Extension<PgPool>,
+    Extension(revocations): Extension<SharedRevocationStore>,
+    headers: HeaderMap,
+    Form(request): Form<TokenLookupRequest>,
+) -> Result<Json<SuccessResponse>, OAuthError> {
+    let client = authenticate_client(
+        &pool,
+        &headers,
+        request.client_id.as_deref(),
+        request.client_secret.as_deref(),
+    )
+    .await?;
+
+    let family_id = sqlx::query_scalar!(
+        "SELECT family_id FROM refresh_tokens WHERE token_hash = $1 AND oauth_client_id = $2",
+        hash_token(&request.token),
+        client.id,
+    )
+    .fetch_optional(&pool)
+    .await?;
+
+    // Succeeds even if nothing matched, so the response reveals nothing about the token.
+    if let Some(family_id) = family_id {
+        let mut tx = pool.begin().await?;
+        let revoked = revocations.revoke_family(&mut tx, family_id).await?;
+        sqlx::query!("DELETE FROM refresh_tokens WHERE family_id = $1", family_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        revocations.apply(revoked);
+    }
+
+    Ok(Json(SuccessResponse {
+        message: "Token revoked".to_string(),
+    }))
+}
@@ -0,0 +1,290 @@
+use crate::{
+    auth::{
+        audit::{AuthEvent, AuthEventKind},
+        client_info::ClientInfo,
+        cookies::SessionCookies,
+        jwt::{generate_mfa_token, hash_token},
+        keys::SharedJwtKeys,
+        oidc::{IdTokenClaims, SharedOidcProviders, pkce_challenge, random_token},
+        roles::Role,
+        scopes::{RequireScope, UsersRead, parse_scope, resolve_requested_scope},
+    },
+    error::{AppError, AppResult},
+    handlers::users::issue_login_response,
+    models::{
+        mfa::MfaChallenge,
+        oidc::{OidcAuthorizeRequest, OidcAuthorizeResponse, OidcCallbackRequest, UserIdentity},
+        users::{LoginOutcome, User},
+    },
+};
+use axum::{
+    Json,
+    extract::{Extension, Path},
+};
+use axum_extra::extract::cookie::CookieJar;
+use rand::Rng;
+use sqlx::{PgConnection, PgPool};
+
+const LOGIN_STATE_TTL_MINUTES: i64 = 10;
+const MAX_USERNAME_LENGTH: usize = 30;
+
+pub async fn authorize(
+    Path(provider): Path<String>,
+    Extension(pool): Extension<PgPool>,
+    Extension(oidc): Extension<SharedOidcProviders>,
+    body: Option<Json<OidcAuthorizeRequest>>,
+) -> AppResult<Json<OidcAuthorizeResponse>> {
+    let provider = oidc.get(&provider)?;
+    let scope = body.and_then(|Json(body)| body.scope);
+    // Checked again against the user's role once we know who they are.
+    if let Some(scope) = &scope {
+        parse_scope(scope)?;
+    }
+
+    let state = random_token();
+    let code_verifier = random_token();
+    let nonce = random_token();
+    let url = oidc
+        .authorization_url(provider, &state, &pkce_challenge(&code_verifier), &nonce)
+        .await?;
+
+    let now = chrono::Utc::now().naive_utc();
+    sqlx::query!("DELETE FROM oidc_login_states WHERE expires_at <= $1", now)
+        .execute(&pool)
+        .await?;
+    sqlx::query!(
+        "INSERT INTO oidc_login_states (state_hash, provider, code_verifier, nonce, scope, expires_at)
+        VALUES ($1, $2, $3, $4, $5, $6)",
+        hash_token(&state),
+        provider.name,
+        code_verifier,
+        nonce,
+        scope,
+        now + chrono::Duration::minutes(LOGIN_STATE_TTL_MINUTES),
+    )
+    .execute(&pool)
+    .await?;
+
+    Ok(Json(OidcAuthorizeResponse {
+        authorization_url: url,
+    }))
+}
+
+pub async fn callback(
+    Path(provider): Path<String>,
+    Extension(pool): Extension<PgPool>,
+    Extension(keys): Extension<SharedJwtKeys>,
+    Extension(oidc): Extension<SharedOidcProviders>,
+    client: ClientInfo,
+    cookies: SessionCookies,
+    Json(body): Json<OidcCallbackRequest>,
+) -> AppResult<(CookieJar, Json<LoginOutcome>)> {
+    let provider = oidc.get(&provider)?;
+    let invalid_state = || AppError::Unauthorized("Invalid or expired sign-in state".to_string());
+
+    // Single use: a replayed callback finds nothing.
+    let login_state = sqlx::query!(
+        "DELETE FROM oidc_login_states WHERE state_hash = $1
+        RETURNING provider, code_verifier, nonce, scope, expires_at",
+        hash_token(&body.state),
+    )
+    .fetch_optional(&pool)
+    .await?
+    .ok_or_else(invalid_state)?;
+
+    if login_state.provider != provider.name
+        || login_state.expires_at <= chrono::Utc::now().naive_utc()
+    {
+        return Err(invalid_state());
+    }
+
+    let claims = oidc
+        .exchange_code(
+            provider,
+            &body.code,
+            &login_state.code_verifier,
+            &login_state.nonce,
+        )
+        .await?;
+
+    let mut tx = pool.begin().await?;
+    let user = find_or_link_user(&mut tx, &provider.name, &claims).await?;
+    tx.commit().await?;
+
+    if user.email_verified_at.is_none() {
+        AuthEvent::failure(AuthEventKind::Login, Some(user.id), "email_unverified")
+            .record(&pool, &client)
+            .await?;
+        return Err(AppError::Forbidden(
+            "Email address has not been verified".to_string(),
+        ));
+    }
+
+    let scopes = resolve_requested_scope(login_state.scope.as_deref(), user.role)?;
+
+    // The provider vouches for the first factor only.
+    if user.totp_enabled_at.is_some() {
+        let challenge = MfaChallenge {
+            mfa_required: true,
+            mfa_token: generate_mfa_token(user.id, login_state.scope.as_deref(), &keys)?,
+        };
+        return Ok((CookieJar::new(), Json(LoginOutcome::MfaRequired(challenge))));
+    }
+
+    // The callback state is spent already, so a lost record mustn't cost the user their sign-in.
+    AuthEvent::success(AuthEventKind::Login, user.id)
+        .with_detail("oidc")
+        .record_or_log(&pool, &client)
+        .await;
+    let mut response = issue_login_response(&pool, &keys, user, scopes, &client).await?;
+    let jar = cookies.store(&mut response.access_token, &mut response.refresh_token);
+    Ok((jar, Json(LoginOutcome::Authenticated(response))))
+}
+
+pub async fn list_identities(
+    RequireScope {
+        user: auth_user, ..
+    }: RequireScope<UsersRead>,
+    Extension(pool): Extension<PgPool>,
+) -> AppResult<Json<Vec<UserIdentity>>> {
+    let identities = sqlx::query_as!(
+        UserIdentity,
+        "SELECT provider, email, created_at, last_login_at FROM user_identities
+        WHERE user_id = $1 ORDER BY created_at",
+        auth_user.user_id
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    Ok(Json(identities))
+}
+
+/// The local user behind an external identity. Unknown identities are linked to the account
+/// with the same email address if the provider has verified it, and otherwise get a new account.
+async fn find_or_link_user(
+    conn: &mut PgConnection,
+    provider: &str,
+    claims: &IdTokenClaims,
+) -> AppResult<User> {
+    let now = chrono::Utc::now().naive_utc();
+
+    let linked = sqlx::query_as!(
+        User,
+        r#"UPDATE user_identities i SET last_login_at = $3, email = COALESCE($4, i.email)
+        FROM users u
+        WHERE u.id = i.user_id AND i.provider = $1 AND i.subject = $2 AND u.deleted_at IS NULL
+        RETURNING u.id, u.username, u.email, u.created_at, u.password_hash,
+            u.role AS "role: Role", u.email_verified_at, u.totp_enabled_at"#,
+        provider,
+        claims.sub,
+        now,
+        claims.email,
+    )
+    .fetch_optional(&mut *conn)
+    .await?;
+    if let Some(user) = linked {
+        return Ok(user);
+    }
+
+    let email = claims.email.as_deref().ok_or_else(|| {
+        AppError::Validation("The identity provider did not share an email address".to_string())
+    })?;
+
+    // Linking on an unverified address would let anyone who can claim it at the provider take
+    // over the local account.
+    let existing = if claims.email_verified {
+        sqlx::query_as!(
+            User,
+            r#"SELECT id, username, email, created_at, password_hash,
+                role AS "role: Role", email_verified_at, totp_enabled_at
+            FROM users WHERE LOWER(email) = LOWER($1) AND deleted_at IS NULL"#,
+            email
+        )
+        .fetch_optional(&mut *conn)
+        .await?
+    } else {
+        None
+    };
+
+    let user = match existing {
+        // Whoever registered the address locally never proved they own it, so they may know the
+        // password of the account the provider's user would be signed in to.
+        Some(user) if user.email_verified_at.is_none() => {
+            return Err(AppError::Conflict(
+                "An unverified account with this email address already exists".to_string(),
+            ));
+        }
+        Some(user) => user,
+        None => {
+            let username = available_username(conn, claims, email).await?;
+            // No password: the account signs in through the provider until one is set via
+            // password reset.
+            sqlx::query_as!(
+                User,
+                r#"INSERT INTO users (username, email, password_hash, email_verified_at)
+                VALUES ($1, $2, '', $3)
+                RETURNING id, username, email, created_at, password_hash,
+                    role AS "role: Role", email_verified_at, totp_enabled_at"#,
+                username,
+                email,
+                claims.email_verified.then_some(now),
+            )
+            .fetch_one(&mut *conn)
+            .await?
+        }
+    };
+
+    sqlx::query!(
+        "INSERT INTO user_identities (user_id, provider, subject, email, last_login_at)
+        VALUES ($1, $2, $3, $4, $5)",
+        user.id,
+        provider,
+        claims.sub,
+        claims.email,
+        now,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    tracing::info!(
+        target: "security",
+        user_id = user.id,
+        provider,
+        "linked external identity"
+    );
+    Ok(user)
+}
+
+/// The provider's preferred username, or the email's local part, made unique if it's taken.
+async fn available_username(
+    conn: &mut PgConnection,
+    claims: &IdTokenClaims,
+    email: &str,
+) -> AppResult<String> {
+    let preferred = claims
+        .preferred_username
+        .as_deref()
+        .unwrap_or_else(|| email.split('@').next().unwrap_or_default());
+    let mut base: String = preferred
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+        .take(MAX_USERNAME_LENGTH - 5)
+        .collect();
+    if base.is_empty() {
+        base = "user".to_string();
+    }
+
+    let mut candidate = base.clone();
+    loop {
+        let taken = sqlx::query_scalar!(
+            r#"SELECT EXISTS(SELECT 1 FROM users WHERE username = $1) AS "taken!""#,
+            candidate
+        )
+        .fetch_one(&mut *conn)
+        .await?;
+        if !taken {
+            return Ok(candidate);
+        }
+        candidate = format!("{base}_{:04}", rand::thread_rng().gen_range(0..10_000));
+    }
+}
@@ -0,0 +1,267 @@
+use crate::{
+    FrontendOrigin,
+    auth::{
+        audit::{AuthEvent, AuthEventKind},
+        client_info::ClientInfo,
+        cookies::SessionCookies,
+        jwt::{generate_access_token, generate_refresh_token, hash_token},
+        keys::SharedJwtKeys,
+        lockout::{SharedLockoutPolicy, check_account, check_ip, record_failure, record_success},
+        password_policy::SharedPasswordPolicy,
+        refresh_tokens::issue_refresh_token,
+        revocation::SharedRevocationStore,
+        roles::Role,
+        scopes::{RequireScope, Scope, UsersWrite, effective_scopes, format_scope},
+    },
+    error::{AppError, AppResult},
+    mail::{Email, SharedMailer},
+    models::{
+        SuccessResponse,
+        users::{
+            ChangePasswordRequest, ForgotPasswordRequest, RefreshResponse, ResetPasswordRequest,
+            User,
+        },
+    },
+};
+use axum::{Json, extract::Extension};
+use axum_extra::extract::cookie::CookieJar;
+use sqlx::PgPool;
+
+const RESET_TOKEN_TTL_MINUTES: i64 = 60;
+
+pub async fn forgot_password(
+    Extension(pool): Extension<PgPool>,
+    Extension(mailer): Extension<SharedMailer>,
+    Extension(FrontendOrigin(frontend_origin)): Extension<FrontendOrigin>,
+    Json(body): Json<ForgotPasswordRequest>,
+) -> AppResult<Json<SuccessResponse>> {
+    // Same response whether or not the account exists, so this can't be used to probe emails.
+    let response = Json(SuccessResponse {
+        message: "If an account exists for that email, a password reset link has been sent"
+            .to_string(),
+    });
+
+    let user = sqlx::query!(
+        "SELECT id, email FROM users WHERE email = $1 AND deleted_at IS NULL",
+        body.email
+    )
+    .fetch_optional(&pool)
+    .await?;
+
+    let Some(user) = user else {
+        return Ok(response);
+    };
+
+    // Only the most recently requested link stays valid.
+    sqlx::query!(
+        "DELETE FROM password_reset_tokens WHERE user_id = $1",
+        user.id
+    )
+    .execute(&pool)
+    .await?;
+
+    let (token_plaintext, token_hash) = generate_refresh_token();
+    let expires_at =
+        chrono::Utc::now().naive_utc() + chrono::Duration::minutes(RESET_TOKEN_TTL_MINUTES);
+
+    sqlx::query!(
+        "INSERT INTO password_reset_tokens (user_id, token_hash, expires_at) VALUES ($1, $2, $3)",
+        user.id,
+        token_hash,
+        expires_at,
+    )
+    .execute(&pool)
+    .await?;
+
+    let sent = mailer
+        .send(Email {
+            to: user.email,
+            subject: "Reset your password".to_string(),
+            body: format!(
+                "Someone asked to reset the password for your account. If that was you, open the link below within {RESET_TOKEN_TTL_MINUTES} minutes.\n\n\
+                {frontend_origin}/reset-password?token={token_plaintext}\n\n\
+                If you didn't ask for this you can ignore this email.\n"
+            ),
+        })
+        .await;
+    // An error here would only ever reach registered addresses, giving them away.
+    if let Err(e) = sent {
+        tracing::warn!(user_id = user.id, error = %e, "failed to send password reset email");
+    }
+
+    Ok(response)
+}
+
+pub async fn reset_password(
+    Extension(pool): Extension<PgPool>,
+    Extension(revocations): Extension<SharedRevocationStore>,
+    Extension(policy): Extension<SharedPasswordPolicy>,
+    client: ClientInfo,
+    Json(body): Json<ResetPasswordRequest>,
+) -> AppResult<Json<SuccessResponse>> {
+    let token_hash = hash_token(&body.token);
+    let now = chrono::Utc::now().naive_utc();
+    let invalid_token = || AppError::Unauthorized("Invalid or expired reset token".to_string());
+
+    let mut tx = pool.begin().await?;
+
+    let record = sqlx::query!(
+        "DELETE FROM password_reset_tokens WHERE token_hash = $1 RETURNING user_id, expires_at",
+        token_hash,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .filter(|record| record.expires_at > now);
+    let Some(record) = record else {
+        AuthEvent::failure(AuthEventKind::PasswordReset, None, "invalid_token")
+            .record(&pool, &client)
+            .await?;
+        return Err(invalid_token());
+    };
+
+    // Rejecting the password rolls back the transaction, so the token can be used again.
+    let user = sqlx::query!(
+        "SELECT username, email FROM users WHERE id = $1",
+        record.user_id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    policy.validate(
+        "new_password",
+        &body.new_password,
+        &user.username,
+        &user.email,
+    )?;
+
+    let password_hash = User::hash_password(&body.new_password)?;
+
+    // Following an emailed link also proves ownership of the address.
+    sqlx::query!(
+        "UPDATE users SET password_hash = $1, email_verified_at = COALESCE(email_verified_at, $2) WHERE id = $3",
+        password_hash,
+        now,
+        record.user_id,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    // Whoever knew the old password may still hold a session; cut them all off.
+    let revoked = revocations
+        .revoke_all_for_user(&mut tx, record.user_id)
+        .await?;
+    sqlx::query!(
+        "DELETE FROM refresh_tokens WHERE user_id = $1",
+        record.user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+    AuthEvent::success(AuthEventKind::PasswordReset, record.user_id)
+        .record(&mut *tx, &client)
+        .await?;
+
+    tx.commit().await?;
+    revocations.apply(revoked);
+
+    Ok(Json(SuccessResponse {
+        message: "Password successfully reset, please log in again".to_string(),
+    }))
+}
+
+pub async fn change_password(
+    RequireScope {
+        user: auth_user, ..
+    }: RequireScope<UsersWrite>,
+    Extension(pool): Extension<PgPool>,
+    (Extension(keys), Extension(revocations)): (
+        Extension<SharedJwtKeys>,
+        Extension<SharedRevocationStore>,
+    ),
+    (Extension(policy), Extension(lockout)): (
+        Extension<SharedPasswordPolicy>,
+        Extension<SharedLockoutPolicy>,
+    ),
+    client: ClientInfo,
+    cookies: SessionCookies,
+    Json(body): Json<ChangePasswordRequest>,
+) -> AppResult<(CookieJar, Json<RefreshResponse>)> {
+    auth_user.require_session()?;
+    auth_user.forbid_impersonation()?;
+
+    // A stolen access token mustn't turn this into an unthrottled way to guess the password.
+    check_ip(&pool, &lockout, &client).await?;
+    check_account(&pool, auth_user.user_id).await?;
+
+    let user = sqlx::query_as!(
+        User,
+        r#"SELECT id, username, email, created_at, password_hash,
+            role AS "role: Role", email_verified_at, totp_enabled_at
+        FROM users WHERE id = $1"#,
+        auth_user.user_id
+    )
+    .fetch_optional(&pool)
+    .await?
+    .ok_or_else(|| AppError::NotFound("Authenticated user not found".to_string()))?;
+
+    if !user.verify_password(&body.current_password) {
+        record_failure(&pool, &lockout, Some(user.id), &user.username, &client).await?;
+        AuthEvent::failure(
+            AuthEventKind::PasswordChanged,
+            Some(user.id),
+            "invalid_credentials",
+        )
+        .record(&pool, &client)
+        .await?;
+        return Err(AppError::Unauthorized(
+            "Current password is incorrect".to_string(),
+        ));
+    }
+    record_success(&pool, user.id, &user.username, &client).await?;
+
+    policy.validate(
+        "new_password",
+        &body.new_password,
+        &user.username,
+        &user.email,
+    )?;
+    let password_hash = User::hash_password(&body.new_password)?;
+
+    let mut tx = pool.begin().await?;
+
+    sqlx::query!(
+        "UPDATE users SET password_hash = $1 WHERE id = $2",
+        password_hash,
+        user.id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    // Drop every existing session and hand the caller a fresh one, so only this device stays
+    // signed in.
+    let revoked = revocations.revoke_all_for_user(&mut tx, user.id).await?;
+    sqlx::query!("DELETE FROM refresh_tokens WHERE user_id = $1", user.id)
+        .execute(&mut *tx)
+        .await?;
+    // The new session keeps the old one's scopes, so changing the password can't widen it.
+    let narrowed = Scope::all_for(user.role)
+        .into_iter()
+        .any(|scope| !auth_user.has_scope(scope));
+    let restriction = narrowed.then_some(auth_user.scopes.as_slice());
+    let scopes = effective_scopes(restriction, user.role);
+    let access = generate_access_token(user.id, user.role, &scopes, None, &keys)?;
+    let refresh_token =
+        issue_refresh_token(&mut tx, user.id, restriction, access.jti, &client).await?;
+    AuthEvent::success(AuthEventKind::PasswordChanged, user.id)
+        .record(&mut *tx, &client)
+        .await?;
+
+    tx.commit().await?;
+    revocations.apply(revoked);
+
+    let mut response = RefreshResponse {
+        access_token: Some(access.token),
+        refresh_token: Some(refresh_token),
+        scope: format_scope(&scopes),
+    };
+    let jar = cookies.store(&mut response.access_token, &mut response.refresh_token);
+    Ok((jar, Json(response)))
+}
@@ -1,214 +1,228 @@
 use crate::{
-    auth::jwt::AuthUser,
+    auth::scopes::{PostsRead, PostsWrite, RequireScope, Scope},
+    error::{AppError, AppResult},
     models::{
-        ErrorResponse, SuccessResponse,
-        posts::{CreatePost, Post, UpdatePost},
+        SuccessResponse,
+        pagination::{Cursor, Page, PageParams},
+        posts::{CreatePost, Post, PostSearchResult, SearchParams, UpdatePost},
     },
 };
 use axum::{
     Json,
-    extract::{Extension, Path},
-    http::StatusCode,
+    extract::{Extension, Path, Query},
 };
 use sqlx::PgPool;
 
+fn post_cursor(post: &Post) -> Cursor {
+    Cursor {
+        created_at: post.created_at,
+        id: post.id,
+    }
+}
+
 pub async fn get_posts(
-    _auth_user: AuthUser,
+    _auth_user: RequireScope<PostsRead>,
     Extension(pool): Extension<PgPool>,
-) -> Result<Json<Vec<Post>>, (StatusCode, Json<ErrorResponse>)> {
-    let posts = sqlx::query_as!(Post, "SELECT * FROM posts ORDER BY created_at DESC")
-        .fetch_all(&pool)
-        .await
-        .map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    error: e.to_string(),
-                    message: "Failed to fetch posts from database".to_string(),
-                    details: None,
-                }),
-            )
-        })?;
+    Query(params): Query<PageParams>,
+) -> AppResult<Json<Page<Post>>> {
+    let (cursor, limit) = params.resolve()?;
 
-    Ok(Json(posts))
+    let posts = sqlx::query_as!(
+        Post,
+        "SELECT p.id, p.title, p.body, p.user_id, p.created_at
+        FROM posts p JOIN users u ON u.id = p.user_id AND u.deleted_at IS NULL
+        WHERE $1::timestamp IS NULL OR (p.created_at, p.id) < ($1, $2::int)
+        ORDER BY p.created_at DESC, p.id DESC
+        LIMIT $3",
+        cursor.map(|c| c.created_at),
+        cursor.map(|c| c.id),
+        limit + 1
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    Ok(Json(Page::from_rows(posts, limit, post_cursor)))
+}
+
+pub async fn search_posts(
+    _auth_user: RequireScope<PostsRead>,
+    Extension(pool): Extension<PgPool>,
+    Query(params): Query<SearchParams>,
+) -> AppResult<Json<Vec<PostSearchResult>>> {
+    let q = params.q.trim();
+    if q.is_empty() {
+        return Err(AppError::Validation(
+            "Search query must not be empty".to_string(),
+        ));
+    }
+
+    // Ranked results aren't keyset-paginated, so only the limit applies.
+    let (_, limit) = PageParams {
+        cursor: None,
+        limit: params.limit,
+    }
+    .resolve()?;
+
+    let results = sqlx::query_as!(
+        PostSearchResult,
+        r#"SELECT p.id, p.title, p.user_id, p.created_at,
+            ts_rank(p.search_vector, query) AS "rank!",
+            -- The body is HTML-escaped first so the only markup in the snippet is our own.
+            ts_headline(
+                'english',
+                replace(replace(replace(replace(replace(
+                    p.body, '&', '&amp;'), '<', '&lt;'), '>', '&gt;'), '"', '&quot;'), '''', '&#39;'),
+                query,
+                'StartSel=<mark>, StopSel=</mark>, MaxFragments=2, MaxWords=30, MinWords=10'
+            ) AS "snippet!"
+        FROM posts p JOIN users u ON u.id = p.user_id AND u.deleted_at IS NULL,
+            websearch_to_tsquery('english', $1) query
+        WHERE p.search_vector @@ query
+        ORDER BY "rank!" DESC, p.id DESC
+        LIMIT $2"#,
+        q,
+        limit
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    Ok(Json(results))
 }
 
 pub async fn get_post(
-    _auth_user: AuthUser,
+    _auth_user: RequireScope<PostsRead>,
     Extension(pool): Extension<PgPool>,
     Path(id): Path<i32>,
-) -> Result<Json<Post>, (StatusCode, Json<ErrorResponse>)> {
-    let post = sqlx::query_as!(Post, "SELECT * FROM posts WHERE id = $1", id)
-        .fetch_optional(&pool)
-        .await
-        .map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    error: e.to_string(),
-                    message: "Failed to fetch post from database".to_string(),
-                    details: None,
-                }),
-            )
-        })?;
-
-    match post {
-        Some(post) => Ok(Json(post)),
-        None => Err((
-            StatusCode::NOT_FOUND,
-            Json(ErrorResponse {
-                error: "Post not found".to_string(),
-                message: format!("Post with id {id} not found"),
-                details: None,
-            }),
-        )),
-    }
+) -> AppResult<Json<Post>> {
+    let post = sqlx::query_as!(
+        Post,
+        "SELECT p.id, p.title, p.body, p.user_id, p.created_at
+        FROM posts p JOIN users u ON u.id = p.user_id AND u.deleted_at IS NULL
+        WHERE p.id = $1",
+        id
+    )
+    .fetch_optional(&pool)
+    .await?
+    .ok_or_else(|| AppError::NotFound(format!("Post with id {id} not found")))?;
+
+    Ok(Json(post))
 }
 
-async fn fetch_user_posts(
-    id: i32,
-    pool: &PgPool,
-) -> Result<Vec<Post>, (StatusCode, Json<ErrorResponse>)> {
+async fn fetch_user_posts(id: i32, params: &PageParams, pool: &PgPool) -> AppResult<Page<Post>> {
+    let (cursor, limit) = params.resolve()?;
+
     let posts = sqlx::query_as!(
         Post,
-        "SELECT * FROM posts WHERE user_id = $1 ORDER BY created_at DESC",
-        id
+        "SELECT p.id, p.title, p.body, p.user_id, p.created_at
+        FROM posts p JOIN users u ON u.id = p.user_id AND u.deleted_at IS NULL
+        WHERE p.user_id = $1 AND ($2::timestamp IS NULL OR (p.created_at, p.id) < ($2, $3::int))
+        ORDER BY p.created_at DESC, p.id DESC
+        LIMIT $4",
+        id,
+        cursor.map(|c| c.created_at),
+        cursor.map(|c| c.id),
+        limit + 1
     )
     .fetch_all(pool)
-    .await
-    .map_err(|e| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                error: e.to_string(),
-                message: "Failed to fetch user posts from database".to_string(),
-                details: None,
-            }),
-        )
-    })?;
+    .await?;
 
-    Ok(posts)
+    Ok(Page::from_rows(posts, limit, post_cursor))
 }
 
 pub async fn get_user_posts(
-    _auth_user: AuthUser,
+    _auth_user: RequireScope<PostsRead>,
     Path(id): Path<i32>,
     Extension(pool): Extension<PgPool>,
-) -> Result<Json<Vec<Post>>, (StatusCode, Json<ErrorResponse>)> {
-    let posts = fetch_user_posts(id, &pool).await?;
+    Query(params): Query<PageParams>,
+) -> AppResult<Json<Page<Post>>> {
+    let posts = fetch_user_posts(id, &params, &pool).await?;
     Ok(Json(posts))
 }
 
 pub async fn get_current_user_posts(
-    auth_user: AuthUser,
+    RequireScope {
+        user: auth_user, ..
+    }: RequireScope<PostsRead>,
     Extension(pool): Extension<PgPool>,
-) -> Result<Json<Vec<Post>>, (StatusCode, Json<ErrorResponse>)> {
-    let posts = fetch_user_posts(auth_user.user_id, &pool).await?;
+    Query(params): Query<PageParams>,
+) -> AppResult<Json<Page<Post>>> {
+    let posts = fetch_user_posts(auth_user.user_id, &params, &pool).await?;
     Ok(Json(posts))
 }
 
 pub async fn create_post(
-    auth_user: AuthUser,
+    RequireScope {
+        user: auth_user, ..
+    }: RequireScope<PostsWrite>,
     Extension(pool): Extension<PgPool>,
     Json(post): Json<CreatePost>,
-) -> Result<Json<Post>, (StatusCode, Json<ErrorResponse>)> {
+) -> AppResult<Json<Post>> {
     let post = sqlx::query_as!(
         Post,
-        "INSERT INTO posts (title, body, user_id) VALUES ($1, $2, $3) RETURNING *",
+        "INSERT INTO posts (title, body, user_id) VALUES ($1, $2, $3) RETURNING id, title, body, user_id, created_at",
         post.title,
         post.body,
         auth_user.user_id
     )
     .fetch_one(&pool)
-    .await
-    .map_err(|e| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                error: e.to_string(),
-                message: "Failed to create post".to_string(),
-                details: None,
-            }),
-        )
-    })?;
+    .await?;
 
     Ok(Json(post))
 }
 
 pub async fn update_post(
-    auth_user: AuthUser,
+    RequireScope {
+        user: auth_user, ..
+    }: RequireScope<PostsWrite>,
     Extension(pool): Extension<PgPool>,
     Path(id): Path<i32>,
     Json(post): Json<UpdatePost>,
-) -> Result<Json<Post>, (StatusCode, Json<ErrorResponse>)> {
+) -> AppResult<Json<Post>> {
     let post = sqlx::query_as!(
         Post,
-        "UPDATE posts SET title = COALESCE($1, title), body = COALESCE($2, body) WHERE id = $3 AND user_id = $4 RETURNING *",
+        "UPDATE posts SET title = COALESCE($1, title), body = COALESCE($2, body) WHERE id = $3 AND (user_id = $4 OR $5) RETURNING id, title, body, user_id, created_at",
         post.title,
         post.body,
         id,
-        auth_user.user_id
+        auth_user.user_id,
+        // The role alone isn't enough: an admin's narrowed or delegated token only covers their
+        // own posts.
+        auth_user.has_scope(Scope::UsersAdmin)
     )
     .fetch_optional(&pool)
-    .await
-    .map_err(|e| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                error: e.to_string(),
-                message: "Failed to update post".to_string(),
-                details: None,
-            }),
-        )
-    })?
+    .await?
     .ok_or_else(|| {
-        (
-            StatusCode::NOT_FOUND,
-            Json(ErrorResponse {
-                error: "Post not found or unauthorized".to_string(),
-                message: format!("Post with id {id} not found or you don't have permission to update it"),
-                details: None,
-            }),
-        )
+        AppError::NotFound(format!(
+            "Post with id {id} not found or you don't have permission to update it"
+        ))
     })?;
 
     Ok(Json(post))
 }
 
 pub async fn delete_post(
-    auth_user: AuthUser,
+    RequireScope {
+        user: auth_user, ..
+    }: RequireScope<PostsWrite>,
     Extension(pool): Extension<PgPool>,
     Path(id): Path<i32>,
-) -> Result<Json<SuccessResponse>, (StatusCode, Json<ErrorResponse>)> {
+) -> AppResult<Json<SuccessResponse>> {
     let result = sqlx::query!(
-        "DELETE FROM posts WHERE id = $1 AND user_id = $2",
+        "DELETE FROM posts WHERE id = $1 AND (user_id = $2 OR $3)",
         id,
-        auth_user.user_id
+        auth_user.user_id,
+        auth_user.has_scope(Scope::UsersAdmin)
     )
     .execute(&pool)
-    .await
-    .map_err(|e| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                error: e.to_string(),
-                message: "Failed to delete post".to_string(),
-                details: None,
-            }),
-        )
-    })?;
+    .await?;
 
-    match result.rows_affected() {
-        0 => Err((
-            StatusCode::NOT_FOUND,
-            Json(ErrorResponse {
-                error: "Post not found or unauthorized".to_string(),
-                message: format!("Post with id {id} not found or you don't have permission to delete it"),
-                details: None,
-            }),
-        )),
-        _ => Ok(Json(SuccessResponse {
-            message: format!("Post with id {id} successfully deleted"),
-        })),
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound(format!(
+            "Post with id {id} not found or you don't have permission to delete it"
+        )));
     }
+
+    Ok(Json(SuccessResponse {
+        message: format!("Post with id {id} successfully deleted"),
+    }))
 }
@@ -0,0 +1,50 @@
+use crate::{
+    auth::{
+        audit::{AuthEventKind, AuthEventOutcome},
+        scopes::{RequireScope, UsersRead},
+    },
+    error::AppResult,
+    models::{
+        auth_events::AuthEventRecord,
+        pagination::{Cursor, Page, PageParams},
+    },
+};
+use axum::{
+    Json,
+    extract::{Extension, Query},
+};
+use sqlx::PgPool;
+
+/// The caller's own audit log, newest first.
+pub async fn list_security_events(
+    RequireScope {
+        user: auth_user, ..
+    }: RequireScope<UsersRead>,
+    Extension(pool): Extension<PgPool>,
+    Query(params): Query<PageParams>,
+) -> AppResult<Json<Page<AuthEventRecord>>> {
+    let (cursor, limit) = params.resolve()?;
+
+    let events = sqlx::query_as!(
+        AuthEventRecord,
+        r#"SELECT id, user_id, event_type AS "event_type: AuthEventKind",
+            outcome AS "outcome: AuthEventOutcome", detail, username, actor_id, ip_address,
+            user_agent, created_at
+        FROM auth_events
+        WHERE user_id = $1
+            AND ($2::timestamp IS NULL OR (created_at, id) < ($2, $3::int))
+        ORDER BY created_at DESC, id DESC
+        LIMIT $4"#,
+        auth_user.user_id,
+        cursor.map(|c| c.created_at),
+        cursor.map(|c| c.id),
+        limit + 1
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    Ok(Json(Page::from_rows(events, limit, |event| Cursor {
+        created_at: event.created_at,
+        id: event.id,
+    })))
+}
@@ -0,0 +1,126 @@
+use crate::{
+    auth::{
+        audit::{AuthEvent, AuthEventKind},
+        client_info::ClientInfo,
+        cookies::SessionCookies,
+        revocation::SharedRevocationStore,
+        scopes::{RequireScope, UsersWrite},
+    },
+    error::{AppError, AppResult},
+    models::{SuccessResponse, sessions::Session},
+};
+use axum::{
+    Json,
+    extract::{Extension, Path},
+};
+use axum_extra::extract::cookie::CookieJar;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+pub async fn list_sessions(
+    RequireScope {
+        user: auth_user, ..
+    }: RequireScope<UsersWrite>,
+    Extension(pool): Extension<PgPool>,
+) -> AppResult<Json<Vec<Session>>> {
+    auth_user.require_session()?;
+
+    let now = chrono::Utc::now().naive_utc();
+
+    // Each family has exactly one live token; its siblings tell us when the session started and
+    // when it last rotated.
+    let sessions = sqlx::query_as!(
+        Session,
+        r#"SELECT rt.family_id AS id, c.name AS "client_name?", rt.user_agent, rt.ip_address,
+            rt.expires_at, family.signed_in_at AS "signed_in_at!", family.last_used_at
+        FROM refresh_tokens rt
+        LEFT JOIN oauth_clients c ON c.id = rt.oauth_client_id
+        JOIN (
+            SELECT family_id, MIN(created_at) AS signed_in_at, MAX(used_at) AS last_used_at
+            FROM refresh_tokens WHERE user_id = $1 GROUP BY family_id
+        ) family ON family.family_id = rt.family_id
+        WHERE rt.user_id = $1 AND rt.used_at IS NULL AND rt.expires_at > $2
+        ORDER BY family.signed_in_at DESC"#,
+        auth_user.user_id,
+        now,
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    Ok(Json(sessions))
+}
+
+pub async fn revoke_session(
+    RequireScope {
+        user: auth_user, ..
+    }: RequireScope<UsersWrite>,
+    Extension(pool): Extension<PgPool>,
+    Extension(revocations): Extension<SharedRevocationStore>,
+    Path(id): Path<Uuid>,
+) -> AppResult<Json<SuccessResponse>> {
+    auth_user.require_session()?;
+    auth_user.forbid_impersonation()?;
+
+    let mut tx = pool.begin().await?;
+
+    let owned = sqlx::query_scalar!(
+        r#"SELECT EXISTS(SELECT 1 FROM refresh_tokens WHERE family_id = $1 AND user_id = $2) AS "exists!""#,
+        id,
+        auth_user.user_id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    if !owned {
+        return Err(AppError::NotFound(format!("Session {id} not found")));
+    }
+
+    let revoked = revocations.revoke_family(&mut tx, id).await?;
+    sqlx::query!("DELETE FROM refresh_tokens WHERE family_id = $1", id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    revocations.apply(revoked);
+
+    Ok(Json(SuccessResponse {
+        message: format!("Session {id} successfully revoked"),
+    }))
+}
+
+pub async fn logout_all(
+    RequireScope {
+        user: auth_user, ..
+    }: RequireScope<UsersWrite>,
+    Extension(pool): Extension<PgPool>,
+    Extension(revocations): Extension<SharedRevocationStore>,
+    client: ClientInfo,
+    cookies: SessionCookies,
+) -> AppResult<(CookieJar, Json<SuccessResponse>)> {
+    auth_user.require_session()?;
+    auth_user.forbid_impersonation()?;
+
+    let mut tx = pool.begin().await?;
+    let revoked = revocations
+        .revoke_all_for_user(&mut tx, auth_user.user_id)
+        .await?;
+    sqlx::query!(
+        "DELETE FROM refresh_tokens WHERE user_id = $1",
+        auth_user.user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+    AuthEvent::success(AuthEventKind::Logout, auth_user.user_id)
+        .with_detail("all_sessions")
+        .with_actor(auth_user.impersonator_id)
+        .record(&mut *tx, &client)
+        .await?;
+    tx.commit().await?;
+    revocations.apply(revoked);
+
+    Ok((
+        cookies.clear(),
+        Json(SuccessResponse {
+            message: "Successfully logged out of all sessions".to_string(),
+        }),
+    ))
+}
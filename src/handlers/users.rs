@@ -1,416 +1,484 @@
 use crate::{
-    auth::jwt::{AuthUser, generate_refresh_token, generate_token, hash_token},
+    FrontendOrigin,
+    auth::{
+        account_deletion::{SharedDeletionPolicy, deactivate_account, issue_restore_token},
+        audit::{AuthEvent, AuthEventKind, changed_fields},
+        client_info::ClientInfo,
+        cookies::{REFRESH_COOKIE, SessionCookies},
+        jwt::{generate_access_token, generate_mfa_token, hash_token},
+        keys::{JwtKeys, SharedJwtKeys},
+        lockout::{SharedLockoutPolicy, check_account, check_ip, record_failure, record_success},
+        password_policy::SharedPasswordPolicy,
+        providers::SharedAuthProvider,
+        refresh_tokens::{issue_refresh_token, rotate_refresh_token},
+        revocation::SharedRevocationStore,
+        roles::Role,
+        scopes::{
+            RequireScope, Scope, UsersRead, UsersWrite, effective_scopes, format_scope,
+            resolve_requested_scope,
+        },
+    },
+    error::{AppError, AppResult},
+    handlers::email_verification::send_verification_email,
+    mail::{Email, SharedMailer},
     models::{
-        ErrorResponse, SuccessResponse,
+        SuccessResponse,
+        mfa::MfaChallenge,
+        pagination::{Cursor, Page, PageParams},
         users::{
-            CreateUser, LoginRequest, LoginResponse, RefreshRequest, RefreshResponse, UpdateUser,
-            User, UserSafe,
+            CreateUser, LoginOutcome, LoginRequest, LoginResponse, RefreshRequest, RefreshResponse,
+            UpdateUser, User, UserSafe,
         },
     },
 };
 use axum::{
     Json,
-    extract::{Extension, Path},
-    http::{HeaderMap, StatusCode},
+    extract::{Extension, Path, Query},
+    http::HeaderMap,
 };
+use axum_extra::extract::cookie::CookieJar;
 use sqlx::PgPool;
 
 pub async fn get_users(
-    _auth_user: AuthUser,
+    _auth_user: RequireScope<UsersRead>,
     Extension(pool): Extension<PgPool>,
-) -> Result<Json<Vec<UserSafe>>, (StatusCode, Json<ErrorResponse>)> {
+    Query(params): Query<PageParams>,
+) -> AppResult<Json<Page<UserSafe>>> {
+    let (cursor, limit) = params.resolve()?;
+
     let users = sqlx::query_as!(
         UserSafe,
-        "SELECT id, username, email, created_at FROM users"
+        "SELECT id, username, email, created_at FROM users
+        WHERE deleted_at IS NULL AND ($1::timestamp IS NULL OR (created_at, id) < ($1, $2::int))
+        ORDER BY created_at DESC, id DESC
+        LIMIT $3",
+        cursor.map(|c| c.created_at),
+        cursor.map(|c| c.id),
+        limit + 1
     )
     .fetch_all(&pool)
-    .await
-    .map_err(|e| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                error: e.to_string(),
-                message: "Failed to fetch users from database".to_string(),
-                details: None,
-            }),
-        )
-    })?;
+    .await?;
 
-    Ok(Json(users))
+    Ok(Json(Page::from_rows(users, limit, |user| Cursor {
+        created_at: user.created_at,
+        id: user.id,
+    })))
 }
 
 pub async fn get_user(
-    _auth_user: AuthUser,
+    _auth_user: RequireScope<UsersRead>,
     Extension(pool): Extension<PgPool>,
     Path(id): Path<i32>,
-) -> Result<Json<UserSafe>, (StatusCode, Json<ErrorResponse>)> {
+) -> AppResult<Json<UserSafe>> {
     let user = sqlx::query_as!(
         UserSafe,
-        "SELECT id, username, email, created_at FROM users WHERE id = $1",
+        "SELECT id, username, email, created_at FROM users WHERE id = $1 AND deleted_at IS NULL",
         id
     )
     .fetch_optional(&pool)
-    .await
-    .map_err(|e| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                error: e.to_string(),
-                message: "Failed to fetch user from database".to_string(),
-                details: None,
-            }),
-        )
-    })?;
-
-    match user {
-        Some(user) => Ok(Json(user)),
-        None => Err((
-            StatusCode::NOT_FOUND,
-            Json(ErrorResponse {
-                error: "User not found".to_string(),
-                message: format!("User with id {id} not found"),
-                details: None,
-            }),
-        )),
-    }
+    .await?
+    .ok_or_else(|| AppError::NotFound(format!("User with id {id} not found")))?;
+
+    Ok(Json(user))
 }
 
 pub async fn get_current_user(
-    auth_user: AuthUser,
+    RequireScope {
+        user: auth_user, ..
+    }: RequireScope<UsersRead>,
     Extension(pool): Extension<PgPool>,
-) -> Result<Json<UserSafe>, (StatusCode, Json<ErrorResponse>)> {
+) -> AppResult<Json<UserSafe>> {
     let user = sqlx::query_as!(
         UserSafe,
-        "SELECT id, username, email, created_at FROM users WHERE id = $1",
+        "SELECT id, username, email, created_at FROM users WHERE id = $1 AND deleted_at IS NULL",
         auth_user.user_id
     )
     .fetch_optional(&pool)
-    .await
-    .map_err(|e| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                error: e.to_string(),
-                message: "Failed to fetch user from database".to_string(),
-                details: None,
-            }),
-        )
-    })?;
-
-    let user = user.ok_or_else(|| {
-        (
-            StatusCode::NOT_FOUND,
-            Json(ErrorResponse {
-                error: "User not found".to_string(),
-                message: "Authenticated user not found".to_string(),
-                details: None,
-            }),
-        )
-    })?;
+    .await?
+    .ok_or_else(|| AppError::NotFound("Authenticated user not found".to_string()))?;
 
     Ok(Json(user))
 }
 
 pub async fn create_user(
     Extension(pool): Extension<PgPool>,
+    Extension(mailer): Extension<SharedMailer>,
+    Extension(FrontendOrigin(frontend_origin)): Extension<FrontendOrigin>,
+    Extension(policy): Extension<SharedPasswordPolicy>,
     Json(user): Json<CreateUser>,
-) -> Result<Json<UserSafe>, (StatusCode, Json<ErrorResponse>)> {
-    let password_hash = User::hash_password(&user.password).map_err(|e| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                error: e.to_string(),
-                message: "Failed to hash password".to_string(),
-                details: None,
-            }),
-        )
-    })?;
+) -> AppResult<Json<UserSafe>> {
+    policy.validate("password", &user.password, &user.username, &user.email)?;
+    let password_hash = User::hash_password(&user.password)?;
 
     let user = sqlx::query_as!(
         User,
-        "INSERT INTO users (username, email, password_hash) VALUES ($1, $2, $3) RETURNING *",
+        r#"INSERT INTO users (username, email, password_hash) VALUES ($1, $2, $3)
+        RETURNING id, username, email, created_at, password_hash,
+            role AS "role: Role", email_verified_at, totp_enabled_at"#,
         user.username,
         user.email,
         password_hash
     )
     .fetch_one(&pool)
-    .await
-    .map_err(|e| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                error: e.to_string(),
-                message: "Failed to create user".to_string(),
-                details: None,
-            }),
-        )
-    })?;
+    .await?;
+
+    // The account exists either way; a failed send can be retried via resend-verification.
+    if let Err(e) =
+        send_verification_email(&pool, &mailer, &frontend_origin, user.id, &user.email).await
+    {
+        tracing::warn!(user_id = user.id, error = %e, "failed to send verification email");
+    }
 
     Ok(Json(user.into()))
 }
 
 pub async fn update_user(
-    auth_user: AuthUser,
+    RequireScope {
+        user: auth_user, ..
+    }: RequireScope<UsersWrite>,
     Extension(pool): Extension<PgPool>,
+    Extension(mailer): Extension<SharedMailer>,
+    Extension(FrontendOrigin(frontend_origin)): Extension<FrontendOrigin>,
+    client: ClientInfo,
     Json(user): Json<UpdateUser>,
-) -> Result<Json<UserSafe>, (StatusCode, Json<ErrorResponse>)> {
-    let user = sqlx::query_as!(UserSafe, "UPDATE users SET username = COALESCE($1, username), email = COALESCE($2, email) WHERE id = $3 RETURNING id, username, email, created_at", user.username, user.email, auth_user.user_id)
-        .fetch_one(&pool)
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse{
-            error: e.to_string(),
-            message: "Failed to update user".to_string(),
-            details: None,
-        })))
-        ?;
+) -> AppResult<Json<UserSafe>> {
+    let changed = changed_fields(&[
+        ("username", user.username.is_some()),
+        ("email", user.email.is_some()),
+    ]);
+
+    let mut tx = pool.begin().await?;
+
+    // A new address has to be verified again before it can be used to log in.
+    let row = sqlx::query!(
+        r#"UPDATE users u SET username = COALESCE($1, u.username), email = COALESCE($2, u.email),
+            email_verified_at = CASE WHEN $2 <> old.email
+                THEN NULL ELSE u.email_verified_at END
+        FROM (SELECT id, email FROM users WHERE id = $3 FOR UPDATE) old
+        WHERE u.id = old.id
+        RETURNING u.id, u.username, u.email, u.created_at, u.email <> old.email AS "email_changed!""#,
+        user.username,
+        user.email,
+        auth_user.user_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| AppError::NotFound("Authenticated user not found".to_string()))?;
+
+    if row.email_changed {
+        // Links sent to the old address must not verify the new one.
+        sqlx::query!(
+            "DELETE FROM email_verification_tokens WHERE user_id = $1",
+            row.id
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+    AuthEvent::success(AuthEventKind::AccountUpdated, auth_user.user_id)
+        .with_detail(&changed)
+        .with_actor(auth_user.impersonator_id)
+        .record(&mut *tx, &client)
+        .await?;
+
+    tx.commit().await?;
+
+    // The change stands either way; a failed send can be retried via resend-verification.
+    if row.email_changed
+        && let Err(e) =
+            send_verification_email(&pool, &mailer, &frontend_origin, row.id, &row.email).await
+    {
+        tracing::warn!(user_id = row.id, error = %e, "failed to send verification email");
+    }
 
-    Ok(Json(user))
+    Ok(Json(UserSafe {
+        id: row.id,
+        username: row.username,
+        email: row.email,
+        created_at: row.created_at,
+    }))
 }
 
 pub async fn delete_user(
-    auth_user: AuthUser,
+    RequireScope {
+        user: auth_user, ..
+    }: RequireScope<UsersWrite>,
     Extension(pool): Extension<PgPool>,
-) -> Result<Json<SuccessResponse>, (StatusCode, Json<ErrorResponse>)> {
-    let result = sqlx::query!("DELETE FROM users WHERE id = $1", auth_user.user_id)
-        .execute(&pool)
-        .await
-        .map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    error: e.to_string(),
-                    message: "Failed to delete user".to_string(),
-                    details: None,
-                }),
-            )
+    Extension(revocations): Extension<SharedRevocationStore>,
+    Extension(deletion): Extension<SharedDeletionPolicy>,
+    Extension(mailer): Extension<SharedMailer>,
+    Extension(FrontendOrigin(frontend_origin)): Extension<FrontendOrigin>,
+    client: ClientInfo,
+) -> AppResult<Json<SuccessResponse>> {
+    auth_user.forbid_impersonation()?;
+
+    let mut tx = pool.begin().await?;
+
+    let account = deactivate_account(&mut tx, &revocations, &deletion, auth_user.user_id)
+        .await?
+        .ok_or_else(|| {
+            AppError::NotFound(format!("User with id {} not found", auth_user.user_id))
         })?;
-
-    match result.rows_affected() {
-        0 => Err((
-            StatusCode::NOT_FOUND,
-            Json(ErrorResponse {
-                error: "Unauthorized".to_string(),
-                message: format!("User with id {} not found", auth_user.user_id),
-                details: None,
-            }),
-        )),
-        _ => Ok(Json(SuccessResponse {
-            message: format!("User with id {} successfully deleted", auth_user.user_id),
-        })),
+    let token_plaintext = issue_restore_token(&mut tx, auth_user.user_id, &account).await?;
+    AuthEvent::success(AuthEventKind::AccountDeleted, auth_user.user_id)
+        .record(&mut *tx, &client)
+        .await?;
+
+    tx.commit().await?;
+    revocations.apply(account.revoked);
+
+    let restore_until = account.purge_after.format("%Y-%m-%d %H:%M UTC");
+    // The account is deactivated either way; without the link an admin can still restore it.
+    let sent = mailer
+        .send(Email {
+            to: account.email,
+            subject: "Your account has been deleted".to_string(),
+            body: format!(
+                "Your account has been deactivated and will be deleted for good on {restore_until}. Changed your mind? Open the link below before then to restore it.\n\n\
+                {frontend_origin}/restore-account?token={token_plaintext}\n"
+            ),
+        })
+        .await;
+    if let Err(e) = sent {
+        tracing::warn!(user_id = auth_user.user_id, error = %e, "failed to send account restore email");
     }
+
+    Ok(Json(SuccessResponse {
+        message: format!(
+            "User with id {} deleted; it can be restored until {restore_until}",
+            auth_user.user_id
+        ),
+    }))
 }
 
 pub async fn login(
     Extension(pool): Extension<PgPool>,
+    Extension(keys): Extension<SharedJwtKeys>,
+    Extension(provider): Extension<SharedAuthProvider>,
+    Extension(policy): Extension<SharedLockoutPolicy>,
+    client: ClientInfo,
+    cookies: SessionCookies,
     Json(login_request): Json<LoginRequest>,
-) -> Result<Json<LoginResponse>, (StatusCode, Json<ErrorResponse>)> {
-    let user = sqlx::query_as!(
+) -> AppResult<(CookieJar, Json<LoginOutcome>)> {
+    let invalid_credentials =
+        || AppError::Unauthorized("Username or password is incorrect".to_string());
+    let failed_login = |user_id, detail| {
+        AuthEvent::failure(AuthEventKind::Login, user_id, detail)
+            .with_username(&login_request.username)
+    };
+
+    if let Err(e) = check_ip(&pool, &policy, &client).await {
+        if matches!(e, AppError::TooManyRequests { .. }) {
+            failed_login(None, "throttled")
+                .record(&pool, &client)
+                .await?;
+        }
+        return Err(e);
+    }
+
+    let local_user = sqlx::query_as!(
         User,
-        "SELECT * FROM users WHERE username = $1",
+        r#"SELECT id, username, email, created_at, password_hash,
+            role AS "role: Role", email_verified_at, totp_enabled_at
+        FROM users WHERE username = $1 AND deleted_at IS NULL"#,
         login_request.username
     )
     .fetch_optional(&pool)
-    .await
-    .map_err(|e| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                error: e.to_string(),
-                message: "Database error".to_string(),
-                details: None,
-            }),
+    .await?;
+    let local_user_id = local_user.as_ref().map(|user| user.id);
+
+    if let Some(user_id) = local_user_id
+        && let Err(e) = check_account(&pool, user_id).await
+    {
+        if matches!(e, AppError::TooManyRequests { .. }) {
+            failed_login(Some(user_id), "locked")
+                .record(&pool, &client)
+                .await?;
+        }
+        return Err(e);
+    }
+
+    let Some(user) = provider
+        .authenticate(
+            &pool,
+            local_user,
+            &login_request.username,
+            &login_request.password,
         )
-    })?;
-
-    let user = user.ok_or_else(|| {
-        (
-            StatusCode::UNAUTHORIZED,
-            Json(ErrorResponse {
-                error: "Invalid credentials".to_string(),
-                message: "Username or password is incorrect".to_string(),
-                details: None,
-            }),
+        .await?
+    else {
+        record_failure(
+            &pool,
+            &policy,
+            local_user_id,
+            &login_request.username,
+            &client,
         )
-    })?;
-
-    if !user.verify_password(&login_request.password) {
-        return Err((
-            StatusCode::UNAUTHORIZED,
-            Json(ErrorResponse {
-                error: "Invalid credentials".to_string(),
-                message: "Username or password is incorrect".to_string(),
-                details: None,
-            }),
+        .await?;
+        failed_login(local_user_id, "invalid_credentials")
+            .record(&pool, &client)
+            .await?;
+        return Err(invalid_credentials());
+    };
+
+    if user.email_verified_at.is_none() {
+        failed_login(Some(user.id), "email_unverified")
+            .record(&pool, &client)
+            .await?;
+        return Err(AppError::Forbidden(
+            "Email address has not been verified".to_string(),
         ));
     }
 
-    let jwt_secret = std::env::var("JWT_SECRET").expect("JWT_SECRET must be set");
-    let access_token = generate_token(user.id, &jwt_secret).map_err(|e| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                error: e.error,
-                message: e.message,
-                details: e.details,
-            }),
-        )
-    })?;
+    let scopes = resolve_requested_scope(login_request.scope.as_deref(), user.role)?;
+
+    if user.totp_enabled_at.is_some() {
+        let challenge = MfaChallenge {
+            mfa_required: true,
+            mfa_token: generate_mfa_token(user.id, login_request.scope.as_deref(), &keys)?,
+        };
+        // The password was right; the second factor is still to come.
+        AuthEvent::success(AuthEventKind::Login, user.id)
+            .with_detail("mfa_required")
+            .record(&pool, &client)
+            .await?;
+        return Ok((CookieJar::new(), Json(LoginOutcome::MfaRequired(challenge))));
+    }
 
-    let (refresh_plaintext, refresh_hash) = generate_refresh_token();
-    let expires_at = chrono::Utc::now().naive_utc() + chrono::Duration::days(7);
+    record_success(&pool, user.id, &user.username, &client).await?;
+    AuthEvent::success(AuthEventKind::Login, user.id)
+        .record(&pool, &client)
+        .await?;
+    let mut response = issue_login_response(&pool, &keys, user, scopes, &client).await?;
+    let jar = cookies.store(&mut response.access_token, &mut response.refresh_token);
+    Ok((jar, Json(LoginOutcome::Authenticated(response))))
+}
 
-    sqlx::query!(
-        "INSERT INTO refresh_tokens (user_id, token_hash, expires_at) VALUES ($1, $2, $3)",
+/// Starts a new session for a fully authenticated user.
+pub(crate) async fn issue_login_response(
+    pool: &PgPool,
+    keys: &JwtKeys,
+    user: User,
+    restriction: Option<Vec<Scope>>,
+    client: &ClientInfo,
+) -> AppResult<LoginResponse> {
+    let scopes = effective_scopes(restriction.as_deref(), user.role);
+    let access = generate_access_token(user.id, user.role, &scopes, None, keys)?;
+
+    let refresh_plaintext = issue_refresh_token(
+        &mut *pool.acquire().await?,
         user.id,
-        refresh_hash,
-        expires_at,
+        restriction.as_deref(),
+        access.jti,
+        client,
     )
-    .execute(&pool)
-    .await
-    .map_err(|e| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                error: e.to_string(),
-                message: "Failed to store refresh token".to_string(),
-                details: None,
-            }),
-        )
-    })?;
+    .await?;
 
-    Ok(Json(LoginResponse {
-        access_token,
-        refresh_token: refresh_plaintext,
+    Ok(LoginResponse {
+        access_token: Some(access.token),
+        refresh_token: Some(refresh_plaintext),
+        scope: format_scope(&scopes),
         user: user.into(),
-    }))
+    })
 }
 
 pub async fn refresh(
     Extension(pool): Extension<PgPool>,
-    Json(body): Json<RefreshRequest>,
-) -> Result<Json<RefreshResponse>, (StatusCode, Json<ErrorResponse>)> {
-    let token_hash = hash_token(&body.refresh_token);
-    let now = chrono::Utc::now().naive_utc();
-
-    let record = sqlx::query!(
-        "SELECT id, user_id FROM refresh_tokens WHERE token_hash = $1 AND expires_at > $2",
-        token_hash,
-        now,
-    )
-    .fetch_optional(&pool)
-    .await
-    .map_err(|e| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                error: e.to_string(),
-                message: "Database error".to_string(),
-                details: None,
-            }),
-        )
-    })?
-    .ok_or_else(|| {
-        (
-            StatusCode::UNAUTHORIZED,
-            Json(ErrorResponse {
-                error: "Invalid or expired refresh token".to_string(),
-                message: "Please log in again".to_string(),
-                details: None,
-            }),
-        )
-    })?;
-
-    sqlx::query!("DELETE FROM refresh_tokens WHERE id = $1", record.id)
-        .execute(&pool)
-        .await
-        .map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    error: e.to_string(),
-                    message: "Failed to rotate refresh token".to_string(),
-                    details: None,
-                }),
-            )
-        })?;
-
-    let jwt_secret = std::env::var("JWT_SECRET").expect("JWT_SECRET must be set");
-    let access_token = generate_token(record.user_id, &jwt_secret).map_err(|e| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                error: e.error,
-                message: e.message,
-                details: e.details,
-            }),
-        )
-    })?;
-
-    let (refresh_plaintext, refresh_hash) = generate_refresh_token();
-    let expires_at = chrono::Utc::now().naive_utc() + chrono::Duration::days(7);
-
-    sqlx::query!(
-        "INSERT INTO refresh_tokens (user_id, token_hash, expires_at) VALUES ($1, $2, $3)",
-        record.user_id,
-        refresh_hash,
-        expires_at,
-    )
-    .execute(&pool)
-    .await
-    .map_err(|e| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                error: e.to_string(),
-                message: "Failed to store refresh token".to_string(),
-                details: None,
-            }),
-        )
-    })?;
-
-    Ok(Json(RefreshResponse {
-        access_token,
-        refresh_token: refresh_plaintext,
-    }))
+    Extension(keys): Extension<SharedJwtKeys>,
+    Extension(revocations): Extension<SharedRevocationStore>,
+    client: ClientInfo,
+    cookies: SessionCookies,
+    body: Option<Json<RefreshRequest>>,
+) -> AppResult<(CookieJar, Json<RefreshResponse>)> {
+    let invalid_token = || {
+        AppError::Unauthorized("Invalid or expired refresh token, please log in again".to_string())
+    };
+    let refresh_token = match body {
+        Some(Json(body)) => body.refresh_token,
+        None => cookies.token(REFRESH_COOKIE)?.ok_or_else(invalid_token)?,
+    };
+    let rotated =
+        match rotate_refresh_token(&pool, &revocations, &keys, &refresh_token, None, &client).await
+        {
+            Ok(rotated) => rotated,
+            Err(e @ AppError::Unauthorized(_)) => {
+                AuthEvent::failure(AuthEventKind::Refresh, None, "invalid_token")
+                    .record(&pool, &client)
+                    .await?;
+                return Err(e);
+            }
+            Err(e) => return Err(e),
+        };
+    // The old token is spent already; failing now would sign the user out.
+    AuthEvent::success(AuthEventKind::Refresh, rotated.user_id)
+        .record_or_log(&pool, &client)
+        .await;
+
+    let mut response = RefreshResponse {
+        access_token: Some(rotated.access_token),
+        refresh_token: Some(rotated.refresh_token),
+        scope: format_scope(&rotated.scopes),
+    };
+    let jar = cookies.store(&mut response.access_token, &mut response.refresh_token);
+    Ok((jar, Json(response)))
 }
 
 pub async fn logout(
     Extension(pool): Extension<PgPool>,
+    Extension(revocations): Extension<SharedRevocationStore>,
+    client: ClientInfo,
     headers: HeaderMap,
-) -> Result<Json<SuccessResponse>, (StatusCode, Json<ErrorResponse>)> {
-    let refresh_token = headers
+    cookies: SessionCookies,
+) -> AppResult<(CookieJar, Json<SuccessResponse>)> {
+    let bearer = headers
         .get("Authorization")
         .and_then(|h| h.to_str().ok())
         .and_then(|h| h.strip_prefix("Bearer "))
-        .ok_or((
-            StatusCode::UNAUTHORIZED,
-            Json(ErrorResponse {
-                error: "Missing or invalid Authorization header".to_string(),
-                message: "Authorization header with Bearer token required".to_string(),
-                details: None,
-            }),
-        ))?;
-
-    let token_hash = hash_token(refresh_token);
-
-    sqlx::query!(
-        "DELETE FROM refresh_tokens WHERE token_hash = $1",
-        token_hash,
+        .map(str::to_string);
+    let refresh_token = match bearer {
+        Some(token) => token,
+        None => cookies.token(REFRESH_COOKIE)?.ok_or_else(|| {
+            AppError::Unauthorized("Authorization header with Bearer token required".to_string())
+        })?,
+    };
+
+    let session = sqlx::query!(
+        "SELECT family_id, user_id FROM refresh_tokens WHERE token_hash = $1",
+        hash_token(&refresh_token),
     )
-    .execute(&pool)
-    .await
-    .map_err(|e| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                error: e.to_string(),
-                message: "Failed to revoke refresh token".to_string(),
-                details: None,
-            }),
-        )
-    })?;
+    .fetch_optional(&pool)
+    .await?;
+
+    // Logging out ends the whole session, including tokens already rotated out of it and the
+    // access tokens issued with them.
+    match session {
+        Some(session) => {
+            let mut tx = pool.begin().await?;
+            let revoked = revocations
+                .revoke_family(&mut tx, session.family_id)
+                .await?;
+            sqlx::query!(
+                "DELETE FROM refresh_tokens WHERE family_id = $1",
+                session.family_id
+            )
+            .execute(&mut *tx)
+            .await?;
+            AuthEvent::success(AuthEventKind::Logout, session.user_id)
+                .record(&mut *tx, &client)
+                .await?;
+            tx.commit().await?;
+            revocations.apply(revoked);
+        }
+        None => {
+            AuthEvent::failure(AuthEventKind::Logout, None, "invalid_token")
+                .record(&pool, &client)
+                .await?
+        }
+    }
 
-    Ok(Json(SuccessResponse {
-        message: "Successfully logged out".to_string(),
-    }))
+    Ok((
+        cookies.clear(),
+        Json(SuccessResponse {
+            message: "Successfully logged out".to_string(),
+        }),
+    ))
 }
@@ -1,10 +1,24 @@
 pub mod auth;
+pub mod error;
 pub mod handlers;
+pub mod mail;
 pub mod models;
 pub mod routes;
 
+use auth::{
+    account_deletion::{DeletionPolicy, SharedDeletionPolicy},
+    cookies::{CookieConfig, SharedCookieConfig},
+    keys::{JwtKeys, SharedJwtKeys},
+    lockout::{LockoutPolicy, SharedLockoutPolicy},
+    oidc::{OidcProviders, SharedOidcProviders},
+    password_policy::{PasswordPolicy, SharedPasswordPolicy},
+    providers::SharedAuthProvider,
+    revocation::{RevocationStore, SharedRevocationStore},
+};
 use axum::{Extension, Router, http::HeaderValue, routing::get};
+use mail::SharedMailer;
 use sqlx::PgPool;
+use std::sync::Arc;
 use tower_http::cors::{AllowHeaders, AllowMethods, CorsLayer};
 
 async fn root(Extension(pool): Extension<PgPool>) -> impl axum::response::IntoResponse {
@@ -27,21 +41,94 @@ async fn root(Extension(pool): Extension<PgPool>) -> impl axum::response::IntoRe
     )
 }
 
+/// Outside dependencies the app is built with, so tests can swap them out.
+pub struct Services {
+    pub mailer: SharedMailer,
+    pub jwt_keys: SharedJwtKeys,
+    pub password_policy: SharedPasswordPolicy,
+    pub cookies: SharedCookieConfig,
+    pub oidc: SharedOidcProviders,
+    pub revocations: SharedRevocationStore,
+    pub auth_provider: SharedAuthProvider,
+    pub deletion: SharedDeletionPolicy,
+    pub lockout: SharedLockoutPolicy,
+}
+
+/// The front end's origin, for links in emails.
+#[derive(Clone)]
+pub struct FrontendOrigin(pub Arc<str>);
+
+impl Services {
+    pub fn from_env() -> Self {
+        Services {
+            mailer: mail::from_env(),
+            jwt_keys: Arc::new(JwtKeys::from_env()),
+            password_policy: Arc::new(PasswordPolicy::from_env()),
+            cookies: Arc::new(CookieConfig::from_env()),
+            oidc: Arc::new(OidcProviders::from_env()),
+            revocations: Arc::new(RevocationStore::from_env()),
+            auth_provider: auth::providers::from_env(),
+            deletion: Arc::new(DeletionPolicy::from_env()),
+            lockout: Arc::new(LockoutPolicy::from_env()),
+        }
+    }
+}
+
 pub fn create_app(pool: PgPool) -> Router {
-    let origin = std::env::var("FRONTEND_ORIGIN")
-        .expect("FRONTEND_ORIGIN must be set")
+    create_app_with_services(pool, Services::from_env())
+}
+
+pub fn create_app_with_mailer(pool: PgPool, mailer: SharedMailer) -> Router {
+    create_app_with_services(
+        pool,
+        Services {
+            mailer,
+            jwt_keys: Arc::new(JwtKeys::from_env()),
+            password_policy: Arc::new(PasswordPolicy::from_env()),
+            cookies: Arc::new(CookieConfig::from_env()),
+            oidc: Arc::new(OidcProviders::from_env()),
+            revocations: Arc::new(RevocationStore::from_env()),
+            auth_provider: auth::providers::from_env(),
+            deletion: Arc::new(DeletionPolicy::from_env()),
+            lockout: Arc::new(LockoutPolicy::from_env()),
+        },
+    )
+}
+
+pub fn create_app_with_services(pool: PgPool, services: Services) -> Router {
+    let frontend_origin = std::env::var("FRONTEND_ORIGIN").expect("FRONTEND_ORIGIN must be set");
+    let origin = frontend_origin
         .parse::<HeaderValue>()
         .expect("FRONTEND_ORIGIN is not a valid header value");
 
-    let cors = CorsLayer::new()
-        .allow_origin(origin)
-        .allow_methods(AllowMethods::any())
-        .allow_headers(AllowHeaders::any());
+    let cors = CorsLayer::new().allow_origin(origin);
+    // Browsers only send cookies cross-origin with credentials allowed, which rules out wildcards.
+    let cors = if services.cookies.enabled {
+        cors.allow_credentials(true)
+            .allow_methods(AllowMethods::mirror_request())
+            .allow_headers(AllowHeaders::mirror_request())
+    } else {
+        cors.allow_methods(AllowMethods::any())
+            .allow_headers(AllowHeaders::any())
+    };
 
     Router::new()
         .route("/", get(root))
         .merge(routes::posts::posts_routes())
         .merge(routes::users::users_routes())
+        .merge(routes::auth::auth_routes())
+        .merge(routes::admin::admin_routes())
+        .merge(routes::oauth::oauth_routes())
         .layer(cors)
         .layer(Extension(pool))
+        .layer(Extension(services.mailer))
+        .layer(Extension(services.jwt_keys))
+        .layer(Extension(services.password_policy))
+        .layer(Extension(services.cookies))
+        .layer(Extension(services.oidc))
+        .layer(Extension(services.revocations))
+        .layer(Extension(services.auth_provider))
+        .layer(Extension(services.deletion))
+        .layer(Extension(services.lockout))
+        .layer(Extension(FrontendOrigin(frontend_origin.into())))
 }
@@ -0,0 +1,61 @@
+use crate::mail::{Email, MailError, Mailer};
+use async_trait::async_trait;
+use std::path::PathBuf;
+
+/// Writes each email to its own file in `dir` instead of delivering it, for local development.
+pub struct FileMailer {
+    dir: PathBuf,
+}
+
+impl FileMailer {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+}
+
+#[async_trait]
+impl Mailer for FileMailer {
+    async fn send(&self, email: Email) -> Result<(), MailError> {
+        tokio::fs::create_dir_all(&self.dir)
+            .await
+            .map_err(|e| MailError(e.to_string()))?;
+
+        let file_name = format!("{}.eml", chrono::Utc::now().format("%Y%m%dT%H%M%S%.6f"));
+        let contents = format!(
+            "To: {}\nSubject: {}\n\n{}\n",
+            email.to, email.subject, email.body
+        );
+
+        tokio::fs::write(self.dir.join(file_name), contents)
+            .await
+            .map_err(|e| MailError(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn writes_email_to_directory() {
+        let dir = std::env::temp_dir().join(format!("file-mailer-{}", std::process::id()));
+        let mailer = FileMailer::new(&dir);
+
+        mailer
+            .send(Email {
+                to: "alice@example.com".to_string(),
+                subject: "Hello".to_string(),
+                body: "World".to_string(),
+            })
+            .await
+            .unwrap();
+
+        let mut entries = std::fs::read_dir(&dir).unwrap();
+        let path = entries.next().unwrap().unwrap().path();
+        let contents = std::fs::read_to_string(path).unwrap();
+        assert!(contents.contains("To: alice@example.com"));
+        assert!(contents.contains("World"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,31 @@
+use crate::mail::{Email, MailError, Mailer};
+use async_trait::async_trait;
+use std::sync::{Arc, Mutex};
+
+/// Keeps every sent email in memory; clones share the same outbox, which makes it handy in tests.
+#[derive(Clone, Default)]
+pub struct InMemoryMailer {
+    outbox: Arc<Mutex<Vec<Email>>>,
+}
+
+impl InMemoryMailer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn sent(&self) -> Vec<Email> {
+        self.outbox.lock().unwrap().clone()
+    }
+
+    pub fn last_to(&self, to: &str) -> Option<Email> {
+        self.sent().into_iter().rev().find(|email| email.to == to)
+    }
+}
+
+#[async_trait]
+impl Mailer for InMemoryMailer {
+    async fn send(&self, email: Email) -> Result<(), MailError> {
+        self.outbox.lock().unwrap().push(email);
+        Ok(())
+    }
+}
@@ -0,0 +1,54 @@
+pub mod file;
+pub mod memory;
+pub mod smtp;
+
+use crate::error::AppError;
+use async_trait::async_trait;
+use std::{fmt, sync::Arc};
+
+pub use file::FileMailer;
+pub use memory::InMemoryMailer;
+pub use smtp::SmtpMailer;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[derive(Debug)]
+pub struct MailError(pub String);
+
+impl fmt::Display for MailError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "failed to send email: {}", self.0)
+    }
+}
+
+impl std::error::Error for MailError {}
+
+impl From<MailError> for AppError {
+    fn from(e: MailError) -> Self {
+        AppError::Internal(e.to_string())
+    }
+}
+
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, email: Email) -> Result<(), MailError>;
+}
+
+pub type SharedMailer = Arc<dyn Mailer>;
+
+/// Builds the mailer selected by `MAIL_TRANSPORT` (`smtp` or `file`, defaulting to `file`).
+pub fn from_env() -> SharedMailer {
+    match std::env::var("MAIL_TRANSPORT").as_deref() {
+        Ok("smtp") => Arc::new(SmtpMailer::from_env()),
+        Ok("file") | Err(_) => {
+            let dir = std::env::var("MAIL_DIR").unwrap_or_else(|_| "mail_outbox".to_string());
+            Arc::new(FileMailer::new(dir))
+        }
+        Ok(other) => panic!("MAIL_TRANSPORT must be `smtp` or `file`, got `{other}`"),
+    }
+}
@@ -0,0 +1,65 @@
+use crate::mail::{Email, MailError, Mailer};
+use async_trait::async_trait;
+use lettre::{
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor, message::Mailbox,
+    transport::smtp::authentication::Credentials,
+};
+
+pub struct SmtpMailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl SmtpMailer {
+    pub fn new(transport: AsyncSmtpTransport<Tokio1Executor>, from: Mailbox) -> Self {
+        Self { transport, from }
+    }
+
+    /// Reads `SMTP_HOST`, `SMTP_PORT`, `SMTP_USERNAME`, `SMTP_PASSWORD` and `MAIL_FROM`.
+    pub fn from_env() -> Self {
+        let host = std::env::var("SMTP_HOST").expect("SMTP_HOST must be set");
+        let from = std::env::var("MAIL_FROM")
+            .expect("MAIL_FROM must be set")
+            .parse::<Mailbox>()
+            .expect("MAIL_FROM is not a valid mailbox");
+
+        let mut builder = AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&host)
+            .expect("Failed to configure SMTP transport");
+
+        if let Ok(port) = std::env::var("SMTP_PORT") {
+            builder = builder.port(port.parse().expect("SMTP_PORT must be a number"));
+        }
+
+        if let (Ok(username), Ok(password)) = (
+            std::env::var("SMTP_USERNAME"),
+            std::env::var("SMTP_PASSWORD"),
+        ) {
+            builder = builder.credentials(Credentials::new(username, password));
+        }
+
+        Self::new(builder.build(), from)
+    }
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, email: Email) -> Result<(), MailError> {
+        let to = email
+            .to
+            .parse::<Mailbox>()
+            .map_err(|e| MailError(e.to_string()))?;
+
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(to)
+            .subject(email.subject)
+            .body(email.body)
+            .map_err(|e| MailError(e.to_string()))?;
+
+        self.transport
+            .send(message)
+            .await
+            .map(|_| ())
+            .map_err(|e| MailError(e.to_string()))
+    }
+}
@@ -1,6 +1,10 @@
 use dotenvy::dotenv;
-use rust_axum_rest_api::create_app;
+use rust_axum_rest_api::{
+    auth::account_deletion::{DeletionPolicy, spawn_purge_task},
+    create_app,
+};
 use sqlx::postgres::PgPoolOptions;
+use std::net::SocketAddr;
 use tracing::{Level, info};
 
 #[tokio::main]
@@ -16,9 +20,16 @@ async fn main() -> Result<(), sqlx::Error> {
         .expect("Failed to connect to the database");
     info!("Connected to the database");
 
+    spawn_purge_task(pool.clone(), DeletionPolicy::from_env());
+
     let listener = tokio::net::TcpListener::bind("0.0.0.0:5000").await.unwrap();
     info!("Listening on http://0.0.0.0:5000");
-    axum::serve(listener, create_app(pool)).await.unwrap();
+    axum::serve(
+        listener,
+        create_app(pool).into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    .unwrap();
 
     Ok(())
 }
@@ -0,0 +1,33 @@
+use crate::auth::audit::{AuthEventKind, AuthEventOutcome};
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+#[derive(Serialize, FromRow)]
+pub struct AuthEventRecord {
+    pub id: i32,
+    pub user_id: Option<i32>,
+    pub event_type: AuthEventKind,
+    pub outcome: AuthEventOutcome,
+    pub detail: Option<String>,
+    pub username: Option<String>,
+    pub actor_id: Option<i32>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+/// Filters for `GET /admin/auth-events`; all optional, combined with AND.
+#[derive(Deserialize, Default)]
+pub struct AuthEventQuery {
+    pub user_id: Option<i32>,
+    pub event_type: Option<AuthEventKind>,
+    pub outcome: Option<AuthEventOutcome>,
+    pub ip_address: Option<String>,
+    /// Only events at or after this time (UTC, e.g. `2026-10-18T09:00:00`).
+    pub since: Option<NaiveDateTime>,
+    /// Only events before this time.
+    pub until: Option<NaiveDateTime>,
+    pub cursor: Option<String>,
+    pub limit: Option<i64>,
+}
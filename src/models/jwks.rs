@@ -0,0 +1,26 @@
+use serde::Serialize;
+
+/// Public half of a signing key, as published at `/.well-known/jwks.json`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Jwk {
+    #[serde(flatten)]
+    pub params: JwkParams,
+    pub kid: String,
+    pub alg: &'static str,
+    #[serde(rename = "use")]
+    pub key_use: &'static str,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kty")]
+pub enum JwkParams {
+    #[serde(rename = "RSA")]
+    Rsa { n: String, e: String },
+    #[serde(rename = "OKP")]
+    Okp { crv: &'static str, x: String },
+}
+
+#[derive(Serialize)]
+pub struct JwkSet {
+    pub keys: Vec<Jwk>,
+}
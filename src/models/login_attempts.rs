@@ -0,0 +1,12 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use sqlx::FromRow;
+
+#[derive(Serialize, FromRow)]
+pub struct LoginAttempt {
+    pub id: i64,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub succeeded: bool,
+    pub created_at: NaiveDateTime,
+}
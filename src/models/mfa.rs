@@ -0,0 +1,38 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize)]
+pub struct MfaEnrollResponse {
+    pub secret: String,
+    pub otpauth_uri: String,
+}
+
+#[derive(Deserialize)]
+pub struct MfaConfirmRequest {
+    pub code: String,
+}
+
+#[derive(Serialize)]
+pub struct MfaConfirmResponse {
+    pub recovery_codes: Vec<String>,
+}
+
+/// Returned by `login` instead of tokens when the account has two-factor authentication enabled.
+#[derive(Serialize)]
+pub struct MfaChallenge {
+    pub mfa_required: bool,
+    pub mfa_token: String,
+}
+
+#[derive(Deserialize)]
+pub struct MfaVerifyRequest {
+    pub mfa_token: String,
+    pub code: Option<String>,
+    pub recovery_code: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct DisableMfaRequest {
+    pub password: Option<String>,
+    /// A current TOTP code, for accounts that sign in without a password.
+    pub code: Option<String>,
+}
@@ -4,7 +4,16 @@ use serde::Serialize;
 pub struct ErrorResponse {
     pub error: String,
     pub message: String,
-    pub details: Option<String>,
+    pub details: Option<Vec<FieldError>>,
+}
+
+/// One problem with one request field, so clients can show it next to the right input.
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldError {
+    pub field: String,
+    /// Stable machine-readable reason, such as `too_short` or `breached`.
+    pub code: String,
+    pub message: String,
 }
 
 #[derive(Serialize)]
@@ -12,5 +21,14 @@ pub struct SuccessResponse {
     pub message: String,
 }
 
+pub mod api_keys;
+pub mod auth_events;
+pub mod jwks;
+pub mod login_attempts;
+pub mod mfa;
+pub mod oauth;
+pub mod oidc;
+pub mod pagination;
 pub mod posts;
+pub mod sessions;
 pub mod users;
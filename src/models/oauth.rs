@@ -0,0 +1,123 @@
+use crate::auth::scopes::Scope;
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+#[derive(Serialize, FromRow)]
+pub struct OAuthClient {
+    pub id: Uuid,
+    pub name: String,
+    pub redirect_uris: Vec<String>,
+    pub scopes: Vec<Scope>,
+    /// Whether the client was issued a secret. Public clients rely on PKCE alone.
+    pub confidential: bool,
+    pub created_at: NaiveDateTime,
+}
+
+fn default_confidential() -> bool {
+    true
+}
+
+#[derive(Deserialize)]
+pub struct CreateOAuthClient {
+    pub name: String,
+    pub redirect_uris: Vec<String>,
+    pub scopes: Vec<Scope>,
+    #[serde(default = "default_confidential")]
+    pub confidential: bool,
+}
+
+/// The only response that ever contains the client secret.
+#[derive(Serialize)]
+pub struct CreatedOAuthClient {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_secret: Option<String>,
+    #[serde(flatten)]
+    pub client: OAuthClient,
+}
+
+/// The authorization request a client sent the user's browser to `/oauth/authorize` with.
+#[derive(Deserialize)]
+pub struct AuthorizationRequest {
+    pub response_type: String,
+    pub client_id: Uuid,
+    pub redirect_uri: String,
+    pub scope: Option<String>,
+    pub state: Option<String>,
+    pub code_challenge: Option<String>,
+    pub code_challenge_method: Option<String>,
+}
+
+/// What the consent screen shows the user before they approve or deny.
+#[derive(Serialize)]
+pub struct ConsentDetails {
+    pub client_id: Uuid,
+    pub client_name: String,
+    pub redirect_uri: String,
+    pub scope: String,
+}
+
+/// The user's answer on the consent screen, alongside the request it answers.
+#[derive(Deserialize)]
+pub struct ConsentDecision {
+    #[serde(flatten)]
+    pub request: AuthorizationRequest,
+    pub approved: bool,
+}
+
+/// Where the front end should send the browser next: back to the client with a code, or with
+/// an error if the user said no.
+#[derive(Serialize)]
+pub struct AuthorizationRedirect {
+    pub redirect_to: String,
+}
+
+/// The form body of `/oauth/token`. Which fields are required depends on `grant_type`.
+#[derive(Deserialize)]
+pub struct TokenRequest {
+    pub grant_type: String,
+    pub code: Option<String>,
+    pub redirect_uri: Option<String>,
+    pub code_verifier: Option<String>,
+    pub refresh_token: Option<String>,
+    pub scope: Option<String>,
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct TokenResponse {
+    pub access_token: String,
+    pub token_type: &'static str,
+    pub expires_in: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+    pub scope: String,
+}
+
+/// The form body of the introspection (RFC 7662) and revocation (RFC 7009) endpoints.
+#[derive(Deserialize)]
+pub struct TokenLookupRequest {
+    pub token: String,
+    pub token_type_hint: Option<String>,
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+}
+
+#[derive(Serialize, Default)]
+pub struct IntrospectionResponse {
+    pub active: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<Uuid>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sub: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_type: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exp: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iat: Option<i64>,
+}
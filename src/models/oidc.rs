@@ -0,0 +1,30 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+#[derive(Deserialize)]
+pub struct OidcAuthorizeRequest {
+    /// Narrower scope for the session, as accepted by `login`.
+    pub scope: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct OidcAuthorizeResponse {
+    pub authorization_url: String,
+}
+
+/// The query parameters the provider redirected the browser back with.
+#[derive(Deserialize)]
+pub struct OidcCallbackRequest {
+    pub code: String,
+    pub state: String,
+}
+
+/// An external account linked to the signed-in user.
+#[derive(Serialize, FromRow)]
+pub struct UserIdentity {
+    pub provider: String,
+    pub email: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub last_login_at: Option<NaiveDateTime>,
+}
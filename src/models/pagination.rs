@@ -0,0 +1,140 @@
+use crate::error::{AppError, AppResult};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use chrono::{DateTime, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_PAGE_LIMIT: i64 = 20;
+pub const MAX_PAGE_LIMIT: i64 = 100;
+
+#[derive(Deserialize, Default)]
+pub struct PageParams {
+    pub cursor: Option<String>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub struct Page<T> {
+    pub data: Vec<T>,
+    pub next_cursor: Option<String>,
+    pub has_more: bool,
+}
+
+/// Keyset position in a list ordered by `created_at DESC, id DESC`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cursor {
+    pub created_at: NaiveDateTime,
+    pub id: i32,
+}
+
+impl Cursor {
+    pub fn encode(&self) -> String {
+        let raw = format!(
+            "{}:{}",
+            self.created_at.and_utc().timestamp_micros(),
+            self.id
+        );
+        URL_SAFE_NO_PAD.encode(raw)
+    }
+
+    pub fn decode(cursor: &str) -> Option<Self> {
+        let raw = String::from_utf8(URL_SAFE_NO_PAD.decode(cursor).ok()?).ok()?;
+        let (micros, id) = raw.split_once(':')?;
+        let created_at = DateTime::from_timestamp_micros(micros.parse().ok()?)?.naive_utc();
+        Some(Cursor {
+            created_at,
+            id: id.parse().ok()?,
+        })
+    }
+}
+
+impl PageParams {
+    /// Validates the query parameters, returning the decoded cursor and the effective limit.
+    pub fn resolve(&self) -> AppResult<(Option<Cursor>, i64)> {
+        let limit = self.limit.unwrap_or(DEFAULT_PAGE_LIMIT);
+        if !(1..=MAX_PAGE_LIMIT).contains(&limit) {
+            return Err(AppError::Validation(format!(
+                "limit must be between 1 and {MAX_PAGE_LIMIT}"
+            )));
+        }
+
+        let cursor = self
+            .cursor
+            .as_deref()
+            .map(|c| {
+                Cursor::decode(c).ok_or_else(|| AppError::Validation("Invalid cursor".to_string()))
+            })
+            .transpose()?;
+
+        Ok((cursor, limit))
+    }
+}
+
+impl<T> Page<T> {
+    /// Builds a page from rows fetched with `LIMIT limit + 1`; the extra row only signals `has_more`.
+    pub fn from_rows(mut rows: Vec<T>, limit: i64, cursor_of: impl Fn(&T) -> Cursor) -> Self {
+        let has_more = rows.len() as i64 > limit;
+        rows.truncate(limit as usize);
+
+        let next_cursor = if has_more {
+            rows.last().map(|row| cursor_of(row).encode())
+        } else {
+            None
+        };
+
+        Page {
+            data: rows,
+            next_cursor,
+            has_more,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cursor(id: i32) -> Cursor {
+        Cursor {
+            created_at: DateTime::from_timestamp_micros(1_700_000_000_123_456)
+                .unwrap()
+                .naive_utc(),
+            id,
+        }
+    }
+
+    #[test]
+    fn cursor_round_trips() {
+        let c = cursor(42);
+        assert_eq!(Cursor::decode(&c.encode()), Some(c));
+    }
+
+    #[test]
+    fn cursor_rejects_garbage() {
+        assert_eq!(Cursor::decode("not a cursor"), None);
+        assert_eq!(Cursor::decode(&URL_SAFE_NO_PAD.encode("abc:def")), None);
+    }
+
+    #[test]
+    fn resolve_applies_default_and_max_limit() {
+        let (_, limit) = PageParams::default().resolve().unwrap();
+        assert_eq!(limit, DEFAULT_PAGE_LIMIT);
+
+        let params = PageParams {
+            cursor: None,
+            limit: Some(MAX_PAGE_LIMIT + 1),
+        };
+        assert!(matches!(params.resolve(), Err(AppError::Validation(_))));
+    }
+
+    #[test]
+    fn from_rows_trims_extra_row_and_sets_cursor() {
+        let page = Page::from_rows(vec![3, 2, 1], 2, |id| cursor(*id));
+        assert_eq!(page.data, vec![3, 2]);
+        assert!(page.has_more);
+        assert_eq!(page.next_cursor, Some(cursor(2).encode()));
+
+        let page = Page::from_rows(vec![1], 2, |id| cursor(*id));
+        assert!(!page.has_more);
+        assert_eq!(page.next_cursor, None);
+    }
+}
@@ -22,3 +22,20 @@ pub struct UpdatePost {
     pub title: Option<String>,
     pub body: Option<String>,
 }
+
+#[derive(Deserialize)]
+pub struct SearchParams {
+    pub q: String,
+    pub limit: Option<i64>,
+}
+
+#[derive(Serialize, FromRow)]
+pub struct PostSearchResult {
+    pub id: i32,
+    pub title: String,
+    pub user_id: i32,
+    pub created_at: NaiveDateTime,
+    pub rank: f32,
+    /// Matching fragments of the HTML-escaped body with search terms wrapped in `<mark>` tags.
+    pub snippet: String,
+}
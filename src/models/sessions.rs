@@ -0,0 +1,17 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use sqlx::FromRow;
+use uuid::Uuid;
+
+/// A signed-in device or an application granted access, i.e. one refresh token family.
+#[derive(Serialize, FromRow)]
+pub struct Session {
+    pub id: Uuid,
+    /// The third-party application the session was granted to, if it isn't a login.
+    pub client_name: Option<String>,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    pub signed_in_at: NaiveDateTime,
+    pub last_used_at: Option<NaiveDateTime>,
+    pub expires_at: NaiveDateTime,
+}
@@ -1,3 +1,8 @@
+use crate::{
+    auth::{password_hasher::password_hasher, roles::Role},
+    error::AppError,
+    models::mfa::MfaChallenge,
+};
 use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
@@ -10,6 +15,9 @@ pub struct User {
     pub created_at: NaiveDateTime,
     #[serde(skip_serializing)]
     pub password_hash: Option<String>,
+    pub role: Role,
+    pub email_verified_at: Option<NaiveDateTime>,
+    pub totp_enabled_at: Option<NaiveDateTime>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -25,19 +33,83 @@ pub struct UpdateUser {
     pub email: Option<String>,
 }
 
+#[derive(Serialize, Deserialize)]
+pub struct AdminUpdateUser {
+    pub username: Option<String>,
+    pub email: Option<String>,
+    pub role: Option<Role>,
+}
+
+#[derive(Deserialize)]
+pub struct VerifyEmailRequest {
+    pub token: String,
+}
+
+#[derive(Deserialize)]
+pub struct ResendVerificationRequest {
+    pub email: String,
+}
+
+#[derive(Deserialize)]
+pub struct ForgotPasswordRequest {
+    pub email: String,
+}
+
+#[derive(Deserialize)]
+pub struct RestoreAccountRequest {
+    pub token: String,
+}
+
+#[derive(Deserialize)]
+pub struct MagicLinkRequest {
+    pub email: String,
+}
+
+#[derive(Deserialize)]
+pub struct MagicLinkVerifyRequest {
+    pub token: String,
+    /// Space-delimited scopes to narrow the session to, as accepted by `login`.
+    pub scope: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ResetPasswordRequest {
+    pub token: String,
+    pub new_password: String,
+}
+
+#[derive(Deserialize)]
+pub struct ChangePasswordRequest {
+    pub current_password: String,
+    pub new_password: String,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct LoginRequest {
     pub username: String,
     pub password: String,
+    /// Space-delimited scopes to narrow the session to; omit for full access.
+    pub scope: Option<String>,
 }
 
 #[derive(Serialize)]
 pub struct LoginResponse {
-    pub access_token: String,
-    pub refresh_token: String,
+    /// Left out in cookie mode, where the tokens travel as HttpOnly cookies instead.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub access_token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+    pub scope: String,
     pub user: UserSafe,
 }
 
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum LoginOutcome {
+    Authenticated(LoginResponse),
+    MfaRequired(MfaChallenge),
+}
+
 #[derive(Deserialize)]
 pub struct RefreshRequest {
     pub refresh_token: String,
@@ -45,8 +117,21 @@ pub struct RefreshRequest {
 
 #[derive(Serialize)]
 pub struct RefreshResponse {
+    /// Left out in cookie mode, where the tokens travel as HttpOnly cookies instead.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub access_token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+    pub scope: String,
+}
+
+#[derive(Serialize)]
+pub struct ImpersonationResponse {
     pub access_token: String,
-    pub refresh_token: String,
+    /// Seconds until the token expires; it can't be refreshed.
+    pub expires_in: u64,
+    pub scope: String,
+    pub user: UserSafe,
 }
 
 #[derive(Serialize, Deserialize, FromRow)]
@@ -69,17 +154,32 @@ impl From<User> for UserSafe {
 }
 
 impl User {
-    pub fn hash_password(password: &str) -> Result<String, bcrypt::BcryptError> {
-        bcrypt::hash(password, bcrypt::DEFAULT_COST)
+    pub fn hash_password(password: &str) -> Result<String, AppError> {
+        password_hasher().hash(password)
     }
 
     pub fn verify_password(&self, password: &str) -> bool {
         if let Some(ref hash) = self.password_hash {
-            bcrypt::verify(password, hash).unwrap_or(false)
+            password_hasher().verify(password, hash)
         } else {
             false
         }
     }
+
+    /// Whether the account has a password at all, rather than signing in only through an
+    /// identity provider, the directory or emailed links.
+    pub fn has_password(&self) -> bool {
+        self.password_hash
+            .as_deref()
+            .is_some_and(|hash| !hash.is_empty())
+    }
+
+    /// Whether the stored hash uses an outdated algorithm or parameters.
+    pub fn password_needs_rehash(&self) -> bool {
+        self.password_hash
+            .as_deref()
+            .is_some_and(|hash| password_hasher().needs_rehash(hash))
+    }
 }
 
 #[cfg(test)]
@@ -92,13 +192,17 @@ mod tests {
             email: "test@example.com".to_string(),
             created_at: chrono::DateTime::from_timestamp(0, 0).unwrap().naive_utc(),
             password_hash,
+            role: Role::User,
+            email_verified_at: None,
+            totp_enabled_at: None,
         }
     }
 
     #[test]
     fn hash_password_produces_verifiable_hash() {
         let hash = User::hash_password("secret").unwrap();
-        assert!(bcrypt::verify("secret", &hash).unwrap());
+        assert!(hash.starts_with("$argon2id$"));
+        assert!(make_user(Some(hash)).verify_password("secret"));
     }
 
     #[test]
@@ -121,12 +225,29 @@ mod tests {
         assert!(!user.verify_password("wrong"));
     }
 
+    #[test]
+    fn legacy_bcrypt_password_verifies_and_needs_rehash() {
+        let user = make_user(Some(bcrypt::hash("hunter2", 4).unwrap()));
+        assert!(user.verify_password("hunter2"));
+        assert!(user.password_needs_rehash());
+
+        let user = make_user(Some(User::hash_password("hunter2").unwrap()));
+        assert!(!user.password_needs_rehash());
+    }
+
     #[test]
     fn verify_password_no_hash() {
         let user = make_user(None);
         assert!(!user.verify_password("anything"));
     }
 
+    #[test]
+    fn empty_hash_is_no_password() {
+        assert!(!make_user(Some(String::new())).has_password());
+        assert!(!make_user(None).has_password());
+        assert!(make_user(Some("hash".to_string())).has_password());
+    }
+
     #[test]
     fn user_safe_from_user() {
         let user = make_user(Some("hash".to_string()));
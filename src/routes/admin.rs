@@ -0,0 +1,20 @@
+use crate::handlers::admin::{
+    delete_user, impersonate_user, list_auth_events, list_login_attempts, list_users, restore_user,
+    unlock_user, update_user,
+};
+use axum::{
+    Router,
+    routing::{delete, get, post, put},
+};
+
+pub fn admin_routes() -> Router {
+    Router::new()
+        .route("/admin/users", get(list_users))
+        .route("/admin/users/{id}", put(update_user))
+        .route("/admin/users/{id}", delete(delete_user))
+        .route("/admin/users/{id}/login-attempts", get(list_login_attempts))
+        .route("/admin/users/{id}/unlock", post(unlock_user))
+        .route("/admin/users/{id}/impersonate", post(impersonate_user))
+        .route("/admin/users/{id}/restore", post(restore_user))
+        .route("/admin/auth-events", get(list_auth_events))
+}
@@ -0,0 +1,37 @@
+use crate::handlers::{
+    account_deletion::restore_account,
+    api_keys::{create_api_key, list_api_keys, revoke_api_key},
+    email_verification::{resend_verification, verify_email},
+    jwks::jwks,
+    magic_link::{request_magic_link, verify_magic_link},
+    mfa, oidc,
+    password::{forgot_password, reset_password},
+    sessions::{list_sessions, logout_all, revoke_session},
+};
+use axum::{
+    Router,
+    routing::{delete, get, post},
+};
+
+pub fn auth_routes() -> Router {
+    Router::new()
+        .route("/.well-known/jwks.json", get(jwks))
+        .route("/auth/verify-email", post(verify_email))
+        .route("/auth/resend-verification", post(resend_verification))
+        .route("/auth/forgot-password", post(forgot_password))
+        .route("/auth/reset-password", post(reset_password))
+        .route("/auth/magic-link", post(request_magic_link))
+        .route("/auth/magic-link/verify", post(verify_magic_link))
+        .route("/auth/restore-account", post(restore_account))
+        .route("/auth/sessions", get(list_sessions))
+        .route("/auth/sessions/{id}", delete(revoke_session))
+        .route("/auth/logout-all", post(logout_all))
+        .route("/auth/mfa/enroll", post(mfa::enroll))
+        .route("/auth/mfa/confirm", post(mfa::confirm))
+        .route("/auth/mfa/verify", post(mfa::verify))
+        .route("/auth/mfa", delete(mfa::disable))
+        .route("/auth/api-keys", get(list_api_keys).post(create_api_key))
+        .route("/auth/api-keys/{id}", delete(revoke_api_key))
+        .route("/auth/oidc/{provider}/authorize", post(oidc::authorize))
+        .route("/auth/oidc/{provider}/callback", post(oidc::callback))
+}
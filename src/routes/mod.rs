@@ -1,2 +1,5 @@
+pub mod admin;
+pub mod auth;
+pub mod oauth;
 pub mod posts;
 pub mod users;
@@ -0,0 +1,17 @@
+use crate::handlers::oauth::{
+    authorize, create_client, decide, delete_client, introspect, list_clients, revoke, token,
+};
+use axum::{
+    Router,
+    routing::{delete, get, post},
+};
+
+pub fn oauth_routes() -> Router {
+    Router::new()
+        .route("/oauth/clients", get(list_clients).post(create_client))
+        .route("/oauth/clients/{id}", delete(delete_client))
+        .route("/oauth/authorize", get(authorize).post(decide))
+        .route("/oauth/token", post(token))
+        .route("/oauth/introspect", post(introspect))
+        .route("/oauth/revoke", post(revoke))
+}
@@ -1,6 +1,6 @@
 use crate::handlers::posts::{
     create_post, delete_post, get_current_user_posts, get_post, get_posts, get_user_posts,
-    update_post,
+    search_posts, update_post,
 };
 use axum::{
     Router,
@@ -10,6 +10,7 @@ use axum::{
 pub fn posts_routes() -> Router {
     Router::new()
         .route("/posts", get(get_posts))
+        .route("/posts/search", get(search_posts))
         .route("/post/{id}", get(get_post))
         .route("/post", post(create_post))
         .route("/post/{id}", put(update_post))
@@ -1,6 +1,11 @@
-use crate::handlers::users::{
-    create_user, delete_user, get_current_user, get_user, get_users, login, logout, refresh,
-    update_user,
+use crate::handlers::{
+    oidc::list_identities,
+    password::change_password,
+    security_events::list_security_events,
+    users::{
+        create_user, delete_user, get_current_user, get_user, get_users, login, logout, refresh,
+        update_user,
+    },
 };
 use axum::{
     Router,
@@ -15,6 +20,9 @@ pub fn users_routes() -> Router {
         .route("/user", put(update_user))
         .route("/user", delete(delete_user))
         .route("/user", get(get_current_user))
+        .route("/user/password", put(change_password))
+        .route("/user/identities", get(list_identities))
+        .route("/user/security-events", get(list_security_events))
         .route("/auth/login", post(login))
         .route("/auth/refresh", post(refresh))
         .route("/auth/logout", post(logout))
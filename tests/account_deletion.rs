@@ -0,0 +1,297 @@
+mod common;
+
+use rust_axum_rest_api::{
+    Services,
+    auth::account_deletion::{DeletionPolicy, purge_deleted_accounts},
+};
+use serde_json::{Value, json};
+use sqlx::PgPool;
+use std::sync::Arc;
+
+fn bearer(session: &Value) -> String {
+    format!("Bearer {}", session["access_token"].as_str().unwrap())
+}
+
+async fn user_id(pool: &PgPool, username: &str) -> i32 {
+    sqlx::query_scalar("SELECT id FROM users WHERE username = $1")
+        .bind(username)
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+/// Registers alice with a post, then has her delete her account.
+async fn delete_alice(server: &axum_test::TestServer, pool: &PgPool) -> i32 {
+    common::register(server, pool, "alice", common::PASSWORD).await;
+    let session = common::login(server, "alice", common::PASSWORD).await;
+    server
+        .post("/post")
+        .add_header("Authorization", bearer(&session))
+        .json(&json!({ "title": "Hello", "body": "World" }))
+        .await
+        .assert_status_ok();
+    server
+        .delete("/user")
+        .add_header("Authorization", bearer(&session))
+        .await
+        .assert_status_ok();
+    user_id(pool, "alice").await
+}
+
+async fn backdate_deletion(pool: &PgPool, user_id: i32, days: i64) {
+    sqlx::query(
+        "UPDATE users SET deleted_at = deleted_at - make_interval(days => $2) WHERE id = $1",
+    )
+    .bind(user_id)
+    .bind(days as i32)
+    .execute(pool)
+    .await
+    .unwrap();
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn deleted_account_is_hidden_but_kept(pool: PgPool) {
+    let server = common::server(pool.clone());
+    let alice = delete_alice(&server, &pool).await;
+    let bob = common::insert_user(&pool, "bob", "user").await;
+
+    let res = server
+        .get("/users")
+        .add_header("Authorization", common::bearer(bob))
+        .await;
+    let body: Value = res.json();
+    assert!(
+        body["data"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .all(|u| u["id"] != alice)
+    );
+    let res = server
+        .get(&format!("/users/{alice}"))
+        .add_header("Authorization", common::bearer(bob))
+        .await;
+    assert_eq!(res.status_code(), 404);
+    server
+        .post("/post")
+        .add_header("Authorization", common::bearer(bob))
+        .json(&json!({ "title": "Still here", "body": "Bob's post" }))
+        .await
+        .assert_status_ok();
+    let res = server
+        .get("/posts")
+        .add_header("Authorization", common::bearer(bob))
+        .await;
+    let body: Value = res.json();
+    let data = body["data"].as_array().unwrap();
+    assert_eq!(data.len(), 1);
+    assert_eq!(data[0]["user_id"], bob);
+
+    let posts: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM posts WHERE user_id = $1")
+        .bind(alice)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(posts, 1);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn deletion_succeeds_when_the_restore_email_fails(pool: PgPool) {
+    let services = Services {
+        mailer: Arc::new(common::FailingMailer),
+        ..common::services()
+    };
+    let server = common::server_with_services(pool.clone(), services);
+    let alice = delete_alice(&server, &pool).await;
+
+    let deleted: bool =
+        sqlx::query_scalar("SELECT deleted_at IS NOT NULL FROM users WHERE id = $1")
+            .bind(alice)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert!(deleted);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn deleted_account_cannot_log_in(pool: PgPool) {
+    let server = common::server(pool.clone());
+    delete_alice(&server, &pool).await;
+
+    let res = server
+        .post("/auth/login")
+        .json(&json!({ "username": "alice", "password": common::PASSWORD }))
+        .await;
+
+    assert_eq!(res.status_code(), 401);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn deleted_account_is_not_the_current_user(pool: PgPool) {
+    let server = common::server(pool.clone());
+    let alice = delete_alice(&server, &pool).await;
+
+    // Signed after the deletion, so only the account's state keeps it out.
+    let res = server
+        .get("/user")
+        .add_header("Authorization", common::bearer(alice))
+        .await;
+
+    assert_eq!(res.status_code(), 404);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn deletion_drops_outstanding_verification_links(pool: PgPool) {
+    let server = common::server(pool.clone());
+    let alice = delete_alice(&server, &pool).await;
+
+    let links: i64 =
+        sqlx::query_scalar("SELECT COUNT(*) FROM email_verification_tokens WHERE user_id = $1")
+            .bind(alice)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+
+    assert_eq!(links, 0);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn emailed_link_restores_the_account(pool: PgPool) {
+    let (server, mailer) = common::server_with_mailer(pool.clone());
+    let alice = delete_alice(&server, &pool).await;
+    let token = common::token_from_last_email(&mailer, "alice@example.com");
+
+    server
+        .post("/auth/restore-account")
+        .json(&json!({ "token": token }))
+        .await
+        .assert_status_ok();
+
+    let session = common::login(&server, "alice", common::PASSWORD).await;
+    let res = server
+        .get("/user/posts")
+        .add_header("Authorization", bearer(&session))
+        .await;
+    let body: Value = res.json();
+    assert_eq!(body["data"][0]["user_id"], alice);
+
+    // Single use.
+    let res = server
+        .post("/auth/restore-account")
+        .json(&json!({ "token": token }))
+        .await;
+    assert_eq!(res.status_code(), 401);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn restore_link_expires_with_the_window(pool: PgPool) {
+    let (server, mailer) = common::server_with_mailer(pool.clone());
+    delete_alice(&server, &pool).await;
+    let token = common::token_from_last_email(&mailer, "alice@example.com");
+    sqlx::query("UPDATE account_restore_tokens SET expires_at = NOW() - INTERVAL '1 second'")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let res = server
+        .post("/auth/restore-account")
+        .json(&json!({ "token": token }))
+        .await;
+
+    assert_eq!(res.status_code(), 401);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn purge_only_removes_accounts_past_the_window(pool: PgPool) {
+    let server = common::server(pool.clone());
+    let alice = delete_alice(&server, &pool).await;
+    let policy = DeletionPolicy::default();
+
+    assert!(
+        purge_deleted_accounts(&pool, &policy)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+
+    backdate_deletion(&pool, alice, 31).await;
+    assert_eq!(
+        purge_deleted_accounts(&pool, &policy).await.unwrap(),
+        vec![alice]
+    );
+
+    let remaining: i64 =
+        sqlx::query_scalar("SELECT (SELECT COUNT(*) FROM users) + (SELECT COUNT(*) FROM posts)")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(remaining, 0);
+    let purged: i64 =
+        sqlx::query_scalar("SELECT COUNT(*) FROM auth_events WHERE event_type = 'account_purged'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(purged, 1);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn admin_deletion_can_only_be_undone_by_an_admin(pool: PgPool) {
+    let (server, mailer) = common::server_with_mailer(pool.clone());
+    let admin_id = common::insert_user(&pool, "root", "admin").await;
+    common::register(&server, &pool, "alice", common::PASSWORD).await;
+    let alice = user_id(&pool, "alice").await;
+
+    server
+        .delete(&format!("/admin/users/{alice}"))
+        .add_header("Authorization", common::admin_bearer(admin_id))
+        .await
+        .assert_status_ok();
+    assert!(
+        mailer
+            .last_to("alice@example.com")
+            .is_none_or(|email| !email.body.contains("restore-account"))
+    );
+
+    server
+        .post(&format!("/admin/users/{alice}/restore"))
+        .add_header("Authorization", common::admin_bearer(admin_id))
+        .await
+        .assert_status_ok();
+    common::login(&server, "alice", common::PASSWORD).await;
+
+    // Nothing left to restore.
+    let res = server
+        .post(&format!("/admin/users/{alice}/restore"))
+        .add_header("Authorization", common::admin_bearer(admin_id))
+        .await;
+    assert_eq!(res.status_code(), 404);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn api_keys_stop_working_while_deleted(pool: PgPool) {
+    let server = common::server(pool.clone());
+    common::register(&server, &pool, "alice", common::PASSWORD).await;
+    let session = common::login(&server, "alice", common::PASSWORD).await;
+    let res = server
+        .post("/auth/api-keys")
+        .add_header("Authorization", bearer(&session))
+        .json(&json!({ "name": "ci", "scopes": ["posts:read"] }))
+        .await;
+    res.assert_status_ok();
+    let key: Value = res.json();
+    server
+        .delete("/user")
+        .add_header("Authorization", bearer(&session))
+        .await
+        .assert_status_ok();
+
+    let res = server
+        .get("/posts")
+        .add_header(
+            "Authorization",
+            format!("Bearer {}", key["key"].as_str().unwrap()),
+        )
+        .await;
+
+    assert_eq!(res.status_code(), 401);
+}
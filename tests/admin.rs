@@ -0,0 +1,162 @@
+mod common;
+
+use serde_json::{Value, json};
+use sqlx::PgPool;
+
+#[sqlx::test(migrations = "./migrations")]
+async fn admin_routes_forbidden_for_regular_users(pool: PgPool) {
+    let server = common::server(pool.clone());
+    let user_id = common::insert_test_user(&pool).await;
+
+    let res = server
+        .get("/admin/users")
+        .add_header("Authorization", common::bearer(user_id))
+        .await;
+
+    assert_eq!(res.status_code(), 403);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn admin_lists_users_with_roles(pool: PgPool) {
+    let server = common::server(pool.clone());
+    let admin_id = common::insert_user(&pool, "root", "admin").await;
+    common::insert_test_user(&pool).await;
+
+    let res = server
+        .get("/admin/users")
+        .add_header("Authorization", common::admin_bearer(admin_id))
+        .await;
+
+    assert_eq!(res.status_code(), 200);
+    let body: Value = res.json();
+    let users = body["data"].as_array().unwrap();
+    assert_eq!(users.len(), 2);
+    let root = users.iter().find(|u| u["username"] == "root").unwrap();
+    let test = users.iter().find(|u| u["username"] == "testuser").unwrap();
+    assert_eq!(root["role"], "admin");
+    assert_eq!(test["role"], "user");
+    assert!(root.get("password_hash").is_none());
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn admin_can_promote_and_delete_users(pool: PgPool) {
+    let server = common::server(pool.clone());
+    let admin_id = common::insert_user(&pool, "root", "admin").await;
+    let user_id = common::insert_test_user(&pool).await;
+
+    let res = server
+        .put(&format!("/admin/users/{user_id}"))
+        .add_header("Authorization", common::admin_bearer(admin_id))
+        .json(&json!({ "role": "admin" }))
+        .await;
+
+    assert_eq!(res.status_code(), 200);
+    let body: Value = res.json();
+    assert_eq!(body["role"], "admin");
+
+    let res = server
+        .delete(&format!("/admin/users/{user_id}"))
+        .add_header("Authorization", common::admin_bearer(admin_id))
+        .await;
+    assert_eq!(res.status_code(), 200);
+
+    let res = server
+        .get(&format!("/users/{user_id}"))
+        .add_header("Authorization", common::admin_bearer(admin_id))
+        .await;
+    assert_eq!(res.status_code(), 404);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn changing_a_role_revokes_existing_tokens(pool: PgPool) {
+    let server = common::server(pool.clone());
+    let admin_id = common::insert_user(&pool, "root", "admin").await;
+    common::register(&server, &pool, "bob", common::PASSWORD).await;
+    sqlx::query("UPDATE users SET role = 'admin' WHERE username = 'bob'")
+        .execute(&pool)
+        .await
+        .unwrap();
+    let session = common::login(&server, "bob", common::PASSWORD).await;
+    let bob = session["user"]["id"].as_i64().unwrap();
+
+    server
+        .put(&format!("/admin/users/{bob}"))
+        .add_header("Authorization", common::admin_bearer(admin_id))
+        .json(&json!({ "role": "user" }))
+        .await
+        .assert_status_ok();
+
+    let res = server
+        .get("/admin/users")
+        .add_header(
+            "Authorization",
+            format!("Bearer {}", session["access_token"].as_str().unwrap()),
+        )
+        .await;
+    assert_eq!(res.status_code(), 401);
+    // Refreshing picks up the new role.
+    let res = server
+        .post("/auth/refresh")
+        .json(&json!({ "refresh_token": session["refresh_token"] }))
+        .await;
+    res.assert_status_ok();
+    let refreshed: Value = res.json();
+    let res = server
+        .get("/admin/users")
+        .add_header(
+            "Authorization",
+            format!("Bearer {}", refreshed["access_token"].as_str().unwrap()),
+        )
+        .await;
+    assert_eq!(res.status_code(), 403);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn deleted_users_cannot_be_edited(pool: PgPool) {
+    let server = common::server(pool.clone());
+    let admin_id = common::insert_user(&pool, "root", "admin").await;
+    let user_id = common::insert_test_user(&pool).await;
+    server
+        .delete(&format!("/admin/users/{user_id}"))
+        .add_header("Authorization", common::admin_bearer(admin_id))
+        .await
+        .assert_status_ok();
+
+    let res = server
+        .put(&format!("/admin/users/{user_id}"))
+        .add_header("Authorization", common::admin_bearer(admin_id))
+        .json(&json!({ "role": "admin" }))
+        .await;
+    assert_eq!(res.status_code(), 404);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn admin_can_edit_and_delete_any_post(pool: PgPool) {
+    let server = common::server(pool.clone());
+    let admin_id = common::insert_user(&pool, "root", "admin").await;
+    let owner_id = common::insert_test_user(&pool).await;
+
+    let created: Value = server
+        .post("/post")
+        .add_header("Authorization", common::bearer(owner_id))
+        .json(&json!({ "title": "Mine", "body": "Body" }))
+        .await
+        .json();
+    let id = created["id"].as_i64().unwrap();
+
+    let res = server
+        .put(&format!("/post/{id}"))
+        .add_header("Authorization", common::admin_bearer(admin_id))
+        .json(&json!({ "title": "Moderated" }))
+        .await;
+    assert_eq!(res.status_code(), 200);
+    let body: Value = res.json();
+    assert_eq!(body["title"], "Moderated");
+    assert_eq!(body["user_id"], owner_id);
+
+    let res = server
+        .delete(&format!("/post/{id}"))
+        .add_header("Authorization", common::admin_bearer(admin_id))
+        .await;
+    assert_eq!(res.status_code(), 200);
+}
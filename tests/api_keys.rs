@@ -0,0 +1,194 @@
+mod common;
+
+use serde_json::{Value, json};
+use sqlx::PgPool;
+
+async fn create_key(
+    server: &axum_test::TestServer,
+    authorization: &str,
+    body: Value,
+) -> axum_test::TestResponse {
+    server
+        .post("/auth/api-keys")
+        .add_header("Authorization", authorization)
+        .json(&body)
+        .await
+}
+
+fn key_bearer(created: &Value) -> String {
+    format!("Bearer {}", created["key"].as_str().unwrap())
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn api_key_authenticates_within_its_scopes(pool: PgPool) {
+    let server = common::server(pool.clone());
+    let user_id = common::insert_test_user(&pool).await;
+
+    let res = create_key(
+        &server,
+        &common::bearer(user_id),
+        json!({ "name": "ci", "scopes": ["posts:read", "users:read"] }),
+    )
+    .await;
+    assert_eq!(res.status_code(), 200);
+    let created: Value = res.json();
+    let key = created["key"].as_str().unwrap();
+    assert!(key.starts_with(created["prefix"].as_str().unwrap()));
+    assert_eq!(created["scopes"], json!(["posts:read", "users:read"]));
+
+    let res = server
+        .get("/user")
+        .add_header("Authorization", key_bearer(&created))
+        .await;
+    assert_eq!(res.status_code(), 200);
+    let body: Value = res.json();
+    assert_eq!(body["id"], user_id);
+
+    let res = server
+        .post("/post")
+        .add_header("Authorization", key_bearer(&created))
+        .json(&json!({ "title": "t", "body": "b" }))
+        .await;
+    assert_eq!(res.status_code(), 403);
+
+    let res = server
+        .get("/auth/api-keys")
+        .add_header("Authorization", common::bearer(user_id))
+        .await;
+    assert_eq!(res.status_code(), 200);
+    let keys: Vec<Value> = res.json();
+    assert_eq!(keys.len(), 1);
+    assert!(keys[0].get("key").is_none());
+    assert!(keys[0]["last_used_at"].is_string());
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn api_keys_cannot_manage_the_account(pool: PgPool) {
+    let server = common::server(pool.clone());
+    let user_id = common::insert_test_user(&pool).await;
+    let created: Value = create_key(
+        &server,
+        &common::bearer(user_id),
+        json!({ "name": "ci", "scopes": ["users:write"] }),
+    )
+    .await
+    .json();
+
+    let res = create_key(
+        &server,
+        &key_bearer(&created),
+        json!({ "name": "another", "scopes": ["users:write"] }),
+    )
+    .await;
+    assert_eq!(res.status_code(), 403);
+
+    let res = server
+        .get("/auth/sessions")
+        .add_header("Authorization", key_bearer(&created))
+        .await;
+    assert_eq!(res.status_code(), 403);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn revoked_and_expired_keys_are_rejected(pool: PgPool) {
+    let server = common::server(pool.clone());
+    let user_id = common::insert_test_user(&pool).await;
+
+    let revoked: Value = create_key(
+        &server,
+        &common::bearer(user_id),
+        json!({ "name": "revoked", "scopes": ["posts:read"] }),
+    )
+    .await
+    .json();
+    let res = server
+        .delete(&format!(
+            "/auth/api-keys/{}",
+            revoked["id"].as_str().unwrap()
+        ))
+        .add_header("Authorization", common::bearer(user_id))
+        .await;
+    assert_eq!(res.status_code(), 200);
+
+    let res = server
+        .get("/posts")
+        .add_header("Authorization", key_bearer(&revoked))
+        .await;
+    assert_eq!(res.status_code(), 401);
+
+    let expired: Value = create_key(
+        &server,
+        &common::bearer(user_id),
+        json!({ "name": "expired", "scopes": ["posts:read"], "expires_in_days": 1 }),
+    )
+    .await
+    .json();
+    sqlx::query("UPDATE api_keys SET expires_at = NOW() - INTERVAL '1 minute'")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let res = server
+        .get("/posts")
+        .add_header("Authorization", key_bearer(&expired))
+        .await;
+    assert_eq!(res.status_code(), 401);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn create_validates_scopes_and_expiry(pool: PgPool) {
+    let server = common::server(pool.clone());
+    let user_id = common::insert_test_user(&pool).await;
+    let auth = common::bearer(user_id);
+
+    let res = create_key(&server, &auth, json!({ "name": "x", "scopes": [] })).await;
+    assert_eq!(res.status_code(), 422);
+
+    let res = create_key(
+        &server,
+        &auth,
+        json!({ "name": "x", "scopes": ["posts:read"], "expires_in_days": 0 }),
+    )
+    .await;
+    assert_eq!(res.status_code(), 422);
+
+    let res = create_key(
+        &server,
+        &auth,
+        json!({ "name": "x", "scopes": ["users:admin"] }),
+    )
+    .await;
+    assert_eq!(res.status_code(), 403);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn admin_key_needs_admin_scope(pool: PgPool) {
+    let server = common::server(pool.clone());
+    let admin_id = common::insert_user(&pool, "root", "admin").await;
+
+    let limited: Value = create_key(
+        &server,
+        &common::admin_bearer(admin_id),
+        json!({ "name": "limited", "scopes": ["users:read"] }),
+    )
+    .await
+    .json();
+    let res = server
+        .get("/admin/users")
+        .add_header("Authorization", key_bearer(&limited))
+        .await;
+    assert_eq!(res.status_code(), 403);
+
+    let full: Value = create_key(
+        &server,
+        &common::admin_bearer(admin_id),
+        json!({ "name": "full", "scopes": ["users:admin"] }),
+    )
+    .await
+    .json();
+    let res = server
+        .get("/admin/users")
+        .add_header("Authorization", key_bearer(&full))
+        .await;
+    assert_eq!(res.status_code(), 200);
+}
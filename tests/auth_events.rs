@@ -0,0 +1,306 @@
+mod common;
+
+use serde_json::{Value, json};
+use sqlx::PgPool;
+
+fn bearer(session: &Value) -> String {
+    format!("Bearer {}", session["access_token"].as_str().unwrap())
+}
+
+async fn events(pool: &PgPool, user_id: i32) -> Vec<(String, String, Option<String>)> {
+    sqlx::query_as(
+        "SELECT event_type, outcome, detail FROM auth_events WHERE user_id = $1 ORDER BY id",
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await
+    .unwrap()
+}
+
+async fn user_id(pool: &PgPool, username: &str) -> i32 {
+    sqlx::query_scalar("SELECT id FROM users WHERE username = $1")
+        .bind(username)
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn logins_are_recorded_with_outcome_and_device(pool: PgPool) {
+    let server = common::server(pool.clone());
+    common::register(&server, &pool, "alice", common::PASSWORD).await;
+    let alice = user_id(&pool, "alice").await;
+
+    server
+        .post("/auth/login")
+        .add_header("User-Agent", "curl/8.0")
+        .json(&json!({ "username": "alice", "password": "wrong" }))
+        .await
+        .assert_status_unauthorized();
+    server
+        .post("/auth/login")
+        .add_header("User-Agent", "curl/8.0")
+        .json(&json!({ "username": "alice", "password": common::PASSWORD }))
+        .await
+        .assert_status_ok();
+
+    assert_eq!(
+        events(&pool, alice).await,
+        vec![
+            (
+                "login".to_string(),
+                "failure".to_string(),
+                Some("invalid_credentials".to_string())
+            ),
+            ("login".to_string(), "success".to_string(), None),
+        ]
+    );
+    let (username, user_agent): (Option<String>, Option<String>) = sqlx::query_as(
+        "SELECT username, user_agent FROM auth_events WHERE user_id = $1 ORDER BY id LIMIT 1",
+    )
+    .bind(alice)
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert_eq!(username.as_deref(), Some("alice"));
+    assert_eq!(user_agent.as_deref(), Some("curl/8.0"));
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn login_for_unknown_username_is_recorded_without_a_user(pool: PgPool) {
+    let server = common::server(pool.clone());
+
+    server
+        .post("/auth/login")
+        .json(&json!({ "username": "nobody", "password": "whatever" }))
+        .await
+        .assert_status_unauthorized();
+
+    let (user_id, username): (Option<i32>, Option<String>) =
+        sqlx::query_as("SELECT user_id, username FROM auth_events")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(user_id, None);
+    assert_eq!(username.as_deref(), Some("nobody"));
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn refresh_and_logout_are_recorded(pool: PgPool) {
+    let server = common::server(pool.clone());
+    common::register(&server, &pool, "alice", common::PASSWORD).await;
+    let alice = user_id(&pool, "alice").await;
+    let session = common::login(&server, "alice", common::PASSWORD).await;
+
+    let res = server
+        .post("/auth/refresh")
+        .json(&json!({ "refresh_token": session["refresh_token"] }))
+        .await;
+    res.assert_status_ok();
+    let refreshed: Value = res.json();
+    server
+        .post("/auth/logout")
+        .add_header(
+            "Authorization",
+            format!("Bearer {}", refreshed["refresh_token"].as_str().unwrap()),
+        )
+        .await
+        .assert_status_ok();
+
+    let kinds: Vec<_> = events(&pool, alice)
+        .await
+        .into_iter()
+        .map(|(kind, outcome, _)| (kind, outcome))
+        .collect();
+    assert_eq!(
+        kinds,
+        vec![
+            ("login".to_string(), "success".to_string()),
+            ("refresh".to_string(), "success".to_string()),
+            ("logout".to_string(), "success".to_string()),
+        ]
+    );
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn account_changes_outlive_the_account(pool: PgPool) {
+    let server = common::server(pool.clone());
+    common::register(&server, &pool, "alice", common::PASSWORD).await;
+    let alice = user_id(&pool, "alice").await;
+    let session = common::login(&server, "alice", common::PASSWORD).await;
+
+    server
+        .put("/user")
+        .add_header("Authorization", bearer(&session))
+        .json(&json!({ "username": "alice2" }))
+        .await
+        .assert_status_ok();
+    server
+        .delete("/user")
+        .add_header("Authorization", bearer(&session))
+        .await
+        .assert_status_ok();
+
+    let recorded = events(&pool, alice).await;
+    assert_eq!(
+        recorded[1..],
+        [
+            (
+                "account_updated".to_string(),
+                "success".to_string(),
+                Some("username".to_string())
+            ),
+            ("account_deleted".to_string(), "success".to_string(), None),
+        ]
+    );
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn password_changes_and_resets_are_recorded(pool: PgPool) {
+    let (server, mailer) = common::server_with_mailer(pool.clone());
+    common::register(&server, &pool, "alice", common::PASSWORD).await;
+    let alice = user_id(&pool, "alice").await;
+    let session = common::login(&server, "alice", common::PASSWORD).await;
+
+    server
+        .put("/user/password")
+        .add_header("Authorization", bearer(&session))
+        .json(&json!({ "current_password": "wrong", "new_password": "a-brand-new-password" }))
+        .await
+        .assert_status_unauthorized();
+    server
+        .put("/user/password")
+        .add_header("Authorization", bearer(&session))
+        .json(&json!({
+            "current_password": common::PASSWORD,
+            "new_password": "a-brand-new-password"
+        }))
+        .await
+        .assert_status_ok();
+    server
+        .post("/auth/forgot-password")
+        .json(&json!({ "email": "alice@example.com" }))
+        .await
+        .assert_status_ok();
+    let token = common::token_from_last_email(&mailer, "alice@example.com");
+    server
+        .post("/auth/reset-password")
+        .json(&json!({ "token": token, "new_password": "yet-another-password" }))
+        .await
+        .assert_status_ok();
+
+    let recorded = events(&pool, alice).await;
+    assert_eq!(
+        recorded[1..],
+        [
+            (
+                "password_changed".to_string(),
+                "failure".to_string(),
+                Some("invalid_credentials".to_string())
+            ),
+            ("password_changed".to_string(), "success".to_string(), None),
+            ("password_reset".to_string(), "success".to_string(), None),
+        ]
+    );
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn users_only_see_their_own_events(pool: PgPool) {
+    let server = common::server(pool.clone());
+    common::register(&server, &pool, "alice", common::PASSWORD).await;
+    common::register(&server, &pool, "bob", common::PASSWORD).await;
+    let alice = user_id(&pool, "alice").await;
+    common::login(&server, "bob", common::PASSWORD).await;
+    let session = common::login(&server, "alice", common::PASSWORD).await;
+
+    let res = server
+        .get("/user/security-events")
+        .add_header("Authorization", bearer(&session))
+        .await;
+
+    res.assert_status_ok();
+    let body: Value = res.json();
+    let data = body["data"].as_array().unwrap();
+    assert_eq!(data.len(), 1);
+    assert_eq!(data[0]["user_id"], alice);
+    assert_eq!(data[0]["event_type"], "login");
+    assert_eq!(data[0]["outcome"], "success");
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn admins_can_filter_all_events(pool: PgPool) {
+    let server = common::server(pool.clone());
+    let admin_id = common::insert_user(&pool, "root", "admin").await;
+    common::register(&server, &pool, "alice", common::PASSWORD).await;
+    common::register(&server, &pool, "bob", common::PASSWORD).await;
+    let alice = user_id(&pool, "alice").await;
+    common::login(&server, "alice", common::PASSWORD).await;
+    common::login(&server, "bob", common::PASSWORD).await;
+    server
+        .post("/auth/login")
+        .json(&json!({ "username": "alice", "password": "wrong" }))
+        .await
+        .assert_status_unauthorized();
+
+    let res = server
+        .get("/admin/auth-events")
+        .add_query_param("user_id", alice)
+        .add_query_param("event_type", "login")
+        .add_query_param("outcome", "failure")
+        .add_header("Authorization", common::admin_bearer(admin_id))
+        .await;
+
+    res.assert_status_ok();
+    let body: Value = res.json();
+    let data = body["data"].as_array().unwrap();
+    assert_eq!(data.len(), 1);
+    assert_eq!(data[0]["detail"], "invalid_credentials");
+
+    let res = server
+        .get("/admin/auth-events")
+        .add_query_param("limit", 2)
+        .add_header("Authorization", common::admin_bearer(admin_id))
+        .await;
+    let body: Value = res.json();
+    assert_eq!(body["data"].as_array().unwrap().len(), 2);
+    assert_eq!(body["has_more"], true);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn admin_query_requires_admin(pool: PgPool) {
+    let server = common::server(pool.clone());
+    let user_id = common::insert_test_user(&pool).await;
+
+    let res = server
+        .get("/admin/auth-events")
+        .add_header("Authorization", common::bearer(user_id))
+        .await;
+
+    assert_eq!(res.status_code(), 403);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn events_cannot_be_changed(pool: PgPool) {
+    let server = common::server(pool.clone());
+    common::register(&server, &pool, "alice", common::PASSWORD).await;
+    common::login(&server, "alice", common::PASSWORD).await;
+
+    assert!(
+        sqlx::query("UPDATE auth_events SET outcome = 'failure'")
+            .execute(&pool)
+            .await
+            .is_err()
+    );
+    assert!(
+        sqlx::query("DELETE FROM auth_events")
+            .execute(&pool)
+            .await
+            .is_err()
+    );
+    assert!(
+        sqlx::query("TRUNCATE auth_events")
+            .execute(&pool)
+            .await
+            .is_err()
+    );
+}
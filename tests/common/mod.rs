@@ -1,19 +1,91 @@
+#![allow(dead_code)] // each integration test crate uses a different subset of these helpers
+
+use async_trait::async_trait;
 use axum_test::TestServer;
-use rust_axum_rest_api::create_app;
+use rust_axum_rest_api::{
+    Services,
+    auth::{
+        account_deletion::DeletionPolicy, cookies::CookieConfig, keys::JwtKeys,
+        lockout::LockoutPolicy, oidc::OidcProviders, password_policy::PasswordPolicy,
+        providers::DatabaseProvider, revocation::RevocationStore, roles::Role,
+    },
+    create_app_with_mailer, create_app_with_services,
+    mail::{Email, InMemoryMailer, MailError, Mailer},
+};
 use sqlx::{PgPool, Row};
+use std::{sync::Arc, time::Duration};
 
 pub const TEST_JWT_SECRET: &str = "test-secret";
 
-pub fn token_for(user_id: i32) -> String {
-    rust_axum_rest_api::auth::jwt::generate_token(user_id, TEST_JWT_SECRET).unwrap()
+/// Strong enough to pass the default password policy.
+pub const PASSWORD: &str = "tangerine-walrus-orbit-42";
+
+pub fn token_for(user_id: i32, role: Role) -> String {
+    let keys = JwtKeys::from_secret(TEST_JWT_SECRET);
+    let scopes = rust_axum_rest_api::auth::scopes::Scope::all_for(role);
+    rust_axum_rest_api::auth::jwt::generate_token(user_id, role, &scopes, &keys).unwrap()
 }
 
 pub fn bearer(user_id: i32) -> String {
-    format!("Bearer {}", token_for(user_id))
+    format!("Bearer {}", token_for(user_id, Role::User))
+}
+
+pub fn admin_bearer(user_id: i32) -> String {
+    format!("Bearer {}", token_for(user_id, Role::Admin))
 }
 
 pub fn server(pool: PgPool) -> TestServer {
-    TestServer::new(create_app(pool)).unwrap()
+    server_with_mailer(pool).0
+}
+
+pub fn server_with_mailer(pool: PgPool) -> (TestServer, InMemoryMailer) {
+    let mailer = InMemoryMailer::new();
+    let server = TestServer::new(create_app_with_mailer(pool, Arc::new(mailer.clone()))).unwrap();
+    (server, mailer)
+}
+
+/// Test defaults for every service, for tests that need to swap one of them out.
+pub fn services() -> Services {
+    Services {
+        mailer: Arc::new(InMemoryMailer::new()),
+        jwt_keys: Arc::new(JwtKeys::from_secret(TEST_JWT_SECRET)),
+        password_policy: Arc::new(PasswordPolicy::default()),
+        cookies: Arc::new(CookieConfig::default()),
+        oidc: Arc::new(OidcProviders::new(vec![])),
+        revocations: Arc::new(RevocationStore::new(Duration::from_secs(5))),
+        auth_provider: Arc::new(DatabaseProvider),
+        deletion: Arc::new(DeletionPolicy::default()),
+        lockout: Arc::new(LockoutPolicy::default()),
+    }
+}
+
+/// A mailer whose server is always down.
+pub struct FailingMailer;
+
+#[async_trait]
+impl Mailer for FailingMailer {
+    async fn send(&self, _email: Email) -> Result<(), MailError> {
+        Err(MailError("connection refused".into()))
+    }
+}
+
+pub fn server_with_services(pool: PgPool, services: Services) -> TestServer {
+    TestServer::new(create_app_with_services(pool, services)).unwrap()
+}
+
+/// Pulls the `token=` query parameter out of the last email sent to `to`.
+pub fn token_from_last_email(mailer: &InMemoryMailer, to: &str) -> String {
+    let email = mailer.last_to(to).expect("no email sent");
+    let (_, rest) = email.body.split_once("token=").expect("email has no token");
+    rest.split_whitespace().next().unwrap().to_string()
+}
+
+pub async fn mark_verified(pool: &PgPool, username: &str) {
+    sqlx::query("UPDATE users SET email_verified_at = NOW() WHERE username = $1")
+        .bind(username)
+        .execute(pool)
+        .await
+        .unwrap();
 }
 
 pub async fn insert_test_user(pool: &PgPool) -> i32 {
@@ -28,3 +100,40 @@ pub async fn insert_test_user(pool: &PgPool) -> i32 {
     .unwrap()
     .get::<i32, _>("id")
 }
+
+pub async fn insert_user(pool: &PgPool, username: &str, role: &str) -> i32 {
+    sqlx::query(
+        "INSERT INTO users (username, email, password_hash, role) VALUES ($1, $2, $3, $4) RETURNING id",
+    )
+    .bind(username)
+    .bind(format!("{username}@example.com"))
+    .bind("irrelevant-hash")
+    .bind(role)
+    .fetch_one(pool)
+    .await
+    .unwrap()
+    .get::<i32, _>("id")
+}
+
+/// Registers `username` through the API with a verified email of `<username>@example.com`.
+pub async fn register(server: &TestServer, pool: &PgPool, username: &str, password: &str) {
+    server
+        .post("/user")
+        .json(&serde_json::json!({
+            "username": username,
+            "email": format!("{username}@example.com"),
+            "password": password
+        }))
+        .await
+        .assert_status_ok();
+    mark_verified(pool, username).await;
+}
+
+pub async fn login(server: &TestServer, username: &str, password: &str) -> serde_json::Value {
+    let res = server
+        .post("/auth/login")
+        .json(&serde_json::json!({ "username": username, "password": password }))
+        .await;
+    res.assert_status_ok();
+    res.json()
+}
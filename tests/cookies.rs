@@ -0,0 +1,150 @@
+mod common;
+
+use axum_test::TestServer;
+use rust_axum_rest_api::{Services, auth::cookies::CookieConfig};
+use serde_json::{Value, json};
+use sqlx::PgPool;
+use std::sync::Arc;
+
+async fn cookie_server(pool: &PgPool) -> TestServer {
+    let services = Services {
+        cookies: Arc::new(CookieConfig {
+            enabled: true,
+            ..CookieConfig::default()
+        }),
+        ..common::services()
+    };
+    let mut server = common::server_with_services(pool.clone(), services);
+    common::register(&server, pool, "alice", common::PASSWORD).await;
+    server.save_cookies();
+    server
+}
+
+/// Logs in and returns the CSRF token the front end would read from its cookie.
+async fn login(server: &TestServer) -> String {
+    let res = server
+        .post("/auth/login")
+        .json(&json!({ "username": "alice", "password": common::PASSWORD }))
+        .await;
+    res.assert_status_ok();
+    res.cookie("csrf_token").value().to_string()
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn login_sets_http_only_cookies_instead_of_returning_tokens(pool: PgPool) {
+    let server = cookie_server(&pool).await;
+
+    let res = server
+        .post("/auth/login")
+        .json(&json!({ "username": "alice", "password": common::PASSWORD }))
+        .await;
+
+    res.assert_status_ok();
+    let body: Value = res.json();
+    assert!(body.get("access_token").is_none());
+    assert!(body.get("refresh_token").is_none());
+    assert_eq!(body["user"]["username"], "alice");
+
+    let access = res.cookie("access_token");
+    assert_eq!(access.http_only(), Some(true));
+    assert_eq!(access.secure(), Some(true));
+    assert_eq!(res.cookie("refresh_token").http_only(), Some(true));
+    assert_ne!(res.cookie("csrf_token").http_only(), Some(true));
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn cookie_authenticates_safe_requests_without_csrf(pool: PgPool) {
+    let server = cookie_server(&pool).await;
+    login(&server).await;
+
+    let res = server.get("/user").await;
+
+    res.assert_status_ok();
+    assert_eq!(res.json::<Value>()["username"], "alice");
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn state_changing_requests_require_csrf_header(pool: PgPool) {
+    let server = cookie_server(&pool).await;
+    let csrf = login(&server).await;
+    let post = json!({ "title": "Hello", "body": "World" });
+
+    let res = server.post("/post").json(&post).await;
+    assert_eq!(res.status_code(), 403);
+
+    let res = server
+        .post("/post")
+        .add_header("X-CSRF-Token", "not-the-token")
+        .json(&post)
+        .await;
+    assert_eq!(res.status_code(), 403);
+
+    server
+        .post("/post")
+        .add_header("X-CSRF-Token", csrf)
+        .json(&post)
+        .await
+        .assert_status_ok();
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn refresh_reads_cookie_and_rotates_it(pool: PgPool) {
+    let server = cookie_server(&pool).await;
+    let csrf = login(&server).await;
+
+    assert_eq!(server.post("/auth/refresh").await.status_code(), 403);
+
+    let res = server
+        .post("/auth/refresh")
+        .add_header("X-CSRF-Token", csrf)
+        .await;
+    res.assert_status_ok();
+    assert!(res.json::<Value>().get("refresh_token").is_none());
+    let new_csrf = res.cookie("csrf_token").value().to_string();
+
+    // The rotated cookies carry the session on.
+    server.get("/user").await.assert_status_ok();
+    server
+        .post("/auth/refresh")
+        .add_header("X-CSRF-Token", new_csrf)
+        .await
+        .assert_status_ok();
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn logout_revokes_session_and_clears_cookies(pool: PgPool) {
+    let server = cookie_server(&pool).await;
+    let csrf = login(&server).await;
+
+    let res = server
+        .post("/auth/logout")
+        .add_header("X-CSRF-Token", csrf)
+        .await;
+    res.assert_status_ok();
+    assert_eq!(res.cookie("access_token").value(), "");
+
+    assert_eq!(server.get("/user").await.status_code(), 401);
+    let remaining: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM refresh_tokens")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(remaining, 0);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn bearer_tokens_still_work_in_cookie_mode(pool: PgPool) {
+    let server = cookie_server(&pool).await;
+    let user_id: i32 = sqlx::query_scalar("SELECT id FROM users WHERE username = 'alice'")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+
+    // No cookies and no CSRF header: a bearer token can't be attached by another site.
+    server
+        .post("/post")
+        .clear_cookies()
+        .add_header("Authorization", common::bearer(user_id))
+        .json(&json!({ "title": "Hello", "body": "World" }))
+        .await
+        .assert_status_ok();
+}
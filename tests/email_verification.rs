@@ -0,0 +1,195 @@
+mod common;
+
+use serde_json::{Value, json};
+use sqlx::PgPool;
+
+async fn register_alice(server: &axum_test::TestServer) {
+    server
+        .post("/user")
+        .json(&json!({
+            "username": "alice",
+            "email": "alice@example.com",
+            "password": common::PASSWORD
+        }))
+        .await;
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn unverified_user_cannot_log_in(pool: PgPool) {
+    let server = common::server(pool);
+    register_alice(&server).await;
+
+    let res = server
+        .post("/auth/login")
+        .json(&json!({ "username": "alice", "password": common::PASSWORD }))
+        .await;
+
+    assert_eq!(res.status_code(), 403);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn verification_link_is_single_use_and_enables_login(pool: PgPool) {
+    let (server, mailer) = common::server_with_mailer(pool);
+    register_alice(&server).await;
+
+    let token = common::token_from_last_email(&mailer, "alice@example.com");
+
+    let res = server
+        .post("/auth/verify-email")
+        .json(&json!({ "token": token }))
+        .await;
+    assert_eq!(res.status_code(), 200);
+
+    let res = server
+        .post("/auth/verify-email")
+        .json(&json!({ "token": token }))
+        .await;
+    assert_eq!(res.status_code(), 401, "token must be single-use");
+
+    let res = server
+        .post("/auth/login")
+        .json(&json!({ "username": "alice", "password": common::PASSWORD }))
+        .await;
+    assert_eq!(res.status_code(), 200);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn resend_replaces_previous_token(pool: PgPool) {
+    let (server, mailer) = common::server_with_mailer(pool.clone());
+    register_alice(&server).await;
+    let first = common::token_from_last_email(&mailer, "alice@example.com");
+
+    // Step outside the resend cooldown.
+    sqlx::query("UPDATE email_verification_tokens SET created_at = created_at - INTERVAL '1 hour'")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let res = server
+        .post("/auth/resend-verification")
+        .json(&json!({ "email": "alice@example.com" }))
+        .await;
+    assert_eq!(res.status_code(), 200);
+
+    let second = common::token_from_last_email(&mailer, "alice@example.com");
+    assert_ne!(first, second);
+
+    let res = server
+        .post("/auth/verify-email")
+        .json(&json!({ "token": first }))
+        .await;
+    assert_eq!(res.status_code(), 401);
+
+    let res = server
+        .post("/auth/verify-email")
+        .json(&json!({ "token": second }))
+        .await;
+    assert_eq!(res.status_code(), 200);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn resend_for_unknown_email_looks_identical(pool: PgPool) {
+    let (server, mailer) = common::server_with_mailer(pool);
+
+    let res = server
+        .post("/auth/resend-verification")
+        .json(&json!({ "email": "nobody@example.com" }))
+        .await;
+
+    assert_eq!(res.status_code(), 200);
+    let body: Value = res.json();
+    assert!(
+        body["message"]
+            .as_str()
+            .unwrap()
+            .starts_with("If an unverified account")
+    );
+    assert!(mailer.sent().is_empty());
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn changing_email_requires_verifying_it_again(pool: PgPool) {
+    let (server, mailer) = common::server_with_mailer(pool.clone());
+    common::register(&server, &pool, "alice", common::PASSWORD).await;
+    let old_token = common::token_from_last_email(&mailer, "alice@example.com");
+    let session = common::login(&server, "alice", common::PASSWORD).await;
+    let bearer = format!("Bearer {}", session["access_token"].as_str().unwrap());
+
+    server
+        .put("/user")
+        .add_header("Authorization", &bearer)
+        .json(&json!({ "username": "alice2" }))
+        .await
+        .assert_status_ok();
+    let res = server
+        .post("/auth/login")
+        .json(&json!({ "username": "alice2", "password": common::PASSWORD }))
+        .await;
+    assert_eq!(
+        res.status_code(),
+        200,
+        "renaming keeps the address verified"
+    );
+
+    server
+        .put("/user")
+        .add_header("Authorization", &bearer)
+        .json(&json!({ "email": "alice@example.org" }))
+        .await
+        .assert_status_ok();
+    let res = server
+        .post("/auth/login")
+        .json(&json!({ "username": "alice2", "password": common::PASSWORD }))
+        .await;
+    assert_eq!(res.status_code(), 403);
+
+    let res = server
+        .post("/auth/verify-email")
+        .json(&json!({ "token": old_token }))
+        .await;
+    assert_eq!(res.status_code(), 401, "links to the old address are void");
+
+    let token = common::token_from_last_email(&mailer, "alice@example.org");
+    server
+        .post("/auth/verify-email")
+        .json(&json!({ "token": token }))
+        .await
+        .assert_status_ok();
+    let res = server
+        .post("/auth/login")
+        .json(&json!({ "username": "alice2", "password": common::PASSWORD }))
+        .await;
+    assert_eq!(res.status_code(), 200);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn admin_changing_email_requires_verifying_it_again(pool: PgPool) {
+    let (server, mailer) = common::server_with_mailer(pool.clone());
+    common::register(&server, &pool, "alice", common::PASSWORD).await;
+    let admin_id = common::insert_user(&pool, "root", "admin").await;
+    let alice: i32 = sqlx::query_scalar("SELECT id FROM users WHERE username = 'alice'")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+
+    let res = server
+        .put(&format!("/admin/users/{alice}"))
+        .add_header("Authorization", common::admin_bearer(admin_id))
+        .json(&json!({ "email": "alice@example.org" }))
+        .await;
+    res.assert_status_ok();
+    assert!(res.json::<Value>()["email_verified_at"].is_null());
+
+    let res = server
+        .post("/auth/login")
+        .json(&json!({ "username": "alice", "password": common::PASSWORD }))
+        .await;
+    assert_eq!(res.status_code(), 403);
+
+    let token = common::token_from_last_email(&mailer, "alice@example.org");
+    server
+        .post("/auth/verify-email")
+        .json(&json!({ "token": token }))
+        .await
+        .assert_status_ok();
+}
@@ -0,0 +1,238 @@
+mod common;
+
+use serde_json::{Value, json};
+use sqlx::PgPool;
+
+async fn impersonate(
+    server: &axum_test::TestServer,
+    admin_id: i32,
+    user_id: i32,
+) -> axum_test::TestResponse {
+    server
+        .post(&format!("/admin/users/{user_id}/impersonate"))
+        .add_header("Authorization", common::admin_bearer(admin_id))
+        .await
+}
+
+fn bearer(body: &Value) -> String {
+    format!("Bearer {}", body["access_token"].as_str().unwrap())
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn admin_acts_as_the_user(pool: PgPool) {
+    let server = common::server(pool.clone());
+    let admin_id = common::insert_user(&pool, "root", "admin").await;
+    let user_id = common::insert_test_user(&pool).await;
+
+    let res = impersonate(&server, admin_id, user_id).await;
+    res.assert_status_ok();
+    let body: Value = res.json();
+    assert_eq!(body["user"]["username"], "testuser");
+    assert_eq!(body["expires_in"], 600);
+
+    let res = server
+        .get("/user")
+        .add_header("Authorization", bearer(&body))
+        .await;
+    res.assert_status_ok();
+    let me: Value = res.json();
+    assert_eq!(me["id"], user_id);
+
+    // Acts with the user's permissions, not the admin's.
+    let res = server
+        .get("/admin/users")
+        .add_header("Authorization", bearer(&body))
+        .await;
+    assert_eq!(res.status_code(), 403);
+
+    let (event_user, actor): (Option<i32>, Option<i32>) = sqlx::query_as(
+        "SELECT user_id, actor_id FROM auth_events WHERE event_type = 'impersonation_started'",
+    )
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert_eq!((event_user, actor), (Some(user_id), Some(admin_id)));
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn sensitive_actions_are_blocked_while_impersonating(pool: PgPool) {
+    let server = common::server(pool.clone());
+    let admin_id = common::insert_user(&pool, "root", "admin").await;
+    let user_id = common::insert_test_user(&pool).await;
+    let body: Value = impersonate(&server, admin_id, user_id).await.json();
+
+    let res = server
+        .put("/user/password")
+        .add_header("Authorization", bearer(&body))
+        .json(&json!({
+            "current_password": "password123",
+            "new_password": "a-brand-new-password"
+        }))
+        .await;
+    assert_eq!(res.status_code(), 403);
+
+    let res = server
+        .delete("/user")
+        .add_header("Authorization", bearer(&body))
+        .await;
+    assert_eq!(res.status_code(), 403);
+    let exists: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM users WHERE id = $1)")
+        .bind(user_id)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert!(exists);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn regular_users_cannot_impersonate(pool: PgPool) {
+    let server = common::server(pool.clone());
+    let user_id = common::insert_test_user(&pool).await;
+    let other_id = common::insert_user(&pool, "bob", "user").await;
+
+    let res = server
+        .post(&format!("/admin/users/{other_id}/impersonate"))
+        .add_header("Authorization", common::bearer(user_id))
+        .await;
+
+    assert_eq!(res.status_code(), 403);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn admins_cannot_be_impersonated(pool: PgPool) {
+    let server = common::server(pool.clone());
+    let admin_id = common::insert_user(&pool, "root", "admin").await;
+    let other_admin_id = common::insert_user(&pool, "boss", "admin").await;
+
+    assert_eq!(
+        impersonate(&server, admin_id, other_admin_id)
+            .await
+            .status_code(),
+        403
+    );
+    assert_eq!(
+        impersonate(&server, admin_id, admin_id).await.status_code(),
+        422
+    );
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn impersonating_unknown_user_returns_404(pool: PgPool) {
+    let server = common::server(pool.clone());
+    let admin_id = common::insert_user(&pool, "root", "admin").await;
+
+    assert_eq!(
+        impersonate(&server, admin_id, 9999).await.status_code(),
+        404
+    );
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn lasting_credentials_cannot_be_created_while_impersonating(pool: PgPool) {
+    let server = common::server(pool.clone());
+    let admin_id = common::insert_user(&pool, "root", "admin").await;
+    let user_id = common::insert_test_user(&pool).await;
+    let body: Value = impersonate(&server, admin_id, user_id).await.json();
+
+    let res = server
+        .post("/auth/api-keys")
+        .add_header("Authorization", bearer(&body))
+        .json(&json!({ "name": "backdoor", "scopes": ["posts:read"] }))
+        .await;
+    assert_eq!(res.status_code(), 403);
+
+    let res = server
+        .post("/oauth/clients")
+        .add_header("Authorization", bearer(&body))
+        .json(&json!({
+            "name": "backdoor",
+            "redirect_uris": ["https://example.com/callback"],
+            "scopes": ["posts:read"]
+        }))
+        .await;
+    assert_eq!(res.status_code(), 403);
+
+    let res = server
+        .post("/oauth/authorize")
+        .add_header("Authorization", bearer(&body))
+        .json(&json!({
+            "response_type": "code",
+            "client_id": "00000000-0000-0000-0000-000000000000",
+            "redirect_uri": "https://example.com/callback",
+            "approved": true
+        }))
+        .await;
+    assert_eq!(res.status_code(), 403);
+
+    let created: i64 = sqlx::query_scalar(
+        "SELECT (SELECT COUNT(*) FROM api_keys) + (SELECT COUNT(*) FROM oauth_clients)",
+    )
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert_eq!(created, 0);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn two_factor_settings_are_blocked_while_impersonating(pool: PgPool) {
+    let server = common::server(pool.clone());
+    let admin_id = common::insert_user(&pool, "root", "admin").await;
+    let user_id = common::insert_test_user(&pool).await;
+    let body: Value = impersonate(&server, admin_id, user_id).await.json();
+
+    let res = server
+        .post("/auth/mfa/enroll")
+        .add_header("Authorization", bearer(&body))
+        .await;
+    assert_eq!(res.status_code(), 403);
+
+    let res = server
+        .post("/auth/mfa/confirm")
+        .add_header("Authorization", bearer(&body))
+        .json(&json!({ "code": "123456" }))
+        .await;
+    assert_eq!(res.status_code(), 403);
+
+    let res = server
+        .delete("/auth/mfa")
+        .add_header("Authorization", bearer(&body))
+        .json(&json!({ "password": "password123" }))
+        .await;
+    assert_eq!(res.status_code(), 403);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn sessions_cannot_be_ended_while_impersonating(pool: PgPool) {
+    let server = common::server(pool.clone());
+    let admin_id = common::insert_user(&pool, "root", "admin").await;
+    common::register(&server, &pool, "alice", common::PASSWORD).await;
+    let session = common::login(&server, "alice", common::PASSWORD).await;
+    let user_id = session["user"]["id"].as_i64().unwrap() as i32;
+    let body: Value = impersonate(&server, admin_id, user_id).await.json();
+
+    let family_id: uuid::Uuid =
+        sqlx::query_scalar("SELECT family_id FROM refresh_tokens WHERE user_id = $1")
+            .bind(user_id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    let res = server
+        .delete(&format!("/auth/sessions/{family_id}"))
+        .add_header("Authorization", bearer(&body))
+        .await;
+    assert_eq!(res.status_code(), 403);
+
+    let res = server
+        .post("/auth/logout-all")
+        .add_header("Authorization", bearer(&body))
+        .await;
+    assert_eq!(res.status_code(), 403);
+
+    let sessions: i64 =
+        sqlx::query_scalar("SELECT COUNT(*) FROM refresh_tokens WHERE user_id = $1")
+            .bind(user_id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(sessions, 1);
+}
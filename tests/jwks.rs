@@ -0,0 +1,75 @@
+mod common;
+
+use axum_test::TestServer;
+use jsonwebtoken::{Algorithm, decode_header};
+use rust_axum_rest_api::{Services, auth::keys::JwtKeys};
+use serde_json::Value;
+use sqlx::PgPool;
+use std::sync::Arc;
+
+const RSA_PRIVATE: &str = include_str!("fixtures/jwt/rsa_private.pem");
+const RSA_PREVIOUS_PUBLIC: &str = include_str!("fixtures/jwt/rsa_previous_public.pem");
+
+fn rsa_server(pool: PgPool) -> TestServer {
+    let keys = JwtKeys::from_private_pem(Algorithm::RS256, RSA_PRIVATE, Some("current".into()))
+        .unwrap()
+        .with_verification_key(RSA_PREVIOUS_PUBLIC, Some("previous".into()))
+        .unwrap();
+    let services = Services {
+        jwt_keys: Arc::new(keys),
+        ..common::services()
+    };
+    common::server_with_services(pool, services)
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn jwks_publishes_every_verification_key(pool: PgPool) {
+    let server = rsa_server(pool);
+
+    let res = server.get("/.well-known/jwks.json").await;
+    assert_eq!(res.status_code(), 200);
+    let body: Value = res.json();
+    let keys = body["keys"].as_array().unwrap();
+    assert_eq!(keys.len(), 2);
+    assert_eq!(keys[0]["kid"], "current");
+    assert_eq!(keys[0]["kty"], "RSA");
+    assert_eq!(keys[0]["alg"], "RS256");
+    assert_eq!(keys[0]["use"], "sig");
+    assert!(keys[0]["n"].is_string());
+    assert_eq!(keys[1]["kid"], "previous");
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn login_issues_rs256_tokens_that_authenticate(pool: PgPool) {
+    let server = rsa_server(pool.clone());
+    common::register(&server, &pool, "alice", common::PASSWORD).await;
+    let session = common::login(&server, "alice", common::PASSWORD).await;
+    let access_token = session["access_token"].as_str().unwrap();
+
+    let header = decode_header(access_token).unwrap();
+    assert_eq!(header.alg, Algorithm::RS256);
+    assert_eq!(header.kid.as_deref(), Some("current"));
+
+    let res = server
+        .get("/user")
+        .add_header("Authorization", format!("Bearer {access_token}"))
+        .await;
+    assert_eq!(res.status_code(), 200);
+
+    // An HS256 token signed with the old shared secret no longer works.
+    let res = server
+        .get("/user")
+        .add_header("Authorization", common::bearer(1))
+        .await;
+    assert_eq!(res.status_code(), 401);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn jwks_is_empty_with_shared_secret(pool: PgPool) {
+    let server = common::server(pool);
+
+    let res = server.get("/.well-known/jwks.json").await;
+    assert_eq!(res.status_code(), 200);
+    let body: Value = res.json();
+    assert_eq!(body["keys"].as_array().unwrap().len(), 0);
+}
@@ -0,0 +1,142 @@
+mod common;
+
+use rust_axum_rest_api::auth::providers::{InMemoryDirectory, LdapProvider};
+use serde_json::{Value, json};
+use sqlx::PgPool;
+use std::sync::Arc;
+
+fn ldap_server(pool: PgPool) -> (axum_test::TestServer, InMemoryDirectory) {
+    let directory = InMemoryDirectory::new();
+    let mut services = common::services();
+    services.auth_provider = Arc::new(LdapProvider::new(Arc::new(directory.clone())));
+    (common::server_with_services(pool, services), directory)
+}
+
+async fn login(server: &axum_test::TestServer, username: &str, password: &str) -> u16 {
+    server
+        .post("/auth/login")
+        .json(&json!({ "username": username, "password": password }))
+        .await
+        .status_code()
+        .as_u16()
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn first_login_provisions_a_local_account(pool: PgPool) {
+    let (server, directory) = ldap_server(pool.clone());
+    directory.add_user("carol", "directory-pass", Some("carol@corp.example"));
+
+    let res = server
+        .post("/auth/login")
+        .json(&json!({ "username": "carol", "password": "directory-pass" }))
+        .await;
+
+    res.assert_status_ok();
+    let body: Value = res.json();
+    assert_eq!(body["user"]["username"], "carol");
+    assert_eq!(body["user"]["email"], "carol@corp.example");
+    assert!(body["access_token"].is_string());
+    let subject: String = sqlx::query_scalar(
+        "SELECT i.subject FROM user_identities i JOIN users u ON u.id = i.user_id
+        WHERE u.username = 'carol' AND i.provider = 'ldap'",
+    )
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert_eq!(subject, "uid=carol,ou=people,dc=example,dc=com");
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn later_logins_reuse_the_account(pool: PgPool) {
+    let (server, directory) = ldap_server(pool.clone());
+    directory.add_user("carol", "directory-pass", Some("carol@corp.example"));
+
+    assert_eq!(login(&server, "carol", "directory-pass").await, 200);
+    assert_eq!(login(&server, "carol", "directory-pass").await, 200);
+
+    let accounts: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM users")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(accounts, 1);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn wrong_directory_password_is_rejected(pool: PgPool) {
+    let (server, directory) = ldap_server(pool.clone());
+    directory.add_user("carol", "directory-pass", Some("carol@corp.example"));
+
+    assert_eq!(login(&server, "carol", "wrong").await, 401);
+    assert_eq!(login(&server, "carol", "").await, 401);
+
+    let accounts: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM users")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(accounts, 0);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn local_password_is_not_accepted(pool: PgPool) {
+    let (server, _directory) = ldap_server(pool.clone());
+    common::register(&server, &pool, "alice", common::PASSWORD).await;
+
+    assert_eq!(login(&server, "alice", common::PASSWORD).await, 401);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn existing_account_with_same_email_is_linked(pool: PgPool) {
+    let (server, directory) = ldap_server(pool.clone());
+    common::register(&server, &pool, "alice", common::PASSWORD).await;
+    directory.add_user("alice", "directory-pass", Some("ALICE@example.com"));
+
+    let res = server
+        .post("/auth/login")
+        .json(&json!({ "username": "alice", "password": "directory-pass" }))
+        .await;
+
+    res.assert_status_ok();
+    let body: Value = res.json();
+    assert_eq!(body["user"]["email"], "alice@example.com");
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn existing_unverified_account_is_not_linked(pool: PgPool) {
+    let (server, directory) = ldap_server(pool.clone());
+    server
+        .post("/user")
+        .json(&json!({
+            "username": "alice",
+            "email": "alice@example.com",
+            "password": common::PASSWORD
+        }))
+        .await
+        .assert_status_ok();
+    directory.add_user("alice", "directory-pass", Some("alice@example.com"));
+
+    assert_eq!(login(&server, "alice", "directory-pass").await, 409);
+    let verified: bool = sqlx::query_scalar(
+        "SELECT email_verified_at IS NOT NULL FROM users WHERE username = 'alice'",
+    )
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert!(!verified);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn existing_account_with_other_email_is_not_taken_over(pool: PgPool) {
+    let (server, directory) = ldap_server(pool.clone());
+    common::register(&server, &pool, "alice", common::PASSWORD).await;
+    directory.add_user("alice", "directory-pass", Some("someone-else@corp.example"));
+
+    assert_eq!(login(&server, "alice", "directory-pass").await, 409);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn entry_without_email_cannot_be_provisioned(pool: PgPool) {
+    let (server, directory) = ldap_server(pool.clone());
+    directory.add_user("svc", "directory-pass", None);
+
+    assert_eq!(login(&server, "svc", "directory-pass").await, 422);
+}
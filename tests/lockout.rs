@@ -0,0 +1,114 @@
+mod common;
+
+use axum::http::header::RETRY_AFTER;
+use axum_test::TestServer;
+use rust_axum_rest_api::{create_app_with_mailer, mail::InMemoryMailer};
+use serde_json::{Value, json};
+use sqlx::PgPool;
+use std::{net::SocketAddr, sync::Arc};
+
+async fn attempt(server: &TestServer, username: &str, password: &str) -> axum_test::TestResponse {
+    server
+        .post("/auth/login")
+        .json(&json!({ "username": username, "password": password }))
+        .await
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn repeated_failures_lock_the_account(pool: PgPool) {
+    let server = common::server(pool.clone());
+    common::register(&server, &pool, "alice", common::PASSWORD).await;
+
+    for _ in 0..5 {
+        let res = attempt(&server, "alice", "wrong-password").await;
+        assert_eq!(res.status_code(), 401);
+    }
+
+    // Even the right password is refused while locked.
+    let res = attempt(&server, "alice", common::PASSWORD).await;
+    assert_eq!(res.status_code(), 429);
+    let retry_after: u64 = res.headers()[RETRY_AFTER]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!((1..=61).contains(&retry_after));
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn successful_login_resets_the_failure_count(pool: PgPool) {
+    let server = common::server(pool.clone());
+    common::register(&server, &pool, "alice", common::PASSWORD).await;
+
+    for _ in 0..4 {
+        attempt(&server, "alice", "wrong-password").await;
+    }
+    attempt(&server, "alice", common::PASSWORD)
+        .await
+        .assert_status_ok();
+    for _ in 0..4 {
+        attempt(&server, "alice", "wrong-password").await;
+    }
+
+    attempt(&server, "alice", common::PASSWORD)
+        .await
+        .assert_status_ok();
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn admin_can_review_attempts_and_unlock(pool: PgPool) {
+    let server = common::server(pool.clone());
+    common::register(&server, &pool, "alice", common::PASSWORD).await;
+    let alice_id: i32 = sqlx::query_scalar("SELECT id FROM users WHERE username = 'alice'")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    let admin_id = common::insert_user(&pool, "root", "admin").await;
+
+    for _ in 0..5 {
+        attempt(&server, "alice", "wrong-password").await;
+    }
+    assert_eq!(
+        attempt(&server, "alice", common::PASSWORD)
+            .await
+            .status_code(),
+        429
+    );
+
+    let res = server
+        .get(&format!("/admin/users/{alice_id}/login-attempts"))
+        .add_header("Authorization", common::admin_bearer(admin_id))
+        .await;
+    assert_eq!(res.status_code(), 200);
+    let attempts: Vec<Value> = res.json();
+    assert_eq!(attempts.len(), 5);
+    assert!(attempts.iter().all(|a| a["succeeded"] == false));
+
+    let res = server
+        .post(&format!("/admin/users/{alice_id}/unlock"))
+        .add_header("Authorization", common::admin_bearer(admin_id))
+        .await;
+    assert_eq!(res.status_code(), 200);
+
+    attempt(&server, "alice", common::PASSWORD)
+        .await
+        .assert_status_ok();
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn too_many_failures_from_one_ip_are_throttled(pool: PgPool) {
+    // A real socket, so requests carry a client IP address.
+    let app = create_app_with_mailer(pool.clone(), Arc::new(InMemoryMailer::new()));
+    let server = TestServer::new(app.into_make_service_with_connect_info::<SocketAddr>()).unwrap();
+    common::register(&server, &pool, "alice", common::PASSWORD).await;
+
+    // Spread across usernames, so no single account is locked.
+    for i in 0..20 {
+        let res = attempt(&server, &format!("nobody{i}"), "wrong-password").await;
+        assert_eq!(res.status_code(), 401);
+    }
+
+    let res = attempt(&server, "alice", common::PASSWORD).await;
+    assert_eq!(res.status_code(), 429);
+    assert!(res.headers().contains_key(RETRY_AFTER));
+}
@@ -0,0 +1,190 @@
+mod common;
+
+use rust_axum_rest_api::Services;
+use serde_json::{Value, json};
+use sqlx::PgPool;
+use std::sync::Arc;
+
+async fn request_link(server: &axum_test::TestServer, email: &str) {
+    server
+        .post("/auth/magic-link")
+        .json(&json!({ "email": email }))
+        .await
+        .assert_status_ok();
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn magic_link_signs_in_account_without_password(pool: PgPool) {
+    let (server, mailer) = common::server_with_mailer(pool.clone());
+    // Accounts created before passwords were required have an empty hash.
+    common::insert_user(&pool, "alice", "user").await;
+    sqlx::query("UPDATE users SET password_hash = '' WHERE username = 'alice'")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    request_link(&server, "alice@example.com").await;
+    let token = common::token_from_last_email(&mailer, "alice@example.com");
+
+    let res = server
+        .post("/auth/magic-link/verify")
+        .json(&json!({ "token": token, "scope": "posts:read" }))
+        .await;
+
+    res.assert_status_ok();
+    let body: Value = res.json();
+    assert_eq!(body["user"]["username"], "alice");
+    assert_eq!(body["scope"], "posts:read");
+    assert!(body["refresh_token"].is_string());
+    // The link also proved the address belongs to them.
+    let verified: bool = sqlx::query_scalar(
+        "SELECT email_verified_at IS NOT NULL FROM users WHERE username = 'alice'",
+    )
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert!(verified);
+    let detail: Option<String> = sqlx::query_scalar(
+        "SELECT detail FROM auth_events WHERE event_type = 'login' AND outcome = 'success'",
+    )
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert_eq!(detail.as_deref(), Some("magic_link"));
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn magic_link_is_single_use(pool: PgPool) {
+    let (server, mailer) = common::server_with_mailer(pool.clone());
+    common::register(&server, &pool, "alice", common::PASSWORD).await;
+    request_link(&server, "alice@example.com").await;
+    let token = common::token_from_last_email(&mailer, "alice@example.com");
+    let verify = json!({ "token": token });
+
+    server
+        .post("/auth/magic-link/verify")
+        .json(&verify)
+        .await
+        .assert_status_ok();
+    let res = server.post("/auth/magic-link/verify").json(&verify).await;
+
+    assert_eq!(res.status_code(), 401);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn expired_magic_link_is_rejected(pool: PgPool) {
+    let (server, mailer) = common::server_with_mailer(pool.clone());
+    common::register(&server, &pool, "alice", common::PASSWORD).await;
+    request_link(&server, "alice@example.com").await;
+    let token = common::token_from_last_email(&mailer, "alice@example.com");
+    sqlx::query("UPDATE magic_link_tokens SET expires_at = NOW() - INTERVAL '1 minute'")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let res = server
+        .post("/auth/magic-link/verify")
+        .json(&json!({ "token": token }))
+        .await;
+
+    assert_eq!(res.status_code(), 401);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn newer_link_replaces_older_one(pool: PgPool) {
+    let (server, mailer) = common::server_with_mailer(pool.clone());
+    common::register(&server, &pool, "alice", common::PASSWORD).await;
+    request_link(&server, "alice@example.com").await;
+    let first = common::token_from_last_email(&mailer, "alice@example.com");
+    // Step past the cooldown.
+    sqlx::query("UPDATE magic_link_tokens SET created_at = created_at - INTERVAL '2 minutes'")
+        .execute(&pool)
+        .await
+        .unwrap();
+    request_link(&server, "alice@example.com").await;
+    let second = common::token_from_last_email(&mailer, "alice@example.com");
+
+    let res = server
+        .post("/auth/magic-link/verify")
+        .json(&json!({ "token": first }))
+        .await;
+    assert_eq!(res.status_code(), 401);
+    server
+        .post("/auth/magic-link/verify")
+        .json(&json!({ "token": second }))
+        .await
+        .assert_status_ok();
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn requests_are_rate_limited_per_email(pool: PgPool) {
+    let (server, mailer) = common::server_with_mailer(pool.clone());
+    common::register(&server, &pool, "alice", common::PASSWORD).await;
+    let sent_to_alice = || {
+        mailer
+            .sent()
+            .iter()
+            .filter(|email| email.to == "alice@example.com" && email.subject.contains("sign-in"))
+            .count()
+    };
+
+    request_link(&server, "alice@example.com").await;
+    request_link(&server, "alice@example.com").await;
+    assert_eq!(
+        sent_to_alice(),
+        1,
+        "second request falls inside the cooldown"
+    );
+
+    for _ in 0..10 {
+        sqlx::query("UPDATE magic_link_tokens SET created_at = created_at - INTERVAL '2 minutes'")
+            .execute(&pool)
+            .await
+            .unwrap();
+        request_link(&server, "alice@example.com").await;
+    }
+    assert_eq!(sent_to_alice(), 5, "at most five links per hour");
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn unknown_email_sends_nothing(pool: PgPool) {
+    let (server, mailer) = common::server_with_mailer(pool);
+
+    request_link(&server, "nobody@example.com").await;
+
+    assert!(mailer.sent().is_empty());
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn mail_failure_looks_like_success(pool: PgPool) {
+    let services = Services {
+        mailer: Arc::new(common::FailingMailer),
+        ..common::services()
+    };
+    let server = common::server_with_services(pool.clone(), services);
+    common::register(&server, &pool, "alice", common::PASSWORD).await;
+
+    request_link(&server, "alice@example.com").await;
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn magic_link_still_requires_second_factor(pool: PgPool) {
+    let (server, mailer) = common::server_with_mailer(pool.clone());
+    common::register(&server, &pool, "alice", common::PASSWORD).await;
+    sqlx::query("UPDATE users SET totp_enabled_at = NOW() WHERE username = 'alice'")
+        .execute(&pool)
+        .await
+        .unwrap();
+    request_link(&server, "alice@example.com").await;
+    let token = common::token_from_last_email(&mailer, "alice@example.com");
+
+    let res = server
+        .post("/auth/magic-link/verify")
+        .json(&json!({ "token": token }))
+        .await;
+
+    res.assert_status_ok();
+    let body: Value = res.json();
+    assert_eq!(body["mfa_required"], true);
+    assert!(body.get("access_token").is_none());
+}
@@ -0,0 +1,339 @@
+mod common;
+
+use serde_json::{Value, json};
+use sqlx::PgPool;
+use std::time::{SystemTime, UNIX_EPOCH};
+use totp_rs::{Algorithm, Secret, TOTP};
+
+/// Code for the 30-second step `offset` steps away from now.
+fn code_at(secret: &str, offset: i64) -> String {
+    let bytes = Secret::Encoded(secret.to_string()).to_bytes().unwrap();
+    let totp = TOTP::new_unchecked(Algorithm::SHA1, 6, 1, 30, bytes, None, "alice".to_string());
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64;
+    totp.generate((now + offset * 30) as u64)
+}
+
+fn bearer(session: &Value) -> String {
+    format!("Bearer {}", session["access_token"].as_str().unwrap())
+}
+
+/// Registers alice and enables MFA, returning her TOTP secret and recovery codes.
+async fn enable_mfa(server: &axum_test::TestServer, pool: &PgPool) -> (String, Vec<String>) {
+    common::register(server, pool, "alice", common::PASSWORD).await;
+    let session = common::login(server, "alice", common::PASSWORD).await;
+
+    let res = server
+        .post("/auth/mfa/enroll")
+        .add_header("Authorization", bearer(&session))
+        .await;
+    res.assert_status_ok();
+    let body: Value = res.json();
+    let secret = body["secret"].as_str().unwrap().to_string();
+    assert!(
+        body["otpauth_uri"]
+            .as_str()
+            .unwrap()
+            .starts_with("otpauth://totp/")
+    );
+
+    // Confirm with the previous step so the current one is still usable for login.
+    let res = server
+        .post("/auth/mfa/confirm")
+        .add_header("Authorization", bearer(&session))
+        .json(&json!({ "code": code_at(&secret, -1) }))
+        .await;
+    res.assert_status_ok();
+    let body: Value = res.json();
+    let codes = body["recovery_codes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|c| c.as_str().unwrap().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(codes.len(), 10);
+
+    (secret, codes)
+}
+
+async fn mfa_token(server: &axum_test::TestServer) -> String {
+    let res = server
+        .post("/auth/login")
+        .json(&json!({ "username": "alice", "password": common::PASSWORD }))
+        .await;
+    res.assert_status_ok();
+    let body: Value = res.json();
+    assert_eq!(body["mfa_required"], true);
+    assert!(body.get("access_token").is_none());
+    body["mfa_token"].as_str().unwrap().to_string()
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn login_with_mfa_requires_second_step(pool: PgPool) {
+    let server = common::server(pool.clone());
+    let (secret, _) = enable_mfa(&server, &pool).await;
+    let mfa_token = mfa_token(&server).await;
+
+    let res = server
+        .post("/auth/mfa/verify")
+        .json(&json!({ "mfa_token": mfa_token, "code": code_at(&secret, 0) }))
+        .await;
+    res.assert_status_ok();
+    let body: Value = res.json();
+    assert!(body["access_token"].is_string());
+    assert!(body["refresh_token"].is_string());
+    assert_eq!(body["user"]["username"], "alice");
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn totp_code_cannot_be_replayed(pool: PgPool) {
+    let server = common::server(pool.clone());
+    let (secret, _) = enable_mfa(&server, &pool).await;
+    let code = code_at(&secret, 0);
+
+    let first = mfa_token(&server).await;
+    server
+        .post("/auth/mfa/verify")
+        .json(&json!({ "mfa_token": first, "code": code }))
+        .await
+        .assert_status_ok();
+
+    let second = mfa_token(&server).await;
+    let res = server
+        .post("/auth/mfa/verify")
+        .json(&json!({ "mfa_token": second, "code": code }))
+        .await;
+    assert_eq!(res.status_code(), 401);
+
+    // The code used at confirmation is consumed as well.
+    let res = server
+        .post("/auth/mfa/verify")
+        .json(&json!({ "mfa_token": second, "code": code_at(&secret, -1) }))
+        .await;
+    assert_eq!(res.status_code(), 401);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn recovery_code_works_once(pool: PgPool) {
+    let server = common::server(pool.clone());
+    let (_, codes) = enable_mfa(&server, &pool).await;
+
+    let token = mfa_token(&server).await;
+    server
+        .post("/auth/mfa/verify")
+        .json(&json!({ "mfa_token": token, "recovery_code": codes[0].to_uppercase() }))
+        .await
+        .assert_status_ok();
+
+    let token = mfa_token(&server).await;
+    let res = server
+        .post("/auth/mfa/verify")
+        .json(&json!({ "mfa_token": token, "recovery_code": codes[0] }))
+        .await;
+    assert_eq!(res.status_code(), 401);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn verify_rejects_wrong_code_and_access_tokens(pool: PgPool) {
+    let server = common::server(pool.clone());
+    let (secret, _) = enable_mfa(&server, &pool).await;
+    let token = mfa_token(&server).await;
+
+    // Codes outside the one-step skew window are refused.
+    let res = server
+        .post("/auth/mfa/verify")
+        .json(&json!({ "mfa_token": token, "code": code_at(&secret, 5) }))
+        .await;
+    assert_eq!(res.status_code(), 401);
+
+    // An access token is not accepted in place of the MFA challenge token.
+    let access = common::bearer(1);
+    let access = access.strip_prefix("Bearer ").unwrap();
+    let res = server
+        .post("/auth/mfa/verify")
+        .json(&json!({ "mfa_token": access, "code": code_at(&secret, 0) }))
+        .await;
+    assert_eq!(res.status_code(), 401);
+
+    // Nor is an MFA token usable as an access token.
+    let res = server
+        .get("/user")
+        .add_header("Authorization", format!("Bearer {token}"))
+        .await;
+    assert_eq!(res.status_code(), 401);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn confirm_rejects_invalid_code(pool: PgPool) {
+    let server = common::server(pool.clone());
+    common::register(&server, &pool, "alice", common::PASSWORD).await;
+    let session = common::login(&server, "alice", common::PASSWORD).await;
+
+    let res = server
+        .post("/auth/mfa/confirm")
+        .add_header("Authorization", bearer(&session))
+        .json(&json!({ "code": "123456" }))
+        .await;
+    assert_eq!(res.status_code(), 422);
+
+    server
+        .post("/auth/mfa/enroll")
+        .add_header("Authorization", bearer(&session))
+        .await
+        .assert_status_ok();
+
+    // Still disabled until a valid code is confirmed.
+    let res = server
+        .post("/auth/login")
+        .json(&json!({ "username": "alice", "password": common::PASSWORD }))
+        .await;
+    let body: Value = res.json();
+    assert!(body["access_token"].is_string());
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn disable_requires_password(pool: PgPool) {
+    let server = common::server(pool.clone());
+    let (secret, _) = enable_mfa(&server, &pool).await;
+    let token = mfa_token(&server).await;
+    let res = server
+        .post("/auth/mfa/verify")
+        .json(&json!({ "mfa_token": token, "code": code_at(&secret, 0) }))
+        .await;
+    let session: Value = res.json();
+
+    let res = server
+        .delete("/auth/mfa")
+        .add_header("Authorization", bearer(&session))
+        .json(&json!({ "password": "wrong-password" }))
+        .await;
+    assert_eq!(res.status_code(), 401);
+
+    server
+        .delete("/auth/mfa")
+        .add_header("Authorization", bearer(&session))
+        .json(&json!({ "password": common::PASSWORD }))
+        .await
+        .assert_status_ok();
+
+    let body = common::login(&server, "alice", common::PASSWORD).await;
+    assert!(body["access_token"].is_string());
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn disable_locks_out_repeated_wrong_passwords(pool: PgPool) {
+    let server = common::server(pool.clone());
+    let (secret, _) = enable_mfa(&server, &pool).await;
+    let token = mfa_token(&server).await;
+    let session: Value = server
+        .post("/auth/mfa/verify")
+        .json(&json!({ "mfa_token": token, "code": code_at(&secret, 0) }))
+        .await
+        .json();
+
+    for _ in 0..5 {
+        let res = server
+            .delete("/auth/mfa")
+            .add_header("Authorization", bearer(&session))
+            .json(&json!({ "password": "wrong-password" }))
+            .await;
+        assert_eq!(res.status_code(), 401);
+    }
+
+    let res = server
+        .delete("/auth/mfa")
+        .add_header("Authorization", bearer(&session))
+        .json(&json!({ "password": common::PASSWORD }))
+        .await;
+    assert_eq!(res.status_code(), 429);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn passwordless_account_disables_with_a_code(pool: PgPool) {
+    let server = common::server(pool.clone());
+    // As provisioned by OIDC or LDAP sign-in.
+    let user_id: i32 = sqlx::query_scalar(
+        "INSERT INTO users (username, email, password_hash, email_verified_at)
+        VALUES ('alice', 'alice@example.com', '', NOW()) RETURNING id",
+    )
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    let res = server
+        .post("/auth/mfa/enroll")
+        .add_header("Authorization", common::bearer(user_id))
+        .await;
+    let secret = res.json::<Value>()["secret"].as_str().unwrap().to_string();
+    server
+        .post("/auth/mfa/confirm")
+        .add_header("Authorization", common::bearer(user_id))
+        .json(&json!({ "code": code_at(&secret, -1) }))
+        .await
+        .assert_status_ok();
+
+    let res = server
+        .delete("/auth/mfa")
+        .add_header("Authorization", common::bearer(user_id))
+        .json(&json!({ "password": "" }))
+        .await;
+    assert_eq!(res.status_code(), 422);
+    let res = server
+        .delete("/auth/mfa")
+        .add_header("Authorization", common::bearer(user_id))
+        .json(&json!({ "code": code_at(&secret, -1) }))
+        .await;
+    assert_eq!(
+        res.status_code(),
+        401,
+        "the confirming code can't be replayed"
+    );
+
+    server
+        .delete("/auth/mfa")
+        .add_header("Authorization", common::bearer(user_id))
+        .json(&json!({ "code": code_at(&secret, 0) }))
+        .await
+        .assert_status_ok();
+    let enabled: bool =
+        sqlx::query_scalar("SELECT totp_enabled_at IS NOT NULL FROM users WHERE id = $1")
+            .bind(user_id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert!(!enabled);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn second_factor_outcomes_are_recorded(pool: PgPool) {
+    let server = common::server(pool.clone());
+    let (secret, _) = enable_mfa(&server, &pool).await;
+    let token = mfa_token(&server).await;
+
+    server
+        .post("/auth/mfa/verify")
+        .json(&json!({ "mfa_token": token, "code": code_at(&secret, 5) }))
+        .await
+        .assert_status_unauthorized();
+    server
+        .post("/auth/mfa/verify")
+        .json(&json!({ "mfa_token": token, "code": code_at(&secret, 0) }))
+        .await
+        .assert_status_ok();
+
+    let recorded: Vec<(String, Option<String>)> = sqlx::query_as(
+        "SELECT outcome, detail FROM auth_events WHERE event_type = 'login'
+        ORDER BY id DESC LIMIT 2",
+    )
+    .fetch_all(&pool)
+    .await
+    .unwrap();
+    assert_eq!(
+        recorded,
+        vec![
+            ("success".to_string(), Some("mfa".to_string())),
+            ("failure".to_string(), Some("invalid_mfa_code".to_string())),
+        ]
+    );
+}
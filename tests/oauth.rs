@@ -0,0 +1,493 @@
+mod common;
+
+use axum_test::TestServer;
+use base64::{Engine, engine::general_purpose::STANDARD};
+use rust_axum_rest_api::auth::oidc::{pkce_challenge, random_token};
+use serde_json::{Value, json};
+use sqlx::PgPool;
+
+const REDIRECT_URI: &str = "https://app.example.com/callback";
+
+struct Client {
+    id: String,
+    secret: Option<String>,
+}
+
+impl Client {
+    fn basic(&self) -> String {
+        let credentials = format!("{}:{}", self.id, self.secret.as_deref().unwrap_or_default());
+        format!("Basic {}", STANDARD.encode(credentials))
+    }
+}
+
+async fn register_client(server: &TestServer, owner_id: i32, confidential: bool) -> Client {
+    let res = server
+        .post("/oauth/clients")
+        .add_header("Authorization", common::bearer(owner_id))
+        .json(&json!({
+            "name": "Partner App",
+            "redirect_uris": [REDIRECT_URI],
+            "scopes": ["posts:read", "users:read"],
+            "confidential": confidential,
+        }))
+        .await;
+    res.assert_status_ok();
+    let body: Value = res.json();
+    Client {
+        id: body["id"].as_str().unwrap().to_string(),
+        secret: body["client_secret"].as_str().map(str::to_string),
+    }
+}
+
+/// Plays the user approving the client on the consent screen; returns the code and verifier.
+async fn approve(server: &TestServer, user_id: i32, client: &Client) -> (String, String) {
+    let verifier = random_token();
+    let res = server
+        .post("/oauth/authorize")
+        .add_header("Authorization", common::bearer(user_id))
+        .json(&json!({
+            "response_type": "code",
+            "client_id": client.id,
+            "redirect_uri": REDIRECT_URI,
+            "scope": "posts:read",
+            "state": "xyz",
+            "code_challenge": pkce_challenge(&verifier),
+            "code_challenge_method": "S256",
+            "approved": true,
+        }))
+        .await;
+    res.assert_status_ok();
+
+    let redirect =
+        reqwest::Url::parse(res.json::<Value>()["redirect_to"].as_str().unwrap()).unwrap();
+    let param = |name: &str| {
+        redirect
+            .query_pairs()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.to_string())
+    };
+    assert_eq!(param("state").as_deref(), Some("xyz"));
+    (param("code").unwrap(), verifier)
+}
+
+async fn exchange_code(server: &TestServer, client: &Client, code: &str, verifier: &str) -> Value {
+    let res = server
+        .post("/oauth/token")
+        .add_header("Authorization", client.basic())
+        .form(&[
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", REDIRECT_URI),
+            ("code_verifier", verifier),
+        ])
+        .await;
+    res.assert_status_ok();
+    assert_eq!(res.header("Cache-Control"), "no-store");
+    res.json()
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn authorization_code_flow_issues_scoped_tokens(pool: PgPool) {
+    let server = common::server(pool.clone());
+    let owner = common::insert_user(&pool, "developer", "user").await;
+    let user = common::insert_user(&pool, "alice", "user").await;
+    let client = register_client(&server, owner, true).await;
+
+    let consent: Value = server
+        .get("/oauth/authorize")
+        .add_header("Authorization", common::bearer(user))
+        .add_query_params(json!({
+            "response_type": "code",
+            "client_id": client.id,
+            "redirect_uri": REDIRECT_URI,
+            "code_challenge": pkce_challenge(&random_token()),
+            "code_challenge_method": "S256",
+        }))
+        .await
+        .json();
+    assert_eq!(consent["client_name"], "Partner App");
+    assert_eq!(consent["scope"], "posts:read users:read");
+
+    let (code, verifier) = approve(&server, user, &client).await;
+    let tokens = exchange_code(&server, &client, &code, &verifier).await;
+    assert_eq!(tokens["token_type"], "Bearer");
+    assert_eq!(tokens["scope"], "posts:read");
+    assert!(tokens["refresh_token"].is_string());
+
+    server
+        .post("/post")
+        .add_header("Authorization", common::bearer(user))
+        .json(&json!({ "title": "Hello", "body": "World" }))
+        .await
+        .assert_status_ok();
+    let access = format!("Bearer {}", tokens["access_token"].as_str().unwrap());
+    server
+        .get("/posts")
+        .add_header("Authorization", &access)
+        .await
+        .assert_status_ok();
+    // Only what the user consented to.
+    assert_eq!(
+        server
+            .get("/user")
+            .add_header("Authorization", &access)
+            .await
+            .status_code(),
+        403
+    );
+    // And never account management, whatever the scope.
+    assert_eq!(
+        server
+            .get("/auth/sessions")
+            .add_header("Authorization", &access)
+            .await
+            .status_code(),
+        403
+    );
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn code_requires_matching_verifier_and_is_single_use(pool: PgPool) {
+    let server = common::server(pool.clone());
+    let owner = common::insert_user(&pool, "developer", "user").await;
+    let client = register_client(&server, owner, true).await;
+    let (code, verifier) = approve(&server, owner, &client).await;
+    let exchange = |verifier: String| {
+        server
+            .post("/oauth/token")
+            .add_header("Authorization", client.basic())
+            .form(&[
+                ("grant_type", "authorization_code".to_string()),
+                ("code", code.clone()),
+                ("redirect_uri", REDIRECT_URI.to_string()),
+                ("code_verifier", verifier),
+            ])
+    };
+
+    let res = exchange(random_token()).await;
+    assert_eq!(res.status_code(), 400);
+    assert_eq!(res.json::<Value>()["error"], "invalid_grant");
+
+    // The failed attempt consumed the code too.
+    let res = exchange(verifier).await;
+    assert_eq!(res.json::<Value>()["error"], "invalid_grant");
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn denied_consent_redirects_with_access_denied(pool: PgPool) {
+    let server = common::server(pool.clone());
+    let owner = common::insert_user(&pool, "developer", "user").await;
+    let client = register_client(&server, owner, true).await;
+
+    let res = server
+        .post("/oauth/authorize")
+        .add_header("Authorization", common::bearer(owner))
+        .json(&json!({
+            "response_type": "code",
+            "client_id": client.id,
+            "redirect_uri": REDIRECT_URI,
+            "state": "xyz",
+            "code_challenge": pkce_challenge(&random_token()),
+            "code_challenge_method": "S256",
+            "approved": false,
+        }))
+        .await;
+
+    res.assert_status_ok();
+    assert_eq!(
+        res.json::<Value>()["redirect_to"],
+        format!("{REDIRECT_URI}?error=access_denied&state=xyz")
+    );
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn authorize_rejects_unregistered_redirect_and_missing_pkce(pool: PgPool) {
+    let server = common::server(pool.clone());
+    let owner = common::insert_user(&pool, "developer", "user").await;
+    let client = register_client(&server, owner, true).await;
+    let authorize = |redirect_uri: &str, pkce: bool| {
+        let mut params = json!({
+            "response_type": "code",
+            "client_id": client.id,
+            "redirect_uri": redirect_uri,
+        });
+        if pkce {
+            params["code_challenge"] = json!(pkce_challenge(&random_token()));
+            params["code_challenge_method"] = json!("S256");
+        }
+        server
+            .get("/oauth/authorize")
+            .add_header("Authorization", common::bearer(owner))
+            .add_query_params(params)
+    };
+
+    assert_eq!(
+        authorize("https://evil.example.com/callback", true)
+            .await
+            .status_code(),
+        422
+    );
+    assert_eq!(authorize(REDIRECT_URI, false).await.status_code(), 422);
+    authorize(REDIRECT_URI, true).await.assert_status_ok();
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn refresh_grant_rotates_and_stays_bound_to_the_client(pool: PgPool) {
+    let server = common::server(pool.clone());
+    let owner = common::insert_user(&pool, "developer", "user").await;
+    let client = register_client(&server, owner, true).await;
+    let other = register_client(&server, owner, true).await;
+    let (code, verifier) = approve(&server, owner, &client).await;
+    let tokens = exchange_code(&server, &client, &code, &verifier).await;
+    let refresh_token = tokens["refresh_token"].as_str().unwrap();
+
+    // Another client can't redeem it, and neither can the first-party refresh endpoint.
+    let res = server
+        .post("/oauth/token")
+        .add_header("Authorization", other.basic())
+        .form(&[
+            ("grant_type", "refresh_token"),
+            ("refresh_token", refresh_token),
+        ])
+        .await;
+    assert_eq!(res.json::<Value>()["error"], "invalid_grant");
+    let res = server
+        .post("/auth/refresh")
+        .json(&json!({ "refresh_token": refresh_token }))
+        .await;
+    assert_eq!(res.status_code(), 401);
+
+    let res = server
+        .post("/oauth/token")
+        .add_header("Authorization", client.basic())
+        .form(&[
+            ("grant_type", "refresh_token"),
+            ("refresh_token", refresh_token),
+        ])
+        .await;
+    res.assert_status_ok();
+    let rotated: Value = res.json();
+    assert_eq!(rotated["scope"], "posts:read");
+    assert_ne!(rotated["refresh_token"], tokens["refresh_token"]);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn client_credentials_acts_as_owner(pool: PgPool) {
+    let server = common::server(pool.clone());
+    let owner = common::insert_user(&pool, "developer", "user").await;
+    let client = register_client(&server, owner, true).await;
+
+    let res = server
+        .post("/oauth/token")
+        .form(&[
+            ("grant_type", "client_credentials"),
+            ("client_id", &client.id),
+            ("client_secret", client.secret.as_deref().unwrap()),
+            ("scope", "users:read"),
+        ])
+        .await;
+
+    res.assert_status_ok();
+    let body: Value = res.json();
+    assert_eq!(body["scope"], "users:read");
+    assert!(body.get("refresh_token").is_none());
+    let me: Value = server
+        .get("/user")
+        .add_header(
+            "Authorization",
+            format!("Bearer {}", body["access_token"].as_str().unwrap()),
+        )
+        .await
+        .json();
+    assert_eq!(me["id"], owner);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn public_clients_cannot_use_client_credentials(pool: PgPool) {
+    let server = common::server(pool.clone());
+    let owner = common::insert_user(&pool, "developer", "user").await;
+    let client = register_client(&server, owner, false).await;
+    assert!(client.secret.is_none());
+
+    let res = server
+        .post("/oauth/token")
+        .form(&[
+            ("grant_type", "client_credentials"),
+            ("client_id", &client.id),
+        ])
+        .await;
+
+    assert_eq!(res.json::<Value>()["error"], "unauthorized_client");
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn wrong_secret_is_invalid_client(pool: PgPool) {
+    let server = common::server(pool.clone());
+    let owner = common::insert_user(&pool, "developer", "user").await;
+    let client = register_client(&server, owner, true).await;
+    let impostor = Client {
+        id: client.id.clone(),
+        secret: Some("guess".to_string()),
+    };
+
+    let res = server
+        .post("/oauth/token")
+        .add_header("Authorization", impostor.basic())
+        .form(&[("grant_type", "client_credentials")])
+        .await;
+
+    assert_eq!(res.status_code(), 401);
+    assert_eq!(res.json::<Value>()["error"], "invalid_client");
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn introspection_and_revocation(pool: PgPool) {
+    let server = common::server(pool.clone());
+    let owner = common::insert_user(&pool, "developer", "user").await;
+    let client = register_client(&server, owner, true).await;
+    let other = register_client(&server, owner, true).await;
+    let (code, verifier) = approve(&server, owner, &client).await;
+    let tokens = exchange_code(&server, &client, &code, &verifier).await;
+    let introspect = |client: &Client, token: &str| {
+        server
+            .post("/oauth/introspect")
+            .add_header("Authorization", client.basic())
+            .form(&[("token", token.to_string())])
+    };
+
+    let access = tokens["access_token"].as_str().unwrap();
+    let info: Value = introspect(&client, access).await.json();
+    assert_eq!(info["active"], true);
+    assert_eq!(info["token_type"], "access_token");
+    assert_eq!(info["scope"], "posts:read");
+    assert_eq!(info["sub"], owner.to_string());
+    assert_eq!(info["client_id"], client.id);
+
+    // Another client learns nothing about it.
+    let info: Value = introspect(&other, access).await.json();
+    assert_eq!(info, json!({ "active": false }));
+
+    let refresh_token = tokens["refresh_token"].as_str().unwrap();
+    let info: Value = introspect(&client, refresh_token).await.json();
+    assert_eq!(info["token_type"], "refresh_token");
+
+    server
+        .post("/oauth/revoke")
+        .add_header("Authorization", client.basic())
+        .form(&[("token", refresh_token)])
+        .await
+        .assert_status_ok();
+    let info: Value = introspect(&client, refresh_token).await.json();
+    assert_eq!(info["active"], false);
+    // Revoking the grant also revoked the access token issued with it.
+    let info: Value = introspect(&client, access).await.json();
+    assert_eq!(info, json!({ "active": false }));
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn access_tokens_revoked_by_the_user_introspect_as_inactive(pool: PgPool) {
+    let server = common::server(pool.clone());
+    common::register(&server, &pool, "alice", common::PASSWORD).await;
+    let session = common::login(&server, "alice", common::PASSWORD).await;
+    let owner = session["user"]["id"].as_i64().unwrap() as i32;
+    let client = register_client(&server, owner, true).await;
+    let (code, verifier) = approve(&server, owner, &client).await;
+    let tokens = exchange_code(&server, &client, &code, &verifier).await;
+    let access = tokens["access_token"].as_str().unwrap().to_string();
+
+    server
+        .post("/auth/logout-all")
+        .add_header(
+            "Authorization",
+            format!("Bearer {}", session["access_token"].as_str().unwrap()),
+        )
+        .await
+        .assert_status_ok();
+
+    let res = server
+        .post("/oauth/introspect")
+        .add_header("Authorization", client.basic())
+        .form(&[("token", access)])
+        .await;
+    assert_eq!(res.json::<Value>(), json!({ "active": false }));
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn grants_show_up_as_revocable_sessions(pool: PgPool) {
+    let server = common::server(pool.clone());
+    let owner = common::insert_user(&pool, "developer", "user").await;
+    let client = register_client(&server, owner, true).await;
+    let (code, verifier) = approve(&server, owner, &client).await;
+    exchange_code(&server, &client, &code, &verifier).await;
+
+    let sessions: Value = server
+        .get("/auth/sessions")
+        .add_header("Authorization", common::bearer(owner))
+        .await
+        .json();
+
+    assert_eq!(sessions.as_array().unwrap().len(), 1);
+    assert_eq!(sessions[0]["client_name"], "Partner App");
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn clients_cannot_request_scopes_beyond_owner_role(pool: PgPool) {
+    let server = common::server(pool.clone());
+    let owner = common::insert_user(&pool, "developer", "user").await;
+
+    let res = server
+        .post("/oauth/clients")
+        .add_header("Authorization", common::bearer(owner))
+        .json(&json!({
+            "name": "Admin Tool",
+            "redirect_uris": [REDIRECT_URI],
+            "scopes": ["users:admin"],
+        }))
+        .await;
+
+    assert_eq!(res.status_code(), 403);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn client_tokens_cannot_revoke_api_keys(pool: PgPool) {
+    let server = common::server(pool.clone());
+    let owner = common::insert_user(&pool, "developer", "user").await;
+    let res = server
+        .post("/oauth/clients")
+        .add_header("Authorization", common::bearer(owner))
+        .json(&json!({
+            "name": "Key Janitor",
+            "redirect_uris": [REDIRECT_URI],
+            "scopes": ["users:write"],
+            "confidential": true,
+        }))
+        .await;
+    res.assert_status_ok();
+    let client: Value = res.json();
+    let key: Value = server
+        .post("/auth/api-keys")
+        .add_header("Authorization", common::bearer(owner))
+        .json(&json!({ "name": "ci", "scopes": ["posts:read"] }))
+        .await
+        .json();
+
+    let token: Value = server
+        .post("/oauth/token")
+        .form(&[
+            ("grant_type", "client_credentials"),
+            ("client_id", client["id"].as_str().unwrap()),
+            ("client_secret", client["client_secret"].as_str().unwrap()),
+            ("scope", "users:write"),
+        ])
+        .await
+        .json();
+    let res = server
+        .delete(&format!("/auth/api-keys/{}", key["id"].as_str().unwrap()))
+        .add_header(
+            "Authorization",
+            format!("Bearer {}", token["access_token"].as_str().unwrap()),
+        )
+        .await;
+
+    assert_eq!(res.status_code(), 403);
+}
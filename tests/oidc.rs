@@ -0,0 +1,386 @@
+mod common;
+
+use axum::{
+    Form, Json, Router,
+    extract::{Query, State},
+    http::{StatusCode, header::LOCATION},
+    response::{IntoResponse, Redirect, Response},
+    routing::{get, post},
+};
+use axum_test::{TestResponse, TestServer};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use jsonwebtoken::Algorithm;
+use rust_axum_rest_api::{
+    Services,
+    auth::{
+        keys::JwtKeys,
+        oidc::{OidcProvider, OidcProviders},
+    },
+};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const CLIENT_ID: &str = "test-client";
+const REDIRECT_URI: &str = "http://localhost:3000/oidc/mock/callback";
+const IDP_PRIVATE_KEY: &str = include_str!("fixtures/jwt/rsa_private.pem");
+
+/// The account the mock provider signs everyone in as.
+#[derive(Clone)]
+struct IdpUser {
+    sub: &'static str,
+    email: &'static str,
+    email_verified: bool,
+}
+
+struct PendingCode {
+    code_challenge: String,
+    nonce: String,
+}
+
+/// A minimal OpenID Connect provider serving discovery, authorize, token and JWKS endpoints.
+#[derive(Clone)]
+struct MockIdp {
+    issuer: String,
+    keys: Arc<JwtKeys>,
+    user: Arc<Mutex<IdpUser>>,
+    codes: Arc<Mutex<HashMap<String, PendingCode>>>,
+    /// Signs ID tokens with this nonce instead of the one the client asked for.
+    nonce_override: Arc<Mutex<Option<String>>>,
+}
+
+impl MockIdp {
+    async fn start() -> Self {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let idp = MockIdp {
+            issuer: format!("http://{}", listener.local_addr().unwrap()),
+            keys: Arc::new(
+                JwtKeys::from_private_pem(Algorithm::RS256, IDP_PRIVATE_KEY, Some("idp".into()))
+                    .unwrap(),
+            ),
+            user: Arc::new(Mutex::new(IdpUser {
+                sub: "idp-user-1",
+                email: "alice@example.com",
+                email_verified: true,
+            })),
+            codes: Arc::default(),
+            nonce_override: Arc::default(),
+        };
+
+        let app = Router::new()
+            .route("/.well-known/openid-configuration", get(discovery))
+            .route("/authorize", get(authorize))
+            .route("/token", post(token))
+            .route("/jwks", get(jwks))
+            .with_state(idp.clone());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        idp
+    }
+
+    fn sign_in_as(&self, user: IdpUser) {
+        *self.user.lock().unwrap() = user;
+    }
+}
+
+async fn discovery(State(idp): State<MockIdp>) -> Json<Value> {
+    Json(json!({
+        "issuer": idp.issuer,
+        "authorization_endpoint": format!("{}/authorize", idp.issuer),
+        "token_endpoint": format!("{}/token", idp.issuer),
+        "jwks_uri": format!("{}/jwks", idp.issuer),
+    }))
+}
+
+async fn jwks(State(idp): State<MockIdp>) -> Response {
+    Json(idp.keys.jwks()).into_response()
+}
+
+async fn authorize(
+    State(idp): State<MockIdp>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Response {
+    if params["client_id"] != CLIENT_ID
+        || params["response_type"] != "code"
+        || params["code_challenge_method"] != "S256"
+        || params["redirect_uri"] != REDIRECT_URI
+    {
+        return StatusCode::BAD_REQUEST.into_response();
+    }
+
+    let code = format!("code-{}", idp.codes.lock().unwrap().len());
+    idp.codes.lock().unwrap().insert(
+        code.clone(),
+        PendingCode {
+            code_challenge: params["code_challenge"].clone(),
+            nonce: params["nonce"].clone(),
+        },
+    );
+    Redirect::to(&format!(
+        "{REDIRECT_URI}?code={code}&state={}",
+        params["state"]
+    ))
+    .into_response()
+}
+
+#[derive(Deserialize)]
+struct TokenRequest {
+    grant_type: String,
+    code: String,
+    client_id: String,
+    code_verifier: String,
+}
+
+async fn token(State(idp): State<MockIdp>, Form(request): Form<TokenRequest>) -> Response {
+    let Some(pending) = idp.codes.lock().unwrap().remove(&request.code) else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+    let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(request.code_verifier.as_bytes()));
+    if request.grant_type != "authorization_code"
+        || request.client_id != CLIENT_ID
+        || challenge != pending.code_challenge
+    {
+        return StatusCode::BAD_REQUEST.into_response();
+    }
+
+    let user = idp.user.lock().unwrap().clone();
+    let nonce = idp
+        .nonce_override
+        .lock()
+        .unwrap()
+        .clone()
+        .unwrap_or(pending.nonce);
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let id_token = idp
+        .keys
+        .encode(&json!({
+            "iss": idp.issuer,
+            "aud": CLIENT_ID,
+            "sub": user.sub,
+            "iat": now,
+            "exp": now + 300,
+            "nonce": nonce,
+            "email": user.email,
+            "email_verified": user.email_verified,
+            "preferred_username": "alice",
+        }))
+        .unwrap();
+
+    Json(
+        json!({ "access_token": "idp-access-token", "token_type": "Bearer", "id_token": id_token }),
+    )
+    .into_response()
+}
+
+fn oidc_server(pool: PgPool, idp: &MockIdp) -> TestServer {
+    let provider = OidcProvider::new("mock", &idp.issuer, CLIENT_ID, None, REDIRECT_URI);
+    let services = Services {
+        oidc: Arc::new(OidcProviders::new(vec![provider])),
+        ..common::services()
+    };
+    common::server_with_services(pool, services)
+}
+
+/// Plays the browser: starts the flow, follows it through the provider and returns the code
+/// and state the front end would receive.
+async fn authorize_at_idp(server: &TestServer) -> (String, String) {
+    let res = server.post("/auth/oidc/mock/authorize").await;
+    res.assert_status_ok();
+    let url = res.json::<Value>()["authorization_url"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap();
+    let redirect = client.get(url).send().await.unwrap();
+    assert_eq!(redirect.status(), StatusCode::SEE_OTHER);
+    let location = reqwest::Url::parse(redirect.headers()[LOCATION].to_str().unwrap()).unwrap();
+    let param = |name: &str| {
+        location
+            .query_pairs()
+            .find(|(key, _)| key == name)
+            .unwrap()
+            .1
+            .to_string()
+    };
+    (param("code"), param("state"))
+}
+
+async fn sign_in(server: &TestServer) -> TestResponse {
+    let (code, state) = authorize_at_idp(server).await;
+    server
+        .post("/auth/oidc/mock/callback")
+        .json(&json!({ "code": code, "state": state }))
+        .await
+}
+
+async fn user_count(pool: &PgPool) -> i64 {
+    sqlx::query_scalar("SELECT COUNT(*) FROM users")
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn first_sign_in_provisions_a_linked_account(pool: PgPool) {
+    let idp = MockIdp::start().await;
+    let server = oidc_server(pool.clone(), &idp);
+
+    let res = sign_in(&server).await;
+    res.assert_status_ok();
+    let body: Value = res.json();
+    assert_eq!(body["user"]["username"], "alice");
+    assert_eq!(body["user"]["email"], "alice@example.com");
+    assert!(body["access_token"].is_string());
+
+    // Signing in again finds the same account through the identity link.
+    let again: Value = sign_in(&server).await.json();
+    assert_eq!(again["user"]["id"], body["user"]["id"]);
+    assert_eq!(user_count(&pool).await, 1);
+
+    let identities: Value = server
+        .get("/user/identities")
+        .add_header(
+            "Authorization",
+            format!("Bearer {}", again["access_token"].as_str().unwrap()),
+        )
+        .await
+        .json();
+    assert_eq!(identities.as_array().unwrap().len(), 1);
+    assert_eq!(identities[0]["provider"], "mock");
+    let logins: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM auth_events WHERE event_type = 'login' AND detail = 'oidc'",
+    )
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert_eq!(logins, 2);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn verified_email_links_existing_account(pool: PgPool) {
+    let idp = MockIdp::start().await;
+    let server = oidc_server(pool.clone(), &idp);
+    common::register(&server, &pool, "alice", common::PASSWORD).await;
+
+    let res = sign_in(&server).await;
+
+    res.assert_status_ok();
+    assert_eq!(res.json::<Value>()["user"]["username"], "alice");
+    assert_eq!(user_count(&pool).await, 1);
+    // The password still works alongside the linked identity.
+    common::login(&server, "alice", common::PASSWORD).await;
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn unverified_email_does_not_take_over_existing_account(pool: PgPool) {
+    let idp = MockIdp::start().await;
+    let server = oidc_server(pool.clone(), &idp);
+    common::register(&server, &pool, "alice", common::PASSWORD).await;
+    idp.sign_in_as(IdpUser {
+        sub: "attacker",
+        email: "alice@example.com",
+        email_verified: false,
+    });
+
+    let res = sign_in(&server).await;
+
+    assert_eq!(res.status_code(), 409);
+    let linked: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM user_identities")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(linked, 0);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn verified_email_does_not_link_unverified_account(pool: PgPool) {
+    let idp = MockIdp::start().await;
+    let server = oidc_server(pool.clone(), &idp);
+    // Registered by someone who never confirmed they own the address.
+    server
+        .post("/user")
+        .json(&json!({
+            "username": "squatter",
+            "email": "alice@example.com",
+            "password": common::PASSWORD
+        }))
+        .await
+        .assert_status_ok();
+
+    let res = sign_in(&server).await;
+
+    assert_eq!(res.status_code(), 409);
+    let linked: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM user_identities")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(linked, 0);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn state_cannot_be_replayed(pool: PgPool) {
+    let idp = MockIdp::start().await;
+    let server = oidc_server(pool, &idp);
+    let (code, state) = authorize_at_idp(&server).await;
+    let callback = json!({ "code": code, "state": state });
+
+    server
+        .post("/auth/oidc/mock/callback")
+        .json(&callback)
+        .await
+        .assert_status_ok();
+    let res = server
+        .post("/auth/oidc/mock/callback")
+        .json(&callback)
+        .await;
+
+    assert_eq!(res.status_code(), 401);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn forged_state_is_rejected(pool: PgPool) {
+    let idp = MockIdp::start().await;
+    let server = oidc_server(pool, &idp);
+    let (code, _) = authorize_at_idp(&server).await;
+
+    let res = server
+        .post("/auth/oidc/mock/callback")
+        .json(&json!({ "code": code, "state": "made-up" }))
+        .await;
+
+    assert_eq!(res.status_code(), 401);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn id_token_with_wrong_nonce_is_rejected(pool: PgPool) {
+    let idp = MockIdp::start().await;
+    let server = oidc_server(pool.clone(), &idp);
+    *idp.nonce_override.lock().unwrap() = Some("replayed-nonce".to_string());
+
+    let res = sign_in(&server).await;
+
+    assert_eq!(res.status_code(), 401);
+    assert_eq!(user_count(&pool).await, 0);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn unknown_provider_returns_404(pool: PgPool) {
+    let idp = MockIdp::start().await;
+    let server = oidc_server(pool, &idp);
+
+    let res = server.post("/auth/oidc/nope/authorize").await;
+
+    assert_eq!(res.status_code(), 404);
+}
@@ -0,0 +1,238 @@
+mod common;
+
+use rust_axum_rest_api::Services;
+use serde_json::json;
+use sqlx::PgPool;
+use std::sync::Arc;
+
+#[sqlx::test(migrations = "./migrations")]
+async fn forgot_password_unknown_email_sends_nothing(pool: PgPool) {
+    let (server, mailer) = common::server_with_mailer(pool);
+
+    let res = server
+        .post("/auth/forgot-password")
+        .json(&json!({ "email": "nobody@example.com" }))
+        .await;
+
+    assert_eq!(res.status_code(), 200);
+    assert!(mailer.sent().is_empty());
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn forgot_password_mail_failure_looks_like_success(pool: PgPool) {
+    let services = Services {
+        mailer: Arc::new(common::FailingMailer),
+        ..common::services()
+    };
+    let server = common::server_with_services(pool.clone(), services);
+    common::register(&server, &pool, "alice", common::PASSWORD).await;
+
+    let res = server
+        .post("/auth/forgot-password")
+        .json(&json!({ "email": "alice@example.com" }))
+        .await;
+
+    assert_eq!(res.status_code(), 200);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn reset_password_changes_password_and_revokes_sessions(pool: PgPool) {
+    let (server, mailer) = common::server_with_mailer(pool.clone());
+    common::register(&server, &pool, "alice", common::PASSWORD).await;
+    let session = common::login(&server, "alice", common::PASSWORD).await;
+
+    server
+        .post("/auth/forgot-password")
+        .json(&json!({ "email": "alice@example.com" }))
+        .await
+        .assert_status_ok();
+    let token = common::token_from_last_email(&mailer, "alice@example.com");
+
+    let res = server
+        .post("/auth/reset-password")
+        .json(&json!({ "token": token, "new_password": "a-brand-new-password" }))
+        .await;
+    assert_eq!(res.status_code(), 200);
+
+    let res = server
+        .post("/auth/refresh")
+        .json(&json!({ "refresh_token": session["refresh_token"] }))
+        .await;
+    assert_eq!(res.status_code(), 401, "existing sessions must be revoked");
+
+    let res = server
+        .post("/auth/login")
+        .json(&json!({ "username": "alice", "password": common::PASSWORD }))
+        .await;
+    assert_eq!(res.status_code(), 401);
+    common::login(&server, "alice", "a-brand-new-password").await;
+
+    let res = server
+        .post("/auth/reset-password")
+        .json(&json!({ "token": token, "new_password": "yet-another-password" }))
+        .await;
+    assert_eq!(res.status_code(), 401, "reset tokens are single-use");
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn reset_password_rejects_expired_token(pool: PgPool) {
+    let (server, mailer) = common::server_with_mailer(pool.clone());
+    common::register(&server, &pool, "alice", common::PASSWORD).await;
+
+    server
+        .post("/auth/forgot-password")
+        .json(&json!({ "email": "alice@example.com" }))
+        .await;
+    let token = common::token_from_last_email(&mailer, "alice@example.com");
+
+    sqlx::query("UPDATE password_reset_tokens SET expires_at = NOW() - INTERVAL '1 minute'")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let res = server
+        .post("/auth/reset-password")
+        .json(&json!({ "token": token, "new_password": "a-brand-new-password" }))
+        .await;
+    assert_eq!(res.status_code(), 401);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn change_password_requires_current_password(pool: PgPool) {
+    let server = common::server(pool.clone());
+    common::register(&server, &pool, "alice", common::PASSWORD).await;
+    let session = common::login(&server, "alice", common::PASSWORD).await;
+
+    let res = server
+        .put("/user/password")
+        .add_header(
+            "Authorization",
+            format!("Bearer {}", session["access_token"].as_str().unwrap()),
+        )
+        .json(&json!({ "current_password": "wrong", "new_password": "a-brand-new-password" }))
+        .await;
+
+    assert_eq!(res.status_code(), 401);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn change_password_locks_out_repeated_wrong_guesses(pool: PgPool) {
+    let server = common::server(pool.clone());
+    common::register(&server, &pool, "alice", common::PASSWORD).await;
+    let session = common::login(&server, "alice", common::PASSWORD).await;
+    let change = |current_password: &'static str| {
+        server
+            .put("/user/password")
+            .add_header(
+                "Authorization",
+                format!("Bearer {}", session["access_token"].as_str().unwrap()),
+            )
+            .json(&json!({
+                "current_password": current_password,
+                "new_password": "a-brand-new-password"
+            }))
+    };
+
+    for _ in 0..5 {
+        assert_eq!(change("wrong").await.status_code(), 401);
+    }
+
+    assert_eq!(change(common::PASSWORD).await.status_code(), 429);
+    let res = server
+        .post("/auth/login")
+        .json(&json!({ "username": "alice", "password": common::PASSWORD }))
+        .await;
+    assert_eq!(res.status_code(), 429);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn change_password_enforces_policy(pool: PgPool) {
+    let server = common::server(pool.clone());
+    common::register(&server, &pool, "alice", common::PASSWORD).await;
+    let session = common::login(&server, "alice", common::PASSWORD).await;
+
+    let res = server
+        .put("/user/password")
+        .add_header(
+            "Authorization",
+            format!("Bearer {}", session["access_token"].as_str().unwrap()),
+        )
+        .json(&json!({ "current_password": common::PASSWORD, "new_password": "short" }))
+        .await;
+
+    assert_eq!(res.status_code(), 422);
+    let body: serde_json::Value = res.json();
+    assert_eq!(body["details"][0]["field"], "new_password");
+    assert_eq!(body["details"][0]["code"], "too_short");
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn reset_password_rejects_weak_password_without_spending_token(pool: PgPool) {
+    let (server, mailer) = common::server_with_mailer(pool.clone());
+    common::register(&server, &pool, "alice", common::PASSWORD).await;
+
+    server
+        .post("/auth/forgot-password")
+        .json(&json!({ "email": "alice@example.com" }))
+        .await;
+    let token = common::token_from_last_email(&mailer, "alice@example.com");
+
+    let res = server
+        .post("/auth/reset-password")
+        .json(&json!({ "token": token, "new_password": "alice-password" }))
+        .await;
+    assert_eq!(res.status_code(), 422);
+    let body: serde_json::Value = res.json();
+    let codes: Vec<&str> = body["details"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| e["code"].as_str().unwrap())
+        .collect();
+    assert!(codes.contains(&"contains_username"), "{codes:?}");
+
+    server
+        .post("/auth/reset-password")
+        .json(&json!({ "token": token, "new_password": "a-brand-new-password" }))
+        .await
+        .assert_status_ok();
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn change_password_revokes_other_sessions(pool: PgPool) {
+    let server = common::server(pool.clone());
+    common::register(&server, &pool, "alice", common::PASSWORD).await;
+    let laptop = common::login(&server, "alice", common::PASSWORD).await;
+    let phone = common::login(&server, "alice", common::PASSWORD).await;
+
+    let res = server
+        .put("/user/password")
+        .add_header(
+            "Authorization",
+            format!("Bearer {}", laptop["access_token"].as_str().unwrap()),
+        )
+        .json(&json!({ "current_password": common::PASSWORD, "new_password": "a-brand-new-password" }))
+        .await;
+    assert_eq!(res.status_code(), 200);
+    let fresh: serde_json::Value = res.json();
+
+    for stale in [&laptop, &phone] {
+        let res = server
+            .post("/auth/refresh")
+            .json(&json!({ "refresh_token": stale["refresh_token"] }))
+            .await;
+        assert_eq!(res.status_code(), 401);
+    }
+
+    let res = server
+        .post("/auth/refresh")
+        .json(&json!({ "refresh_token": fresh["refresh_token"] }))
+        .await;
+    assert_eq!(
+        res.status_code(),
+        200,
+        "the session returned by the change stays valid"
+    );
+
+    common::login(&server, "alice", "a-brand-new-password").await;
+}
@@ -11,7 +11,7 @@ async fn get_posts_requires_auth(pool: PgPool) {
 }
 
 #[sqlx::test(migrations = "./migrations")]
-async fn get_posts_empty_returns_404(pool: PgPool) {
+async fn get_posts_empty_returns_empty_page(pool: PgPool) {
     let server = common::server(pool.clone());
     let user_id = common::insert_test_user(&pool).await;
 
@@ -20,7 +20,10 @@ async fn get_posts_empty_returns_404(pool: PgPool) {
         .add_header("Authorization", common::bearer(user_id))
         .await;
 
-    assert_eq!(res.status_code(), 404);
+    assert_eq!(res.status_code(), 200);
+    let body: Value = res.json();
+    assert_eq!(body["data"], json!([]));
+    assert_eq!(body["has_more"], false);
 }
 
 #[sqlx::test(migrations = "./migrations")]
@@ -164,3 +167,169 @@ async fn delete_post_returns_200(pool: PgPool) {
         .await;
     assert_eq!(fetch.status_code(), 404);
 }
+
+#[sqlx::test(migrations = "./migrations")]
+async fn get_posts_paginates_with_cursor(pool: PgPool) {
+    let server = common::server(pool.clone());
+    let user_id = common::insert_test_user(&pool).await;
+
+    for title in ["first", "second", "third"] {
+        server
+            .post("/post")
+            .add_header("Authorization", common::bearer(user_id))
+            .json(&json!({ "title": title, "body": "Body" }))
+            .await;
+    }
+
+    let page: Value = server
+        .get("/posts?limit=2")
+        .add_header("Authorization", common::bearer(user_id))
+        .await
+        .json();
+
+    assert_eq!(page["has_more"], true);
+    let titles: Vec<&str> = page["data"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|p| p["title"].as_str().unwrap())
+        .collect();
+    assert_eq!(titles, ["third", "second"]);
+
+    let cursor = page["next_cursor"].as_str().unwrap();
+    let page: Value = server
+        .get(&format!("/posts?limit=2&cursor={cursor}"))
+        .add_header("Authorization", common::bearer(user_id))
+        .await
+        .json();
+
+    assert_eq!(page["has_more"], false);
+    assert!(page["next_cursor"].is_null());
+    assert_eq!(page["data"].as_array().unwrap().len(), 1);
+    assert_eq!(page["data"][0]["title"], "first");
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn get_user_posts_rejects_limit_above_maximum(pool: PgPool) {
+    let server = common::server(pool.clone());
+    let user_id = common::insert_test_user(&pool).await;
+
+    let res = server
+        .get(&format!("/user/{user_id}/posts?limit=1000"))
+        .add_header("Authorization", common::bearer(user_id))
+        .await;
+
+    assert_eq!(res.status_code(), 422);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn get_current_user_posts_rejects_invalid_cursor(pool: PgPool) {
+    let server = common::server(pool.clone());
+    let user_id = common::insert_test_user(&pool).await;
+
+    let res = server
+        .get("/user/posts?cursor=garbage")
+        .add_header("Authorization", common::bearer(user_id))
+        .await;
+
+    assert_eq!(res.status_code(), 422);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn search_posts_requires_auth(pool: PgPool) {
+    let server = common::server(pool);
+    let res = server.get("/posts/search?q=rust").await;
+    assert_eq!(res.status_code(), 401);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn search_posts_ranks_and_highlights_matches(pool: PgPool) {
+    let server = common::server(pool.clone());
+    let user_id = common::insert_test_user(&pool).await;
+
+    for (title, body) in [
+        ("Gardening", "Tomatoes need plenty of sun."),
+        ("Crabs", "The crab is a friendly crustacean."),
+        (
+            "Rust crabs",
+            "Ferris the crab is the unofficial mascot of Rust.",
+        ),
+    ] {
+        server
+            .post("/post")
+            .add_header("Authorization", common::bearer(user_id))
+            .json(&json!({ "title": title, "body": body }))
+            .await;
+    }
+
+    let res = server
+        .get("/posts/search?q=crab")
+        .add_header("Authorization", common::bearer(user_id))
+        .await;
+
+    assert_eq!(res.status_code(), 200);
+    let body: Value = res.json();
+    let results = body.as_array().unwrap();
+    assert_eq!(results.len(), 2);
+    assert_eq!(
+        results[0]["title"], "Rust crabs",
+        "title matches rank higher"
+    );
+    assert!(
+        results[0]["snippet"]
+            .as_str()
+            .unwrap()
+            .contains("<mark>crab</mark>")
+    );
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn search_posts_empty_query_returns_422(pool: PgPool) {
+    let server = common::server(pool.clone());
+    let user_id = common::insert_test_user(&pool).await;
+
+    let res = server
+        .get("/posts/search?q=%20")
+        .add_header("Authorization", common::bearer(user_id))
+        .await;
+
+    assert_eq!(res.status_code(), 422);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn search_snippets_escape_html_in_the_body(pool: PgPool) {
+    let server = common::server(pool.clone());
+    let user_id = common::insert_test_user(&pool).await;
+    server
+        .post("/post")
+        .add_header("Authorization", common::bearer(user_id))
+        .json(&json!({
+            "title": "Payload",
+            "body": "A crab <img src=x onerror=alert(1)> & \"friends\""
+        }))
+        .await;
+
+    let res = server
+        .get("/posts/search?q=crab")
+        .add_header("Authorization", common::bearer(user_id))
+        .await;
+
+    let body: Value = res.json();
+    let snippet = body[0]["snippet"].as_str().unwrap();
+    assert!(snippet.contains("<mark>crab</mark>"));
+    assert!(snippet.contains("&lt;img src=x onerror=alert(1)&gt; &amp; &quot;friends"));
+    assert!(!snippet.contains("<img"));
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn search_posts_validates_limit(pool: PgPool) {
+    let server = common::server(pool.clone());
+    let user_id = common::insert_test_user(&pool).await;
+
+    let res = server
+        .get("/posts/search?q=crab&limit=0")
+        .add_header("Authorization", common::bearer(user_id))
+        .await;
+
+    assert_eq!(res.status_code(), 422);
+}
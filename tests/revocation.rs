@@ -0,0 +1,198 @@
+mod common;
+
+use serde_json::{Value, json};
+use sqlx::PgPool;
+
+fn bearer(session: &Value) -> String {
+    format!("Bearer {}", session["access_token"].as_str().unwrap())
+}
+
+async fn me_status(server: &axum_test::TestServer, session: &Value) -> u16 {
+    server
+        .get("/user")
+        .add_header("Authorization", bearer(session))
+        .await
+        .status_code()
+        .as_u16()
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn logout_revokes_the_access_token(pool: PgPool) {
+    let server = common::server(pool.clone());
+    common::register(&server, &pool, "alice", common::PASSWORD).await;
+    let session = common::login(&server, "alice", common::PASSWORD).await;
+    assert_eq!(me_status(&server, &session).await, 200);
+
+    server
+        .post("/auth/logout")
+        .add_header(
+            "Authorization",
+            format!("Bearer {}", session["refresh_token"].as_str().unwrap()),
+        )
+        .await
+        .assert_status_ok();
+
+    let res = server
+        .get("/user")
+        .add_header("Authorization", bearer(&session))
+        .await;
+    assert_eq!(res.status_code(), 401);
+    let body: Value = res.json();
+    assert_eq!(body["message"], "Token has been revoked");
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn logout_also_revokes_access_tokens_from_earlier_rotations(pool: PgPool) {
+    let server = common::server(pool.clone());
+    common::register(&server, &pool, "alice", common::PASSWORD).await;
+    let session = common::login(&server, "alice", common::PASSWORD).await;
+    let refreshed: Value = server
+        .post("/auth/refresh")
+        .json(&json!({ "refresh_token": session["refresh_token"] }))
+        .await
+        .json();
+
+    server
+        .post("/auth/logout")
+        .add_header(
+            "Authorization",
+            format!("Bearer {}", refreshed["refresh_token"].as_str().unwrap()),
+        )
+        .await
+        .assert_status_ok();
+
+    assert_eq!(me_status(&server, &session).await, 401);
+    assert_eq!(me_status(&server, &refreshed).await, 401);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn revoking_a_session_leaves_other_devices_signed_in(pool: PgPool) {
+    let server = common::server(pool.clone());
+    common::register(&server, &pool, "alice", common::PASSWORD).await;
+    let laptop = common::login(&server, "alice", common::PASSWORD).await;
+    let phone = common::login(&server, "alice", common::PASSWORD).await;
+    let phone_family: uuid::Uuid =
+        sqlx::query_scalar("SELECT family_id FROM refresh_tokens WHERE token_hash = $1")
+            .bind(rust_axum_rest_api::auth::jwt::hash_token(
+                phone["refresh_token"].as_str().unwrap(),
+            ))
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+
+    server
+        .delete(&format!("/auth/sessions/{phone_family}"))
+        .add_header("Authorization", bearer(&laptop))
+        .await
+        .assert_status_ok();
+
+    assert_eq!(me_status(&server, &phone).await, 401);
+    assert_eq!(me_status(&server, &laptop).await, 200);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn logout_all_revokes_every_access_token(pool: PgPool) {
+    let server = common::server(pool.clone());
+    common::register(&server, &pool, "alice", common::PASSWORD).await;
+    let laptop = common::login(&server, "alice", common::PASSWORD).await;
+    let phone = common::login(&server, "alice", common::PASSWORD).await;
+
+    server
+        .post("/auth/logout-all")
+        .add_header("Authorization", bearer(&laptop))
+        .await
+        .assert_status_ok();
+
+    assert_eq!(me_status(&server, &laptop).await, 401);
+    assert_eq!(me_status(&server, &phone).await, 401);
+    // Signing in again afterwards works as normal.
+    let fresh = common::login(&server, "alice", common::PASSWORD).await;
+    assert_eq!(me_status(&server, &fresh).await, 200);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn logout_all_revokes_sessionless_tokens_from_the_same_second(pool: PgPool) {
+    let server = common::server(pool.clone());
+    common::register(&server, &pool, "alice", common::PASSWORD).await;
+    let session = common::login(&server, "alice", common::PASSWORD).await;
+    let alice: i32 = sqlx::query_scalar("SELECT id FROM users WHERE username = 'alice'")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    // Like an impersonation or client_credentials token: no refresh token records its ID.
+    let sessionless = common::bearer(alice);
+
+    server
+        .post("/auth/logout-all")
+        .add_header("Authorization", bearer(&session))
+        .await
+        .assert_status_ok();
+
+    let res = server
+        .get("/user")
+        .add_header("Authorization", sessionless)
+        .await;
+    assert_eq!(res.status_code(), 401);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn password_change_revokes_old_tokens_but_not_the_new_pair(pool: PgPool) {
+    let server = common::server(pool.clone());
+    common::register(&server, &pool, "alice", common::PASSWORD).await;
+    let other_device = common::login(&server, "alice", common::PASSWORD).await;
+    let this_device = common::login(&server, "alice", common::PASSWORD).await;
+
+    let res = server
+        .put("/user/password")
+        .add_header("Authorization", bearer(&this_device))
+        .json(&json!({
+            "current_password": common::PASSWORD,
+            "new_password": "a-brand-new-password"
+        }))
+        .await;
+    res.assert_status_ok();
+    let new_pair: Value = res.json();
+
+    assert_eq!(me_status(&server, &other_device).await, 401);
+    assert_eq!(me_status(&server, &this_device).await, 401);
+    assert_eq!(me_status(&server, &new_pair).await, 200);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn tokens_issued_before_a_watermark_are_rejected(pool: PgPool) {
+    let server = common::server(pool.clone());
+    let user_id = common::insert_test_user(&pool).await;
+    // Issued without a session, so only the per-user cutoff can catch it.
+    let token = common::bearer(user_id);
+    sqlx::query(
+        "INSERT INTO access_token_watermarks (user_id, issued_before)
+        VALUES ($1, NOW() AT TIME ZONE 'UTC' + INTERVAL '1 minute')",
+    )
+    .bind(user_id)
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    let res = server.get("/user").add_header("Authorization", token).await;
+
+    assert_eq!(res.status_code(), 401);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn deleted_account_tokens_are_revoked(pool: PgPool) {
+    let server = common::server(pool.clone());
+    common::register(&server, &pool, "alice", common::PASSWORD).await;
+    let session = common::login(&server, "alice", common::PASSWORD).await;
+
+    server
+        .delete("/user")
+        .add_header("Authorization", bearer(&session))
+        .await
+        .assert_status_ok();
+
+    let res = server
+        .get("/user/posts")
+        .add_header("Authorization", bearer(&session))
+        .await;
+    assert_eq!(res.status_code(), 401);
+}
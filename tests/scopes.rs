@@ -0,0 +1,293 @@
+mod common;
+
+use serde_json::{Value, json};
+use sqlx::PgPool;
+
+async fn login_with_scope(
+    server: &axum_test::TestServer,
+    username: &str,
+    scope: &str,
+) -> axum_test::TestResponse {
+    server
+        .post("/auth/login")
+        .json(&json!({ "username": username, "password": common::PASSWORD, "scope": scope }))
+        .await
+}
+
+fn bearer(session: &Value) -> String {
+    format!("Bearer {}", session["access_token"].as_str().unwrap())
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn login_without_scope_grants_everything_the_role_allows(pool: PgPool) {
+    let server = common::server(pool.clone());
+    common::register(&server, &pool, "alice", common::PASSWORD).await;
+
+    let session = common::login(&server, "alice", common::PASSWORD).await;
+    assert_eq!(
+        session["scope"],
+        "posts:read posts:write users:read users:write"
+    );
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn narrowed_session_is_limited_to_its_scopes(pool: PgPool) {
+    let server = common::server(pool.clone());
+    common::register(&server, &pool, "alice", common::PASSWORD).await;
+
+    let res = login_with_scope(&server, "alice", "posts:read users:read").await;
+    assert_eq!(res.status_code(), 200);
+    let session: Value = res.json();
+    assert_eq!(session["scope"], "posts:read users:read");
+
+    let res = server
+        .get("/user")
+        .add_header("Authorization", bearer(&session))
+        .await;
+    assert_eq!(res.status_code(), 200);
+
+    let res = server
+        .post("/post")
+        .add_header("Authorization", bearer(&session))
+        .json(&json!({ "title": "t", "body": "b" }))
+        .await;
+    assert_eq!(res.status_code(), 403);
+
+    // Refreshing keeps the session narrowed.
+    let res = server
+        .post("/auth/refresh")
+        .json(&json!({ "refresh_token": session["refresh_token"] }))
+        .await;
+    assert_eq!(res.status_code(), 200);
+    let refreshed: Value = res.json();
+    assert_eq!(refreshed["scope"], "posts:read users:read");
+
+    let res = server
+        .post("/post")
+        .add_header("Authorization", bearer(&refreshed))
+        .json(&json!({ "title": "t", "body": "b" }))
+        .await;
+    assert_eq!(res.status_code(), 403);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn login_rejects_unknown_or_disallowed_scopes(pool: PgPool) {
+    let server = common::server(pool.clone());
+    common::register(&server, &pool, "alice", common::PASSWORD).await;
+
+    let res = login_with_scope(&server, "alice", "posts:delete").await;
+    assert_eq!(res.status_code(), 422);
+
+    let res = login_with_scope(&server, "alice", "users:admin").await;
+    assert_eq!(res.status_code(), 403);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn admin_endpoints_need_the_admin_scope(pool: PgPool) {
+    let server = common::server(pool.clone());
+    common::register(&server, &pool, "root", common::PASSWORD).await;
+    sqlx::query("UPDATE users SET role = 'admin' WHERE username = 'root'")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let session: Value = login_with_scope(&server, "root", "posts:read").await.json();
+    let res = server
+        .get("/admin/users")
+        .add_header("Authorization", bearer(&session))
+        .await;
+    assert_eq!(res.status_code(), 403);
+
+    let session: Value = login_with_scope(&server, "root", "users:admin")
+        .await
+        .json();
+    let res = server
+        .get("/admin/users")
+        .add_header("Authorization", bearer(&session))
+        .await;
+    assert_eq!(res.status_code(), 200);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn only_the_admin_scope_reaches_other_users_posts(pool: PgPool) {
+    let server = common::server(pool.clone());
+    let alice = common::insert_user(&pool, "alice", "user").await;
+    common::register(&server, &pool, "root", common::PASSWORD).await;
+    sqlx::query("UPDATE users SET role = 'admin' WHERE username = 'root'")
+        .execute(&pool)
+        .await
+        .unwrap();
+    let post: Value = server
+        .post("/post")
+        .add_header("Authorization", common::bearer(alice))
+        .json(&json!({ "title": "Mine", "body": "Body" }))
+        .await
+        .json();
+    let path = format!("/post/{}", post["id"]);
+
+    let narrowed: Value = login_with_scope(&server, "root", "posts:write")
+        .await
+        .json();
+    let res = server
+        .put(&path)
+        .add_header("Authorization", bearer(&narrowed))
+        .json(&json!({ "title": "Edited" }))
+        .await;
+    assert_eq!(res.status_code(), 404);
+    let res = server
+        .delete(&path)
+        .add_header("Authorization", bearer(&narrowed))
+        .await;
+    assert_eq!(res.status_code(), 404);
+
+    let full: Value = login_with_scope(&server, "root", "posts:write users:admin")
+        .await
+        .json();
+    server
+        .put(&path)
+        .add_header("Authorization", bearer(&full))
+        .json(&json!({ "title": "Edited" }))
+        .await
+        .assert_status_ok();
+    server
+        .delete(&path)
+        .add_header("Authorization", bearer(&full))
+        .await
+        .assert_status_ok();
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn narrowed_session_cannot_grant_scopes_it_lacks(pool: PgPool) {
+    let server = common::server(pool.clone());
+    common::register(&server, &pool, "alice", common::PASSWORD).await;
+    let owner_id = common::insert_user(&pool, "partner", "user").await;
+    let session: Value = login_with_scope(&server, "alice", "posts:read users:write")
+        .await
+        .json();
+
+    let res = server
+        .post("/auth/api-keys")
+        .add_header("Authorization", bearer(&session))
+        .json(&json!({ "name": "ci", "scopes": ["posts:read", "posts:write"] }))
+        .await;
+    assert_eq!(res.status_code(), 403);
+
+    let res = server
+        .post("/oauth/clients")
+        .add_header("Authorization", bearer(&session))
+        .json(&json!({
+            "name": "Mine",
+            "redirect_uris": ["https://app.example.com/callback"],
+            "scopes": ["posts:write"]
+        }))
+        .await;
+    assert_eq!(res.status_code(), 403);
+
+    let res = server
+        .post("/oauth/clients")
+        .add_header("Authorization", common::bearer(owner_id))
+        .json(&json!({
+            "name": "Partner App",
+            "redirect_uris": ["https://app.example.com/callback"],
+            "scopes": ["posts:read", "posts:write"]
+        }))
+        .await;
+    res.assert_status_ok();
+    let client_id = res.json::<Value>()["id"].as_str().unwrap().to_string();
+    let authorization = |scope: Option<&str>| {
+        let mut query = vec![
+            ("response_type", "code".to_string()),
+            ("client_id", client_id.clone()),
+            (
+                "redirect_uri",
+                "https://app.example.com/callback".to_string(),
+            ),
+            ("code_challenge", "a".repeat(43)),
+            ("code_challenge_method", "S256".to_string()),
+        ];
+        if let Some(scope) = scope {
+            query.push(("scope", scope.to_string()));
+        }
+        query
+    };
+
+    let res = server
+        .get("/oauth/authorize")
+        .add_query_params(authorization(Some("posts:write")))
+        .add_header("Authorization", bearer(&session))
+        .await;
+    assert_eq!(res.status_code(), 403);
+
+    // Without a scope, consent covers only what both the client and the session hold.
+    let res = server
+        .get("/oauth/authorize")
+        .add_query_params(authorization(None))
+        .add_header("Authorization", bearer(&session))
+        .await;
+    res.assert_status_ok();
+    assert_eq!(res.json::<Value>()["scope"], "posts:read");
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn account_management_needs_users_write(pool: PgPool) {
+    let server = common::server(pool.clone());
+    common::register(&server, &pool, "alice", common::PASSWORD).await;
+    let session: Value = login_with_scope(&server, "alice", "posts:read users:read")
+        .await
+        .json();
+
+    for path in ["/auth/api-keys", "/auth/sessions", "/oauth/clients"] {
+        let res = server
+            .get(path)
+            .add_header("Authorization", bearer(&session))
+            .await;
+        assert_eq!(res.status_code(), 403, "GET {path}");
+    }
+    let res = server
+        .post("/auth/mfa/enroll")
+        .add_header("Authorization", bearer(&session))
+        .await;
+    assert_eq!(res.status_code(), 403);
+    let res = server
+        .post("/auth/logout-all")
+        .add_header("Authorization", bearer(&session))
+        .await;
+    assert_eq!(res.status_code(), 403);
+    let res = server
+        .put("/user/password")
+        .add_header("Authorization", bearer(&session))
+        .json(&json!({
+            "current_password": common::PASSWORD,
+            "new_password": "a-brand-new-password-77"
+        }))
+        .await;
+    assert_eq!(res.status_code(), 403);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn changing_password_keeps_the_session_narrowed(pool: PgPool) {
+    let server = common::server(pool.clone());
+    common::register(&server, &pool, "alice", common::PASSWORD).await;
+    let session: Value = login_with_scope(&server, "alice", "posts:read users:write")
+        .await
+        .json();
+
+    let res = server
+        .put("/user/password")
+        .add_header("Authorization", bearer(&session))
+        .json(&json!({
+            "current_password": common::PASSWORD,
+            "new_password": "a-brand-new-password-77"
+        }))
+        .await;
+    res.assert_status_ok();
+    let changed: Value = res.json();
+    assert_eq!(changed["scope"], "posts:read users:write");
+
+    let res = server
+        .post("/auth/refresh")
+        .json(&json!({ "refresh_token": changed["refresh_token"] }))
+        .await;
+    res.assert_status_ok();
+    assert_eq!(res.json::<Value>()["scope"], "posts:read users:write");
+}
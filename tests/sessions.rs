@@ -0,0 +1,134 @@
+mod common;
+
+use serde_json::{Value, json};
+use sqlx::PgPool;
+
+async fn login_from(server: &axum_test::TestServer, user_agent: &str) -> Value {
+    let res = server
+        .post("/auth/login")
+        .add_header("User-Agent", user_agent)
+        .json(&json!({ "username": "alice", "password": common::PASSWORD }))
+        .await;
+    res.assert_status_ok();
+    res.json()
+}
+
+fn bearer(session: &Value) -> String {
+    format!("Bearer {}", session["access_token"].as_str().unwrap())
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn list_sessions_shows_each_device(pool: PgPool) {
+    let server = common::server(pool.clone());
+    common::register(&server, &pool, "alice", common::PASSWORD).await;
+    let laptop = login_from(&server, "Laptop/1.0").await;
+    login_from(&server, "Phone/2.0").await;
+
+    server
+        .post("/auth/refresh")
+        .add_header("User-Agent", "Laptop/1.0")
+        .json(&json!({ "refresh_token": laptop["refresh_token"] }))
+        .await
+        .assert_status_ok();
+
+    let res = server
+        .get("/auth/sessions")
+        .add_header("Authorization", bearer(&laptop))
+        .await;
+    assert_eq!(res.status_code(), 200);
+
+    let sessions: Vec<Value> = res.json();
+    assert_eq!(sessions.len(), 2, "rotation must not create a new session");
+    let laptop_session = sessions
+        .iter()
+        .find(|s| s["user_agent"] == "Laptop/1.0")
+        .unwrap();
+    let phone_session = sessions
+        .iter()
+        .find(|s| s["user_agent"] == "Phone/2.0")
+        .unwrap();
+    assert!(laptop_session["last_used_at"].is_string());
+    assert!(phone_session["last_used_at"].is_null());
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn revoke_session_kills_only_that_device(pool: PgPool) {
+    let server = common::server(pool.clone());
+    common::register(&server, &pool, "alice", common::PASSWORD).await;
+    let laptop = login_from(&server, "Laptop/1.0").await;
+    let phone = login_from(&server, "Phone/2.0").await;
+
+    let sessions: Vec<Value> = server
+        .get("/auth/sessions")
+        .add_header("Authorization", bearer(&laptop))
+        .await
+        .json();
+    let phone_id = sessions
+        .iter()
+        .find(|s| s["user_agent"] == "Phone/2.0")
+        .unwrap()["id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let res = server
+        .delete(&format!("/auth/sessions/{phone_id}"))
+        .add_header("Authorization", bearer(&laptop))
+        .await;
+    assert_eq!(res.status_code(), 200);
+
+    let res = server
+        .post("/auth/refresh")
+        .json(&json!({ "refresh_token": phone["refresh_token"] }))
+        .await;
+    assert_eq!(res.status_code(), 401);
+
+    let res = server
+        .post("/auth/refresh")
+        .json(&json!({ "refresh_token": laptop["refresh_token"] }))
+        .await;
+    assert_eq!(res.status_code(), 200);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn revoke_session_of_another_user_returns_404(pool: PgPool) {
+    let server = common::server(pool.clone());
+    common::register(&server, &pool, "alice", common::PASSWORD).await;
+    let alice = login_from(&server, "Laptop/1.0").await;
+    let mallory_id = common::insert_user(&pool, "mallory", "user").await;
+
+    let sessions: Vec<Value> = server
+        .get("/auth/sessions")
+        .add_header("Authorization", bearer(&alice))
+        .await
+        .json();
+    let id = sessions[0]["id"].as_str().unwrap();
+
+    let res = server
+        .delete(&format!("/auth/sessions/{id}"))
+        .add_header("Authorization", common::bearer(mallory_id))
+        .await;
+    assert_eq!(res.status_code(), 404);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn logout_all_revokes_every_session(pool: PgPool) {
+    let server = common::server(pool.clone());
+    common::register(&server, &pool, "alice", common::PASSWORD).await;
+    let laptop = login_from(&server, "Laptop/1.0").await;
+    let phone = login_from(&server, "Phone/2.0").await;
+
+    server
+        .post("/auth/logout-all")
+        .add_header("Authorization", bearer(&laptop))
+        .await
+        .assert_status_ok();
+
+    for session in [&laptop, &phone] {
+        let res = server
+            .post("/auth/refresh")
+            .json(&json!({ "refresh_token": session["refresh_token"] }))
+            .await;
+        assert_eq!(res.status_code(), 401);
+    }
+}
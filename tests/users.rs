@@ -1,7 +1,9 @@
 mod common;
 
+use rust_axum_rest_api::{Services, auth::password_policy::PasswordPolicy};
 use serde_json::{Value, json};
 use sqlx::PgPool;
+use std::sync::Arc;
 
 #[sqlx::test(migrations = "./migrations")]
 async fn create_user_returns_200(pool: PgPool) {
@@ -12,7 +14,7 @@ async fn create_user_returns_200(pool: PgPool) {
         .json(&json!({
             "username": "alice",
             "email": "alice@example.com",
-            "password": "password123"
+            "password": common::PASSWORD
         }))
         .await;
 
@@ -25,20 +27,21 @@ async fn create_user_returns_200(pool: PgPool) {
 
 #[sqlx::test(migrations = "./migrations")]
 async fn login_success_returns_tokens(pool: PgPool) {
-    let server = common::server(pool);
+    let server = common::server(pool.clone());
 
     server
         .post("/user")
         .json(&json!({
             "username": "alice",
             "email": "alice@example.com",
-            "password": "password123"
+            "password": common::PASSWORD
         }))
         .await;
+    common::mark_verified(&pool, "alice").await;
 
     let res = server
         .post("/auth/login")
-        .json(&json!({ "username": "alice", "password": "password123" }))
+        .json(&json!({ "username": "alice", "password": common::PASSWORD }))
         .await;
 
     assert_eq!(res.status_code(), 200);
@@ -57,7 +60,7 @@ async fn login_wrong_password_returns_401(pool: PgPool) {
         .json(&json!({
             "username": "alice",
             "email": "alice@example.com",
-            "password": "password123"
+            "password": common::PASSWORD
         }))
         .await;
 
@@ -105,20 +108,21 @@ async fn get_current_user_returns_200(pool: PgPool) {
 
 #[sqlx::test(migrations = "./migrations")]
 async fn refresh_token_returns_new_tokens(pool: PgPool) {
-    let server = common::server(pool);
+    let server = common::server(pool.clone());
 
     server
         .post("/user")
         .json(&json!({
             "username": "alice",
             "email": "alice@example.com",
-            "password": "password123"
+            "password": common::PASSWORD
         }))
         .await;
+    common::mark_verified(&pool, "alice").await;
 
     let login: Value = server
         .post("/auth/login")
-        .json(&json!({ "username": "alice", "password": "password123" }))
+        .json(&json!({ "username": "alice", "password": common::PASSWORD }))
         .await
         .json();
 
@@ -135,3 +139,204 @@ async fn refresh_token_returns_new_tokens(pool: PgPool) {
     assert!(body["refresh_token"].as_str().is_some());
     assert_ne!(body["refresh_token"].as_str(), Some(refresh_token), "token should be rotated");
 }
+
+#[sqlx::test(migrations = "./migrations")]
+async fn create_user_duplicate_username_returns_409(pool: PgPool) {
+    let server = common::server(pool);
+
+    let payload = json!({
+        "username": "alice",
+        "email": "alice@example.com",
+        "password": common::PASSWORD
+    });
+    server.post("/user").json(&payload).await;
+
+    let res = server
+        .post("/user")
+        .json(&json!({
+            "username": "alice",
+            "email": "other@example.com",
+            "password": common::PASSWORD
+        }))
+        .await;
+
+    assert_eq!(res.status_code(), 409);
+    let body: Value = res.json();
+    assert_eq!(body["message"], "Username is already taken");
+    assert!(
+        !body.to_string().contains("duplicate key"),
+        "raw database errors must not leak to clients"
+    );
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn create_user_rejects_weak_password_with_field_errors(pool: PgPool) {
+    let server = common::server(pool);
+
+    for (password, code) in [
+        ("", "too_short"),
+        ("password123", "too_weak"),
+        ("alice-walrus-orbit-42", "contains_username"),
+    ] {
+        let res = server
+            .post("/user")
+            .json(&json!({
+                "username": "alice",
+                "email": "alice@example.com",
+                "password": password
+            }))
+            .await;
+
+        assert_eq!(res.status_code(), 422, "{password:?} should be rejected");
+        let body: Value = res.json();
+        assert_eq!(body["message"], "One or more fields are invalid");
+        assert_eq!(body["details"][0]["field"], "password");
+        assert_eq!(body["details"][0]["code"], code);
+    }
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn create_user_rejects_breached_password(pool: PgPool) {
+    let policy = PasswordPolicy {
+        breached_dir: Some(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/breached").into()),
+        ..PasswordPolicy::default()
+    };
+    let services = Services {
+        password_policy: Arc::new(policy),
+        ..common::services()
+    };
+    let server = common::server_with_services(pool, services);
+
+    let res = server
+        .post("/user")
+        .json(&json!({
+            "username": "alice",
+            "email": "alice@example.com",
+            "password": "correct-horse-battery-staple"
+        }))
+        .await;
+
+    assert_eq!(res.status_code(), 422);
+    let body: Value = res.json();
+    assert_eq!(body["details"][0]["code"], "breached");
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn invalid_token_returns_json_401(pool: PgPool) {
+    let server = common::server(pool);
+
+    let res = server
+        .get("/user")
+        .add_header("Authorization", "Bearer not-a-jwt")
+        .await;
+
+    assert_eq!(res.status_code(), 401);
+    let body: Value = res.json();
+    assert_eq!(body["error"], "Unauthorized");
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn reused_refresh_token_revokes_family(pool: PgPool) {
+    let server = common::server(pool.clone());
+    common::register(&server, &pool, "alice", common::PASSWORD).await;
+    let login = common::login(&server, "alice", common::PASSWORD).await;
+    let original = login["refresh_token"].as_str().unwrap();
+
+    let rotated: Value = server
+        .post("/auth/refresh")
+        .json(&json!({ "refresh_token": original }))
+        .await
+        .json();
+
+    // An attacker replays the token that was already rotated.
+    let res = server
+        .post("/auth/refresh")
+        .json(&json!({ "refresh_token": original }))
+        .await;
+    assert_eq!(res.status_code(), 401);
+
+    // The legitimate client's newer token died with the family.
+    let res = server
+        .post("/auth/refresh")
+        .json(&json!({ "refresh_token": rotated["refresh_token"] }))
+        .await;
+    assert_eq!(res.status_code(), 401);
+
+    let remaining: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM refresh_tokens")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(remaining, 0);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn reuse_only_revokes_the_affected_family(pool: PgPool) {
+    let server = common::server(pool.clone());
+    common::register(&server, &pool, "alice", common::PASSWORD).await;
+    let laptop = common::login(&server, "alice", common::PASSWORD).await;
+    let phone = common::login(&server, "alice", common::PASSWORD).await;
+
+    for _ in 0..2 {
+        server
+            .post("/auth/refresh")
+            .json(&json!({ "refresh_token": laptop["refresh_token"] }))
+            .await;
+    }
+
+    let res = server
+        .post("/auth/refresh")
+        .json(&json!({ "refresh_token": phone["refresh_token"] }))
+        .await;
+    assert_eq!(res.status_code(), 200);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn logout_revokes_rotated_tokens_of_the_session(pool: PgPool) {
+    let server = common::server(pool.clone());
+    common::register(&server, &pool, "alice", common::PASSWORD).await;
+    let login = common::login(&server, "alice", common::PASSWORD).await;
+
+    let rotated: Value = server
+        .post("/auth/refresh")
+        .json(&json!({ "refresh_token": login["refresh_token"] }))
+        .await
+        .json();
+    let current = rotated["refresh_token"].as_str().unwrap();
+
+    server
+        .post("/auth/logout")
+        .add_header("Authorization", format!("Bearer {current}"))
+        .await
+        .assert_status_ok();
+
+    let remaining: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM refresh_tokens")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(remaining, 0);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn login_upgrades_legacy_bcrypt_hash(pool: PgPool) {
+    let server = common::server(pool.clone());
+    let legacy_hash = bcrypt::hash(common::PASSWORD, 4).unwrap();
+    sqlx::query(
+        "INSERT INTO users (username, email, password_hash, email_verified_at)
+        VALUES ('alice', 'alice@example.com', $1, NOW())",
+    )
+    .bind(&legacy_hash)
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    common::login(&server, "alice", common::PASSWORD).await;
+
+    let stored: String =
+        sqlx::query_scalar("SELECT password_hash FROM users WHERE username = 'alice'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert!(stored.starts_with("$argon2id$"));
+
+    common::login(&server, "alice", common::PASSWORD).await;
+}